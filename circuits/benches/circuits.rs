@@ -0,0 +1,131 @@
+//! Proving and verification times of the production circuits, run with `cargo bench`.
+//!
+//! Circuits are proven from their empty witnesses, since the witness values don't affect the
+//! proving time.
+
+use std::collections::BTreeMap;
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, VerifyingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{
+    rand::{rngs::StdRng, SeedableRng},
+    UniformRand, Zero,
+};
+use circuits::{
+    merkle_tree::SparseMerkleTree, poseidon::PoseidonHash, utils::poseidon_bn254, MainCircuitBn254,
+    MigrationCircuitBn254, SplittedSettleCircuitBn254, SplittedSpendCircuitBn254, N_ASSETS,
+    TREE_DEPTH,
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+type Main = MainCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type Migration = MigrationCircuitBn254<{ N_ASSETS }, { N_ASSETS + 1 }, { TREE_DEPTH }>;
+type Spend = SplittedSpendCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type Settle = SplittedSettleCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type Tree = SparseMerkleTree<Fr, PoseidonHash<Fr>, { TREE_DEPTH }>;
+
+fn bench_prove<C: ConstraintSynthesizer<Fr>>(c: &mut Criterion, name: &str, empty: impl Fn() -> C) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (pk, _) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(empty(), &mut rng)
+        .expect("setup should not fail");
+
+    c.bench_function(&format!("prove {name}"), |b| {
+        b.iter_batched(
+            &empty,
+            |circuit| Groth16::<Bn254, LibsnarkReduction>::prove(&pk, circuit, &mut rng),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn prove(c: &mut Criterion) {
+    let hasher = poseidon_bn254();
+
+    bench_prove(c, "main", || Main::empty_without_tree(&hasher));
+    bench_prove(c, "migration", || Migration::empty_without_tree(&hasher));
+    bench_prove(c, "spend", || Spend::empty_without_tree(&hasher));
+    bench_prove(c, "settle", || Settle::empty_without_tree(&hasher));
+}
+
+fn verify(c: &mut Criterion) {
+    let hasher = poseidon_bn254();
+    let mut rng = StdRng::seed_from_u64(0);
+    let (pk, vk) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(
+        Main::empty_without_tree(&hasher),
+        &mut rng,
+    )
+    .expect("setup should not fail");
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+        &pk,
+        Main::empty_without_tree(&hasher),
+        &mut rng,
+    )
+    .expect("proving should not fail");
+    let public_inputs = [Fr::zero(); 6];
+
+    // Same unprepared verification as the contract
+    c.bench_function("verify main", |b| {
+        b.iter(|| Groth16::<Bn254, LibsnarkReduction>::verify(&vk, &public_inputs, &proof))
+    });
+}
+
+/// Loading the main circuit VK as the contract does before each verification, compressed VKs
+/// taking about half the storage but requiring each point to be decompressed.
+fn deserialize_vk(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (_, vk) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(
+        Main::empty_without_tree(&poseidon_bn254()),
+        &mut rng,
+    )
+    .expect("setup should not fail");
+
+    let mut uncompressed = vec![];
+    vk.serialize_uncompressed(&mut uncompressed)
+        .expect("serialization should not fail");
+    let mut compressed = vec![];
+    vk.serialize_compressed(&mut compressed)
+        .expect("serialization should not fail");
+
+    let mut group = c.benchmark_group("deserialize_vk");
+    group.bench_function(BenchmarkId::new("uncompressed", uncompressed.len()), |b| {
+        b.iter(|| {
+            VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(black_box(&uncompressed[..]))
+        })
+    });
+    group.bench_function(BenchmarkId::new("compressed", compressed.len()), |b| {
+        b.iter(|| {
+            VerifyingKey::<Bn254>::deserialize_compressed_unchecked(black_box(&compressed[..]))
+        })
+    });
+    group.finish();
+}
+
+fn insert_batch(c: &mut Criterion) {
+    let hasher = poseidon_bn254();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let mut group = c.benchmark_group("insert_batch");
+    for size in [1u32, 16, 256] {
+        let leaves = (0..size)
+            .map(|i| (i, Fr::rand(&mut rng)))
+            .collect::<BTreeMap<_, _>>();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &leaves, |b, leaves| {
+            b.iter_batched(
+                || Tree::new(&BTreeMap::new(), &hasher, &Fr::zero()).expect("empty tree"),
+                |mut tree| tree.insert_batch(black_box(leaves), &hasher),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = prove, verify, deserialize_vk, insert_batch
+}
+criterion_main!(benches);
@@ -0,0 +1,136 @@
+use ark_crypto_primitives::crh::{
+    CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget,
+};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{
+    fields::fp::FpVar,
+    prelude::{AllocVar, Boolean, EqGadget, FieldVar},
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use super::gadgets::calculate_balance_root;
+
+/// Asset Bitmap Circuit
+///
+/// Proves that bit `i` of the public `bitmap` is set if and only if the balance of the asset at
+/// index `i` of `note` is nonzero, revealing which assets the note holds but not their amounts.
+///
+/// Public inputs are `[note, bitmap]`.
+pub struct AssetBitmapCircuit<
+    const N_ASSETS: usize,
+    F: PrimeField,
+    HP: Clone,
+    HPV: AllocVar<HP, F>,
+    H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+        + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+    HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+        + TwoToOneCRHSchemeGadget<
+            H,
+            F,
+            InputVar = FpVar<F>,
+            OutputVar = FpVar<F>,
+            ParametersVar = HPV,
+        >,
+> {
+    pub note: F,   // Public
+    pub bitmap: F, // Public
+
+    pub address: F,
+    pub nullifier: F,
+    pub note_blinding: F,
+    pub note_balances: [F; N_ASSETS],
+
+    pub parameters: HP, // Constant
+    pub _hg: std::marker::PhantomData<(H, HG)>,
+}
+
+impl<
+        const N_ASSETS: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > AssetBitmapCircuit<N_ASSETS, F, HP, HPV, H, HG>
+{
+    pub fn empty(hasher: &HP) -> Self {
+        Self {
+            note: F::zero(),
+            bitmap: F::zero(),
+            address: F::zero(),
+            nullifier: F::zero(),
+            note_blinding: F::zero(),
+            note_balances: [F::zero(); N_ASSETS],
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        const N_ASSETS: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > ConstraintSynthesizer<F> for AssetBitmapCircuit<N_ASSETS, F, HP, HPV, H, HG>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let parameters = HPV::new_constant(ns!(cs, "parameters"), &self.parameters)?;
+
+        let note = FpVar::new_input(ns!(cs, "note"), || Ok(self.note))?;
+        let bitmap = FpVar::new_input(ns!(cs, "bitmap"), || Ok(self.bitmap))?;
+
+        let address = FpVar::new_witness(ns!(cs, "address"), || Ok(self.address))?;
+        let nullifier = FpVar::new_witness(ns!(cs, "nullifier"), || Ok(self.nullifier))?;
+        let note_blinding =
+            FpVar::new_witness(ns!(cs, "note_blinding"), || Ok(self.note_blinding))?;
+        let note_balances = Vec::<FpVar<F>>::new_witness(ns!(cs, "note_balances"), || {
+            Ok(self.note_balances.to_vec())
+        })?;
+
+        // Assert that the balances are the ones of the note
+        let calculated_note = <HG as CRHSchemeGadget<H, F>>::evaluate(
+            &parameters,
+            &[
+                calculate_balance_root::<F, H, HG>(&parameters, &note_balances)?,
+                <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(
+                    &parameters,
+                    &address,
+                    &note_blinding,
+                )?,
+                nullifier,
+            ],
+        )?;
+        calculated_note.enforce_equal(&note)?;
+
+        // Assert that bit `i` is set if and only if the balance at `i` is nonzero
+        let bits = note_balances
+            .iter()
+            .map(|balance| balance.is_neq(&FpVar::zero()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Boolean::le_bits_to_fp_var(&bits)?.enforce_equal(&bitmap)?;
+
+        Ok(())
+    }
+}
@@ -14,7 +14,7 @@ pub fn calculate_balance_root<
     hasher: &HG::ParametersVar,
     balances: &[FpVar<F>],
 ) -> Result<FpVar<F>, SynthesisError> {
-    <HG as CRHSchemeGadget<H, F>>::evaluate(hasher, &balances)
+    <HG as CRHSchemeGadget<H, F>>::evaluate(hasher, balances)
 }
 
 pub fn check_valid_balance_root<
@@ -135,6 +135,30 @@ impl<
             _hg: std::marker::PhantomData,
         }
     }
+
+    /// First time deposit of zero of every asset by `address`, whose public inputs are zero but
+    /// for the diff balance root `H_crh([0; N_ASSETS])` and the new note. Its proof checks that a
+    /// key was built for `N_ASSETS` assets, which the public input count alone can't tell.
+    pub fn vk_check(
+        address: F,
+        nullifier: F,
+        blinding: F,
+        hasher: &HP,
+    ) -> Result<Self, ark_crypto_primitives::Error> {
+        let diff_balance_root = <H as CRHScheme>::evaluate(hasher, [F::zero(); N_ASSETS])?;
+        let identifier = <H as TwoToOneCRHScheme>::evaluate(hasher, address, blinding)?;
+        let new_note =
+            <H as CRHScheme>::evaluate(hasher, [diff_balance_root, identifier, nullifier])?;
+
+        Ok(Self {
+            address,
+            nullifier,
+            diff_balance_root,
+            new_note,
+            new_note_blinding: blinding,
+            ..Self::empty_without_tree(hasher)
+        })
+    }
 }
 
 impl<
@@ -248,6 +272,12 @@ impl<
         let new_note_balance_root =
             calculate_balance_root::<F, H, HG>(&parameters, &new_note_balances)?;
 
+        // Assert that new note can't be mistaken for an empty leaf or balance root
+        new_note
+            .is_neq(&FpVar::zero())?
+            .and(&new_note.is_neq(&zero_balance_root)?)?
+            .enforce_equal(&Boolean::TRUE)?;
+
         // Assert validity of new note
         new_note.enforce_equal(&<HG as CRHSchemeGadget<H, F>>::evaluate(
             &parameters,
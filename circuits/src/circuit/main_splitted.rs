@@ -374,6 +374,12 @@ impl<
         let new_note_balance_root =
             calculate_balance_root::<F, H, HG>(&parameters, &new_note_balances)?;
 
+        // Assert that new note can't be mistaken for an empty leaf or balance root
+        new_note
+            .is_neq(&FpVar::zero())?
+            .and(&new_note.is_neq(&zero_balance_root)?)?
+            .enforce_equal(&Boolean::TRUE)?;
+
         // Assert validity of new note
         new_note.enforce_equal(&<HG as CRHSchemeGadget<H, F>>::evaluate(
             &parameters,
@@ -122,10 +122,10 @@ impl<
     for MigrationCircuit<N_ASSETS, M_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
 {
     fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
-        assert!(
-            N_ASSETS < M_ASSETS,
-            "Migration is only supported for N_ASSETS < M_ASSETS"
-        );
+        // Migration is only supported for N_ASSETS < M_ASSETS
+        if N_ASSETS >= M_ASSETS {
+            return Err(SynthesisError::Unsatisfiable);
+        }
 
         let parameters = HPV::new_constant(ns!(cs, "parameters"), &self.parameters)?;
         let zero = FpVar::zero();
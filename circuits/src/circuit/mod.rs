@@ -6,6 +6,23 @@ pub mod main;
 /// The main circuit, but splitted into two parts.
 pub mod main_splitted;
 
+/// The split circuit, spending a note into multiple new notes of the same owner.
+pub mod split;
+
+/// The balance threshold circuit, proving a note holds at least some amount of an asset without
+/// revealing it.
+pub mod threshold;
+
+/// The same owner circuit, proving two notes are owned by the same address without revealing it.
+pub mod same_owner;
+
+/// The ownership proof circuit, proving a note of an address is in the tree for a challenge
+/// without revealing the note or its nullifier hash.
+pub mod ownership;
+
+/// The asset bitmap circuit, proving which assets a note holds without revealing their amounts.
+pub mod bitmap;
+
 /// The migration circuit for the protocol, used to handle migration between the main circuit of
 /// set of fixed asset to another set of fixed asset.
 pub mod migration;
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use ark_crypto_primitives::crh::{
+    CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget,
+};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{
+    fields::fp::FpVar,
+    prelude::{AllocVar, Boolean, EqGadget},
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use crate::merkle_tree::{Path, PathVar, SparseMerkleTree};
+
+use super::gadgets::calculate_balance_root;
+
+/// Ownership Proof Circuit
+///
+/// Proves that a note of `address` is in the tree, without revealing the note, for a verifier
+/// issued `challenge`.
+///
+/// The challenge is bound by `challenge_tag = H_tto_crh(nullifier, challenge)`, which differs
+/// from the nullifier hash of the note, so the proof cannot be replayed for another challenge nor
+/// be used to spend the note. It does not prove that the note is unspent.
+///
+/// Public inputs are `[utxo_root, address, challenge, challenge_tag]`.
+pub struct OwnershipProofCircuit<
+    const N_ASSETS: usize,
+    const TREE_DEPTH: usize,
+    F: PrimeField,
+    HP: Clone,
+    HPV: AllocVar<HP, F>,
+    H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+        + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+    HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+        + TwoToOneCRHSchemeGadget<
+            H,
+            F,
+            InputVar = FpVar<F>,
+            OutputVar = FpVar<F>,
+            ParametersVar = HPV,
+        >,
+> {
+    pub utxo_root: F,     // Public
+    pub address: F,       // Public
+    pub challenge: F,     // Public
+    pub challenge_tag: F, // Public
+
+    pub nullifier: F,
+    pub note_blinding: F,
+    pub note_path: Path<F, H, TREE_DEPTH>,
+    pub note_balances: [F; N_ASSETS],
+
+    pub parameters: HP, // Constant
+    pub _hg: std::marker::PhantomData<HG>,
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > OwnershipProofCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    pub fn empty(hasher: &HP) -> (Self, SparseMerkleTree<F, H, TREE_DEPTH>) {
+        let empty_tree = SparseMerkleTree::new(&BTreeMap::new(), hasher, &F::zero())
+            .expect("should create empty tree");
+        (
+            Self {
+                note_path: empty_tree.generate_membership_proof(0),
+                ..Self::empty_without_tree(hasher)
+            },
+            empty_tree,
+        )
+    }
+
+    pub fn empty_without_tree(hasher: &HP) -> Self {
+        Self {
+            utxo_root: F::zero(),
+            address: F::zero(),
+            challenge: F::zero(),
+            challenge_tag: F::zero(),
+            nullifier: F::zero(),
+            note_blinding: F::zero(),
+            note_path: Path {
+                path: [(F::zero(), F::zero()); TREE_DEPTH],
+                marker: std::marker::PhantomData,
+            },
+            note_balances: [F::zero(); N_ASSETS],
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > ConstraintSynthesizer<F> for OwnershipProofCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let parameters = HPV::new_constant(ns!(cs, "parameters"), &self.parameters)?;
+
+        let utxo_root = FpVar::new_input(ns!(cs, "utxo_root"), || Ok(self.utxo_root))?;
+        let address = FpVar::new_input(ns!(cs, "address"), || Ok(self.address))?;
+        let challenge = FpVar::new_input(ns!(cs, "challenge"), || Ok(self.challenge))?;
+        let challenge_tag = FpVar::new_input(ns!(cs, "challenge_tag"), || Ok(self.challenge_tag))?;
+
+        let nullifier = FpVar::new_witness(ns!(cs, "nullifier"), || Ok(self.nullifier))?;
+        let note_blinding =
+            FpVar::new_witness(ns!(cs, "note_blinding"), || Ok(self.note_blinding))?;
+        let note_path = PathVar::<F, H, HG, TREE_DEPTH>::new_witness(ns!(cs, "note_path"), || {
+            Ok(self.note_path)
+        })?;
+        let note_balances = Vec::<FpVar<F>>::new_witness(ns!(cs, "note_balances"), || {
+            Ok(self.note_balances.to_vec())
+        })?;
+
+        // Calculate note
+        let note = <HG as CRHSchemeGadget<H, F>>::evaluate(
+            &parameters,
+            &[
+                calculate_balance_root::<F, H, HG>(&parameters, &note_balances)?,
+                <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(
+                    &parameters,
+                    &address,
+                    &note_blinding,
+                )?,
+                nullifier.clone(),
+            ],
+        )?;
+
+        // Assert validity of note path
+        note_path
+            .check_membership(&utxo_root, &note, &parameters)?
+            .enforce_equal(&Boolean::TRUE)?;
+
+        // Assert validity of challenge tag
+        challenge_tag.enforce_equal(&<HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(
+            &parameters,
+            &nullifier,
+            &challenge,
+        )?)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use ark_crypto_primitives::crh::{
+    CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget,
+};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{
+    fields::fp::FpVar,
+    prelude::{AllocVar, Boolean, EqGadget},
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use crate::merkle_tree::{Path, PathVar, SparseMerkleTree};
+
+use super::gadgets::calculate_balance_root;
+
+/// Witness of a note in the tree, of the owner proven by [`SameOwnerCircuit`].
+#[derive(Clone)]
+pub struct OwnedNote<const N_ASSETS: usize, const TREE_DEPTH: usize, F: PrimeField, H>
+where
+    H: CRHScheme<Input = [F], Output = F> + TwoToOneCRHScheme<Input = F, Output = F>,
+{
+    pub identifier: F, // Public
+    pub nullifier: F,
+    pub blinding: F,
+    pub path: Path<F, H, TREE_DEPTH>,
+    pub balances: [F; N_ASSETS],
+}
+
+impl<const N_ASSETS: usize, const TREE_DEPTH: usize, F: PrimeField, H>
+    OwnedNote<N_ASSETS, TREE_DEPTH, F, H>
+where
+    H: CRHScheme<Input = [F], Output = F> + TwoToOneCRHScheme<Input = F, Output = F>,
+{
+    pub fn empty() -> Self {
+        Self {
+            identifier: F::zero(),
+            nullifier: F::zero(),
+            blinding: F::zero(),
+            path: Path {
+                path: [(F::zero(), F::zero()); TREE_DEPTH],
+                marker: std::marker::PhantomData,
+            },
+            balances: [F::zero(); N_ASSETS],
+        }
+    }
+}
+
+/// Same Owner Circuit
+///
+/// Proves that the notes of `first_identifier` and `second_identifier` are both in the tree and
+/// owned by the same address, without revealing it. Each identifier may use its own blinding.
+///
+/// Public inputs are `[utxo_root, first_identifier, second_identifier]`.
+pub struct SameOwnerCircuit<
+    const N_ASSETS: usize,
+    const TREE_DEPTH: usize,
+    F: PrimeField,
+    HP: Clone,
+    HPV: AllocVar<HP, F>,
+    H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+        + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+    HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+        + TwoToOneCRHSchemeGadget<
+            H,
+            F,
+            InputVar = FpVar<F>,
+            OutputVar = FpVar<F>,
+            ParametersVar = HPV,
+        >,
+> {
+    pub utxo_root: F, // Public
+    pub address: F,
+
+    pub first_note: OwnedNote<N_ASSETS, TREE_DEPTH, F, H>,
+    pub second_note: OwnedNote<N_ASSETS, TREE_DEPTH, F, H>,
+
+    pub parameters: HP, // Constant
+    pub _hg: std::marker::PhantomData<HG>,
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > SameOwnerCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    pub fn empty(hasher: &HP) -> (Self, SparseMerkleTree<F, H, TREE_DEPTH>) {
+        let empty_tree = SparseMerkleTree::new(&BTreeMap::new(), hasher, &F::zero())
+            .expect("should create empty tree");
+        (
+            Self {
+                first_note: OwnedNote {
+                    path: empty_tree.generate_membership_proof(0),
+                    ..OwnedNote::empty()
+                },
+                second_note: OwnedNote {
+                    path: empty_tree.generate_membership_proof(0),
+                    ..OwnedNote::empty()
+                },
+                ..Self::empty_without_tree(hasher)
+            },
+            empty_tree,
+        )
+    }
+
+    pub fn empty_without_tree(hasher: &HP) -> Self {
+        Self {
+            utxo_root: F::zero(),
+            address: F::zero(),
+            first_note: OwnedNote::empty(),
+            second_note: OwnedNote::empty(),
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > ConstraintSynthesizer<F> for SameOwnerCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let parameters = HPV::new_constant(ns!(cs, "parameters"), &self.parameters)?;
+
+        let utxo_root = FpVar::new_input(ns!(cs, "utxo_root"), || Ok(self.utxo_root))?;
+        let first_identifier = FpVar::new_input(ns!(cs, "first_identifier"), || {
+            Ok(self.first_note.identifier)
+        })?;
+        let second_identifier = FpVar::new_input(ns!(cs, "second_identifier"), || {
+            Ok(self.second_note.identifier)
+        })?;
+
+        let address = FpVar::new_witness(ns!(cs, "address"), || Ok(self.address))?;
+
+        for (identifier, note) in [
+            (first_identifier, self.first_note),
+            (second_identifier, self.second_note),
+        ] {
+            let nullifier = FpVar::new_witness(ns!(cs, "nullifier"), || Ok(note.nullifier))?;
+            let blinding = FpVar::new_witness(ns!(cs, "blinding"), || Ok(note.blinding))?;
+            let path =
+                PathVar::<F, H, HG, TREE_DEPTH>::new_witness(ns!(cs, "path"), || Ok(note.path))?;
+            let balances =
+                Vec::<FpVar<F>>::new_witness(ns!(cs, "balances"), || Ok(note.balances.to_vec()))?;
+
+            // Assert that the identifier derives from the shared address
+            identifier.enforce_equal(&<HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(
+                &parameters,
+                &address,
+                &blinding,
+            )?)?;
+
+            // Calculate note
+            let note = <HG as CRHSchemeGadget<H, F>>::evaluate(
+                &parameters,
+                &[
+                    calculate_balance_root::<F, H, HG>(&parameters, &balances)?,
+                    identifier,
+                    nullifier,
+                ],
+            )?;
+
+            // Assert validity of note path
+            path.check_membership(&utxo_root, &note, &parameters)?
+                .enforce_equal(&Boolean::TRUE)?;
+        }
+
+        Ok(())
+    }
+}
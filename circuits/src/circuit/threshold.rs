@@ -0,0 +1,189 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use ark_crypto_primitives::crh::{
+    CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget,
+};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{
+    fields::fp::FpVar,
+    prelude::{AllocVar, Boolean, EqGadget, FieldVar},
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use crate::merkle_tree::{Path, PathVar, SparseMerkleTree};
+
+use super::gadgets::calculate_balance_root;
+
+/// Balance Threshold Circuit
+///
+/// Proves that a note of `address` in the tree has a balance of at least `threshold` of the
+/// asset at `asset_index`, without revealing the note, its exact balance or its other balances.
+///
+/// Spent notes stay in the tree, so the note's `nullifier_hash`, `H_tto_crh(note, nullifier)`,
+/// is exposed for the verifier to check that it is not spent, which also links proofs made with
+/// the same note.
+///
+/// Public inputs are `[utxo_root, address, asset_index, threshold, nullifier_hash]`.
+pub struct BalanceThresholdCircuit<
+    const N_ASSETS: usize,
+    const TREE_DEPTH: usize,
+    F: PrimeField,
+    HP: Clone,
+    HPV: AllocVar<HP, F>,
+    H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+        + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+    HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+        + TwoToOneCRHSchemeGadget<
+            H,
+            F,
+            InputVar = FpVar<F>,
+            OutputVar = FpVar<F>,
+            ParametersVar = HPV,
+        >,
+> {
+    pub utxo_root: F,      // Public
+    pub address: F,        // Public
+    pub asset_index: F,    // Public
+    pub threshold: F,      // Public
+    pub nullifier_hash: F, // Public
+
+    pub nullifier: F,
+    pub note_blinding: F,
+    pub note_path: Path<F, H, TREE_DEPTH>,
+    pub note_balances: [F; N_ASSETS],
+
+    pub parameters: HP, // Constant
+    pub _hg: std::marker::PhantomData<HG>,
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > BalanceThresholdCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    pub fn empty(hasher: &HP) -> (Self, SparseMerkleTree<F, H, TREE_DEPTH>) {
+        let empty_tree = SparseMerkleTree::new(&BTreeMap::new(), hasher, &F::zero())
+            .expect("should create empty tree");
+        (
+            Self {
+                note_path: empty_tree.generate_membership_proof(0),
+                ..Self::empty_without_tree(hasher)
+            },
+            empty_tree,
+        )
+    }
+
+    pub fn empty_without_tree(hasher: &HP) -> Self {
+        Self {
+            utxo_root: F::zero(),
+            address: F::zero(),
+            asset_index: F::zero(),
+            threshold: F::zero(),
+            nullifier_hash: F::zero(),
+            nullifier: F::zero(),
+            note_blinding: F::zero(),
+            note_path: Path {
+                path: [(F::zero(), F::zero()); TREE_DEPTH],
+                marker: std::marker::PhantomData,
+            },
+            note_balances: [F::zero(); N_ASSETS],
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        F: PrimeField,
+        HP: Clone,
+        HPV: AllocVar<HP, F>,
+        H: CRHScheme<Input = [F], Output = F, Parameters = HP>
+            + TwoToOneCRHScheme<Input = F, Output = F, Parameters = HP>,
+        HG: CRHSchemeGadget<H, F, InputVar = [FpVar<F>], OutputVar = FpVar<F>, ParametersVar = HPV>
+            + TwoToOneCRHSchemeGadget<
+                H,
+                F,
+                InputVar = FpVar<F>,
+                OutputVar = FpVar<F>,
+                ParametersVar = HPV,
+            >,
+    > ConstraintSynthesizer<F>
+    for BalanceThresholdCircuit<N_ASSETS, TREE_DEPTH, F, HP, HPV, H, HG>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let parameters = HPV::new_constant(ns!(cs, "parameters"), &self.parameters)?;
+
+        let utxo_root = FpVar::new_input(ns!(cs, "utxo_root"), || Ok(self.utxo_root))?;
+        let address = FpVar::new_input(ns!(cs, "address"), || Ok(self.address))?;
+        let asset_index = FpVar::new_input(ns!(cs, "asset_index"), || Ok(self.asset_index))?;
+        let threshold = FpVar::new_input(ns!(cs, "threshold"), || Ok(self.threshold))?;
+        let nullifier_hash =
+            FpVar::new_input(ns!(cs, "nullifier_hash"), || Ok(self.nullifier_hash))?;
+
+        let nullifier = FpVar::new_witness(ns!(cs, "nullifier"), || Ok(self.nullifier))?;
+        let note_blinding =
+            FpVar::new_witness(ns!(cs, "note_blinding"), || Ok(self.note_blinding))?;
+        let note_path = PathVar::<F, H, HG, TREE_DEPTH>::new_witness(ns!(cs, "note_path"), || {
+            Ok(self.note_path)
+        })?;
+        let note_balances = Vec::<FpVar<F>>::new_witness(ns!(cs, "note_balances"), || {
+            Ok(self.note_balances.to_vec())
+        })?;
+
+        // Calculate note
+        let note = <HG as CRHSchemeGadget<H, F>>::evaluate(
+            &parameters,
+            &[
+                calculate_balance_root::<F, H, HG>(&parameters, &note_balances)?,
+                <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(
+                    &parameters,
+                    &address,
+                    &note_blinding,
+                )?,
+                nullifier.clone(),
+            ],
+        )?;
+
+        // Assert validity of nullifier hash
+        <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(&parameters, &note, &nullifier)?
+            .enforce_equal(&nullifier_hash)?;
+
+        // Assert validity of note path
+        note_path
+            .check_membership(&utxo_root, &note, &parameters)?
+            .enforce_equal(&Boolean::TRUE)?;
+
+        // Select the balance of the asset at `asset_index`, which must be in range
+        let mut is_index_valid = Boolean::FALSE;
+        let mut balance = FpVar::zero();
+        for (i, note_balance) in note_balances.iter().enumerate() {
+            let is_selected = asset_index.is_eq(&FpVar::constant(F::from(i as u64)))?;
+            is_index_valid = is_index_valid.or(&is_selected)?;
+            balance += FpVar::from(is_selected) * note_balance;
+        }
+        is_index_valid.enforce_equal(&Boolean::TRUE)?;
+
+        // Assert that balance >= threshold, both being smaller than mod_minus_one_div_two
+        balance.enforce_cmp(&threshold, Ordering::Greater, true)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+//! Canonical byte encodings of public data bound into proofs.
+//!
+//! Both the contract and the clients must derive the exact same field elements from the same data,
+//! so every encoding here uses fixed width little endian integers and length prefixed strings
+//! instead of relying on a serialization format.
+
+use ark_crypto_primitives::sponge::{poseidon::PoseidonConfig, Absorb};
+use ark_ff::{BigInteger, PrimeField, ToConstraintField};
+use thiserror::Error;
+
+use crate::poseidon::PoseidonHash;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum EventError {
+    #[error("Missing attribute {0}")]
+    MissingAttribute(&'static str),
+
+    #[error("Invalid attribute {0}")]
+    InvalidAttribute(&'static str),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FieldError {
+    #[error("Decode Base64: {0}")]
+    Decode(#[from] base64::DecodeError),
+
+    #[error("Field element encoding is not canonical")]
+    NonCanonical,
+}
+
+/// Note insertion of a deposit, decoded from the contract's `index`, `new_root` and `leaf`
+/// attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepositEvent<F: PrimeField> {
+    pub index: u64,
+    pub new_root: F,
+    pub leaf: F,
+}
+
+/// A single swap hop, `(pool_id, token_out_denom)`.
+pub type SwapRoute<'a> = (u64, &'a str);
+
+/// Maximum number of hops of a swap, bounding the cost of hashing its encoding into `aux`.
+pub const MAX_ROUTES: usize = 8;
+
+fn encode_str(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend((value.len() as u64).to_le_bytes());
+    bytes.extend(value.as_bytes());
+}
+
+/// Derives the address field element bound into note identifiers from the bech32 `address`
+/// string, exactly as the contract does from the message sender.
+pub fn address_to_field<F: PrimeField>(address: &str) -> F {
+    F::from_le_bytes_mod_order(address.as_bytes())
+}
+
+/// Public bitmap of the assets held by a note of `balances`, bit `i` being set if and only if the
+/// balance at index `i` is nonzero. Notes of more than 64 assets can't be represented.
+pub fn asset_bitmap<F: PrimeField>(balances: &[F]) -> u64 {
+    balances
+        .iter()
+        .enumerate()
+        .filter(|(_, balance)| !balance.is_zero())
+        .fold(0, |bitmap, (i, _)| bitmap | 1 << i)
+}
+
+/// Decodes a base64 little endian field element as the contract encodes it, rejecting encodings
+/// that don't fit a field element or aren't below the modulus instead of silently reducing them.
+pub fn decode_field<F: PrimeField>(value: &str) -> Result<F, FieldError> {
+    let bytes = base64::decode(value)?;
+    let field = F::from_le_bytes_mod_order(&bytes);
+    let canonical = field.into_bigint().to_bytes_le();
+    (bytes.len() <= canonical.len() && canonical.starts_with(&bytes))
+        .then_some(field)
+        .ok_or(FieldError::NonCanonical)
+}
+
+/// Decodes the note insertion of a deposit from the `(key, value)` attributes of the contract's
+/// `wasm` event. Messages inserting multiple notes repeat the attributes, only the first ones are
+/// decoded.
+pub fn parse_deposit_event<F: PrimeField>(
+    attrs: &[(String, String)],
+) -> Result<DepositEvent<F>, EventError> {
+    let attribute = |key: &'static str| {
+        attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .ok_or(EventError::MissingAttribute(key))
+    };
+    let field = |key: &'static str| {
+        decode_field(attribute(key)?).map_err(|_| EventError::InvalidAttribute(key))
+    };
+
+    Ok(DepositEvent {
+        index: attribute("index")?
+            .parse()
+            .map_err(|_| EventError::InvalidAttribute("index"))?,
+        new_root: field("new_root")?,
+        leaf: field("leaf")?,
+    })
+}
+
+/// Encodes a swap into the bytes bound by the `aux` public input.
+///
+/// Routes are kept in the given order since the hop order is part of the swap.
+pub fn encode_swap(
+    routes: &[SwapRoute],
+    token_in_denom: &str,
+    token_in_amount: u128,
+    token_out_min_amount: u128,
+    timeout: Option<u64>,
+) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    bytes.extend((routes.len() as u64).to_le_bytes());
+    for (pool_id, token_out_denom) in routes {
+        bytes.extend(pool_id.to_le_bytes());
+        encode_str(&mut bytes, token_out_denom);
+    }
+
+    encode_str(&mut bytes, token_in_denom);
+    bytes.extend(token_in_amount.to_le_bytes());
+    bytes.extend(token_out_min_amount.to_le_bytes());
+
+    bytes.push(timeout.is_some() as u8);
+    bytes.extend(timeout.unwrap_or_default().to_le_bytes());
+
+    bytes
+}
+
+/// Packs `bytes` into field elements, then hashes them into a single field element.
+pub fn hash_bytes<F: PrimeField + Absorb>(
+    parameters: &PoseidonConfig<F>,
+    bytes: &[u8],
+) -> Result<F, ark_crypto_primitives::Error> {
+    let elements = bytes.to_field_elements().ok_or(ark_std::io::Error::new(
+        ark_std::io::ErrorKind::InvalidData,
+        "failed to pack bytes into field elements",
+    ))?;
+    PoseidonHash::crh(parameters, &elements)
+}
+
+/// Calculates the `aux` public input of a swap, `H_crh(encode_swap(..))`.
+pub fn swap_aux<F: PrimeField + Absorb>(
+    parameters: &PoseidonConfig<F>,
+    routes: &[SwapRoute],
+    token_in_denom: &str,
+    token_in_amount: u128,
+    token_out_min_amount: u128,
+    timeout: Option<u64>,
+) -> Result<F, ark_crypto_primitives::Error> {
+    hash_bytes(
+        parameters,
+        &encode_swap(
+            routes,
+            token_in_denom,
+            token_in_amount,
+            token_out_min_amount,
+            timeout,
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{encode_swap, parse_deposit_event, EventError};
+
+    #[test]
+    fn fixed_width_encoding() {
+        let one_hop = encode_swap(&[(1, "uatom")], "uosmo", 100, 50, None);
+        assert_eq!(one_hop.len(), 8 + (8 + 8 + 5) + (8 + 5) + 16 + 16 + 1 + 8);
+
+        // Same digits, different integer boundaries must not collide
+        assert_ne!(
+            encode_swap(&[(12, "uatom")], "uosmo", 3, 50, None),
+            encode_swap(&[(1, "uatom")], "uosmo", 23, 50, None),
+        );
+        assert_ne!(
+            encode_swap(&[(1, "uatom")], "uosmo", 100, 50, None),
+            encode_swap(&[(1, "uatom")], "uosmo", 100, 50, Some(0)),
+        );
+    }
+
+    #[test]
+    fn invalid_deposit_event() {
+        let attrs = |index: &str, new_root: &str| {
+            [("index", index), ("new_root", new_root), ("leaf", "AQ==")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        };
+
+        assert_eq!(
+            parse_deposit_event::<Fr>(&attrs("3", "AQ==")[1..]),
+            Err(EventError::MissingAttribute("index"))
+        );
+        assert_eq!(
+            parse_deposit_event::<Fr>(&attrs("-1", "AQ==")),
+            Err(EventError::InvalidAttribute("index"))
+        );
+        assert_eq!(
+            parse_deposit_event::<Fr>(&attrs("3", "!!")),
+            Err(EventError::InvalidAttribute("new_root"))
+        );
+        assert_eq!(
+            parse_deposit_event::<Fr>(&attrs("3", "AQ=="))
+                .expect("Event must be valid")
+                .leaf,
+            Fr::from(1)
+        );
+    }
+}
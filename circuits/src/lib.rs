@@ -1,6 +1,10 @@
 pub mod circuit;
+pub mod encoding;
 pub mod merkle_tree;
 pub mod poseidon;
+pub mod prelude;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod test_vectors;
 pub mod utils;
 
 pub use types::*;
@@ -12,15 +16,21 @@ mod types {
 
     use crate::{
         circuit::{
+            bitmap::AssetBitmapCircuit,
             main::MainCircuit,
             main_splitted::{MainSettleCircuit, MainSpendCircuit},
             migration::MigrationCircuit,
+            ownership::OwnershipProofCircuit,
+            same_owner::SameOwnerCircuit,
+            split::SplitCircuit,
+            threshold::BalanceThresholdCircuit,
         },
         poseidon::PoseidonHash,
     };
 
     pub const TREE_DEPTH: usize = 25;
     pub const N_ASSETS: usize = 7;
+    pub const N_SPLIT_NOTES: usize = 2;
 
     pub type PoseidonConfigVar<F> = CRHParametersVar<F>;
 
@@ -47,6 +57,58 @@ mod types {
         PoseidonHash<Fr>,
         PoseidonHash<Fr>,
     >;
+    pub type SplitCircuitBn254<
+        const N_ASSETS: usize,
+        const TREE_DEPTH: usize,
+        const N_NOTES: usize,
+    > = SplitCircuit<
+        N_ASSETS,
+        TREE_DEPTH,
+        N_NOTES,
+        Fr,
+        PoseidonConfig<Fr>,
+        PoseidonConfigVar<Fr>,
+        PoseidonHash<Fr>,
+        PoseidonHash<Fr>,
+    >;
+    pub type BalanceThresholdCircuitBn254<const N_ASSETS: usize, const TREE_DEPTH: usize> =
+        BalanceThresholdCircuit<
+            N_ASSETS,
+            TREE_DEPTH,
+            Fr,
+            PoseidonConfig<Fr>,
+            PoseidonConfigVar<Fr>,
+            PoseidonHash<Fr>,
+            PoseidonHash<Fr>,
+        >;
+    pub type AssetBitmapCircuitBn254<const N_ASSETS: usize> = AssetBitmapCircuit<
+        N_ASSETS,
+        Fr,
+        PoseidonConfig<Fr>,
+        PoseidonConfigVar<Fr>,
+        PoseidonHash<Fr>,
+        PoseidonHash<Fr>,
+    >;
+    pub type SameOwnerCircuitBn254<const N_ASSETS: usize, const TREE_DEPTH: usize> =
+        SameOwnerCircuit<
+            N_ASSETS,
+            TREE_DEPTH,
+            Fr,
+            PoseidonConfig<Fr>,
+            PoseidonConfigVar<Fr>,
+            PoseidonHash<Fr>,
+            PoseidonHash<Fr>,
+        >;
+    pub type OwnershipProofCircuitBn254<const N_ASSETS: usize, const TREE_DEPTH: usize> =
+        OwnershipProofCircuit<
+            N_ASSETS,
+            TREE_DEPTH,
+            Fr,
+            PoseidonConfig<Fr>,
+            PoseidonConfigVar<Fr>,
+            PoseidonHash<Fr>,
+            PoseidonHash<Fr>,
+        >;
     pub type SplittedSpendCircuitBn254<const N_ASSETS: usize, const TREE_DEPTH: usize> =
         MainSpendCircuit<
             N_ASSETS,
@@ -10,7 +10,7 @@ use std::{
 };
 
 use ark_crypto_primitives::crh::{TwoToOneCRHScheme, TwoToOneCRHSchemeGadget};
-use ark_ff::PrimeField;
+use ark_ff::{BigInteger, PrimeField};
 use ark_r1cs_std::{
     fields::fp::FpVar,
     prelude::{AllocVar, AllocationMode, Boolean, EqGadget, FieldVar},
@@ -30,6 +30,10 @@ pub enum MerkleError {
     #[error("Path nodes are not consistent")]
     InvalidPathNodes,
 
+    /// Thrown when inserting a leaf at an index which is already occupied.
+    #[error("Leaf index {0} is already occupied")]
+    OccupiedIndex(u32),
+
     #[error("Io: {0}")]
     Io(String),
 
@@ -131,6 +135,68 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
 
         Ok(index)
     }
+
+    /// Describes each level's left and right hashes in little endian hex, with the side `leaf`
+    /// ascends from, followed by the derived index.
+    ///
+    /// Stops at the first level not containing the hash of the level below, as it is the one
+    /// causing [`MerkleError::InvalidPathNodes`].
+    pub fn describe(&self, leaf: &F, hasher: &H::Parameters) -> Result<String, MerkleError> {
+        let to_hex = |f: &F| hex::encode(f.into_bigint().to_bytes_le());
+
+        let mut lines = vec![];
+        let mut prev = *leaf;
+        let mut index = F::zero();
+        let mut twopower = F::one();
+        for (level, (left_hash, right_hash)) in self.path.iter().enumerate() {
+            let side = if &prev == left_hash {
+                "left"
+            } else if &prev == right_hash {
+                index += twopower;
+                "right"
+            } else {
+                "not consistent"
+            };
+            lines.push(format!(
+                "level {level}: left {}, right {} ({side})",
+                to_hex(left_hash),
+                to_hex(right_hash),
+            ));
+            if side == "not consistent" {
+                return Ok(lines.join("\n"));
+            }
+            twopower = twopower + twopower;
+            prev = <H as TwoToOneCRHScheme>::evaluate(hasher, left_hash, right_hash)?;
+        }
+        lines.push(format!("index: {index}"));
+
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Computes the hashes of empty subtrees for each of the `N` levels, starting from the
+/// `empty_leaf` itself, with each level being the previous one hashed with itself.
+///
+/// The contract's tree must be initialized with the same hashes for the roots to match.
+pub fn compute_empty_hashes<
+    F: PrimeField,
+    H: TwoToOneCRHScheme<Input = F, Output = F>,
+    const N: usize,
+>(
+    hasher: &H::Parameters,
+    empty_leaf: &F,
+) -> Result<[F; N], MerkleError> {
+    let mut empty_hashes = [<F>::zero(); N];
+
+    let mut empty_hash = *empty_leaf;
+    empty_hashes[0] = empty_hash;
+
+    for hash in empty_hashes.iter_mut().skip(1) {
+        empty_hash = <H as TwoToOneCRHScheme>::evaluate(hasher, &empty_hash, &empty_hash)?;
+        *hash = empty_hash;
+    }
+
+    Ok(empty_hashes)
 }
 
 /// The Sparse Merkle Tree struct.
@@ -157,6 +223,9 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
 {
     /// Takes a batch of field elements, inserts
     /// these hashes into the tree, and updates the merkle root.
+    ///
+    /// The updated nodes are only written once all of them are hashed, so the tree is left
+    /// unchanged on error.
     pub fn insert_batch(
         &mut self,
         leaves: &BTreeMap<u32, F>,
@@ -164,10 +233,11 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
     ) -> Result<(), MerkleError> {
         let last_level_index: u64 = (1u64 << N) - 1;
 
+        let mut changes: BTreeMap<u64, F> = BTreeMap::new();
         let mut level_idxs: BTreeSet<u64> = BTreeSet::new();
         for (i, leaf) in leaves {
             let true_index = last_level_index + (*i as u64);
-            self.tree.insert(true_index, *leaf);
+            changes.insert(true_index, *leaf);
             level_idxs.insert((true_index - 1) >> 1);
         }
 
@@ -178,10 +248,18 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
                 let right_index = 2 * i + 2;
 
                 let empty_hash = self.empty_hashes[level];
-                let left = self.tree.get(&left_index).unwrap_or(&empty_hash);
-                let right = self.tree.get(&right_index).unwrap_or(&empty_hash);
-                let hashed = <H as TwoToOneCRHScheme>::evaluate(hasher, left, right)?;
-                self.tree.insert(i, hashed);
+                let node = |index| {
+                    changes
+                        .get(&index)
+                        .or_else(|| self.tree.get(&index))
+                        .unwrap_or(&empty_hash)
+                };
+                let hashed = <H as TwoToOneCRHScheme>::evaluate(
+                    hasher,
+                    node(left_index),
+                    node(right_index),
+                )?;
+                changes.insert(i, hashed);
 
                 let parent = match i > 0 {
                     true => (i - 1) >> 1,
@@ -192,9 +270,29 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
             level_idxs = new_idxs;
         }
 
+        self.tree.extend(changes);
         Ok(())
     }
 
+    /// Same as [`SparseMerkleTree::insert_batch`], but fails without touching the tree if any of
+    /// the `leaves` targets an index already holding a leaf.
+    ///
+    /// Notes are only ever appended, so overwriting a leaf means the index was reused by mistake.
+    pub fn insert_checked(
+        &mut self,
+        leaves: &BTreeMap<u32, F>,
+        hasher: &H::Parameters,
+    ) -> Result<(), MerkleError> {
+        let last_level_index: u64 = (1u64 << N) - 1;
+        if let Some(i) = leaves
+            .keys()
+            .find(|i| self.tree.contains_key(&(last_level_index + **i as u64)))
+        {
+            return Err(MerkleError::OccupiedIndex(*i));
+        }
+        self.insert_batch(leaves, hasher)
+    }
+
     /// Creates a new Sparse Merkle Tree from a map of indices to field
     /// elements.
     pub fn new(
@@ -206,23 +304,12 @@ impl<F: PrimeField, H: TwoToOneCRHScheme<Input = F, Output = F>, const N: usize>
         let last_level_size = leaves.len().next_power_of_two();
         let tree_size = 2 * last_level_size - 1;
         let tree_height = ark_std::log2(tree_size);
-        assert!(tree_height <= N as u32);
+        // The height counts the leaf level, which is not part of the depth
+        assert!(tree_height <= N as u32 + 1);
 
         // Initialize the merkle tree
         let tree: BTreeMap<u64, F> = BTreeMap::new();
-        let empty_hashes = {
-            let mut empty_hashes = [<F>::zero(); N];
-
-            let mut empty_hash = *empty_leaf;
-            empty_hashes[0] = empty_hash;
-
-            for hash in empty_hashes.iter_mut().skip(1) {
-                empty_hash = <H as TwoToOneCRHScheme>::evaluate(hasher, &empty_hash, &empty_hash)?;
-                *hash = empty_hash;
-            }
-
-            Result::<_, MerkleError>::Ok(empty_hashes)
-        }?;
+        let empty_hashes = compute_empty_hashes::<F, H, N>(hasher, empty_leaf)?;
 
         let mut smt = SparseMerkleTree::<F, H, N> {
             tree,
@@ -374,7 +461,7 @@ impl<
             twopower = &twopower + &twopower;
 
             previous_hash =
-                <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(hasher, &left_hash, &right_hash)?;
+                <HG as TwoToOneCRHSchemeGadget<H, F>>::evaluate(hasher, left_hash, right_hash)?;
         }
 
         Ok(index)
@@ -420,14 +507,116 @@ impl<
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::BTreeMap, error::Error};
+    use std::{
+        borrow::Borrow,
+        collections::{BTreeMap, BTreeSet},
+        error::Error,
+    };
 
     use ark_bn254::Fr;
+    use ark_crypto_primitives::crh::TwoToOneCRHScheme;
+    use ark_ff::Field;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_r1cs_std::{
+        fields::fp::FpVar,
+        prelude::{AllocVar, EqGadget},
+        R1CSVar,
+    };
+    use ark_relations::{
+        ns,
+        r1cs::{ConstraintSystem, ConstraintSystemRef},
+    };
     use ark_std::Zero;
+    use proptest::{prelude::*, sample::Index};
 
-    use crate::{poseidon::PoseidonHash, utils::poseidon_bn254, TREE_DEPTH};
+    use crate::{poseidon::PoseidonHash, utils::poseidon_bn254, PoseidonConfigVar, TREE_DEPTH};
 
-    use super::SparseMerkleTree;
+    use super::{MerkleError, PathVar, SparseMerkleTree};
+
+    /// Small depth for fuzzing, so that every leaf position is reachable.
+    const FUZZ_DEPTH: usize = 4;
+
+    type FuzzTree = SparseMerkleTree<Fr, PoseidonHash<Fr>, FUZZ_DEPTH>;
+
+    /// Builds a tree with a distinct leaf derived from `seed` at each of `indices`, returning it
+    /// with the leaf at the sampled index.
+    fn fuzz_tree(
+        indices: &BTreeSet<u32>,
+        seed: u64,
+        selected: &Index,
+    ) -> Result<(FuzzTree, u32, Fr), Box<dyn Error>> {
+        let hash = poseidon_bn254();
+        let leaves = indices
+            .iter()
+            .map(|i| {
+                Ok((
+                    *i,
+                    PoseidonHash::tto_crh(&hash, Fr::from(seed), Fr::from(*i))?,
+                ))
+            })
+            .collect::<Result<BTreeMap<_, _>, Box<dyn Error>>>()?;
+        let tree = FuzzTree::new(&leaves, &hash, &Fr::zero())?;
+        let (index, leaf) = leaves
+            .iter()
+            .nth(selected.index(leaves.len()))
+            .expect("sampled index is in range");
+
+        Ok((tree, *index, *leaf))
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_path(
+            indices in prop::collection::btree_set(0..1u32 << FUZZ_DEPTH, 1..=1 << FUZZ_DEPTH),
+            seed in any::<u64>(),
+            selected in any::<Index>(),
+        ) {
+            let hash = poseidon_bn254();
+            let (tree, index, leaf) = fuzz_tree(&indices, seed, &selected).unwrap();
+            let proof = tree.generate_membership_proof(index as u64);
+
+            prop_assert_eq!(proof.calculate_root(&leaf, &hash).unwrap(), tree.root());
+            prop_assert_eq!(
+                proof.get_index(&tree.root(), &leaf, &hash).unwrap(),
+                Fr::from(index)
+            );
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+
+        #[test]
+        fn fuzz_path_var(
+            indices in prop::collection::btree_set(0..1u32 << FUZZ_DEPTH, 1..=1 << FUZZ_DEPTH),
+            seed in any::<u64>(),
+            selected in any::<Index>(),
+        ) {
+            let hash = poseidon_bn254();
+            let (tree, index, leaf) = fuzz_tree(&indices, seed, &selected).unwrap();
+            let proof = tree.generate_membership_proof(index as u64);
+
+            let cs: ConstraintSystemRef<Fr> = ConstraintSystem::new_ref();
+            let parameters = PoseidonConfigVar::new_constant(ns!(cs, "parameters"), &hash).unwrap();
+            let root = FpVar::new_input(ns!(cs, "root"), || Ok(tree.root())).unwrap();
+            let leaf = FpVar::new_witness(ns!(cs, "leaf"), || Ok(leaf)).unwrap();
+            let path = PathVar::<Fr, PoseidonHash<Fr>, PoseidonHash<Fr>, FUZZ_DEPTH>::new_witness(
+                ns!(cs, "path"),
+                || Ok(proof),
+            )
+            .unwrap();
+
+            path.root_hash(&leaf, &parameters)
+                .unwrap()
+                .enforce_equal(&root)
+                .unwrap();
+            prop_assert_eq!(
+                path.get_index(&leaf, &parameters).unwrap().value().unwrap(),
+                Fr::from(index)
+            );
+            prop_assert!(cs.is_satisfied().unwrap());
+        }
+    }
 
     #[test]
     fn correct_proof() -> Result<(), Box<dyn Error>> {
@@ -450,4 +639,122 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn insert_checked_rejects_occupied_index() -> Result<(), Box<dyn Error>> {
+        let hash = poseidon_bn254();
+        let mut tree = FuzzTree::new(&BTreeMap::from([(0, Fr::from(1))]), &hash, &Fr::zero())?;
+        let before = tree.clone();
+
+        assert!(matches!(
+            tree.insert_checked(&BTreeMap::from([(1, Fr::from(2)), (0, Fr::from(3))]), &hash),
+            Err(MerkleError::OccupiedIndex(0))
+        ));
+        assert_eq!(tree, before);
+
+        tree.insert_checked(&BTreeMap::from([(1, Fr::from(2))]), &hash)?;
+        assert_eq!(
+            tree.root(),
+            FuzzTree::new_sequential(&[Fr::from(1), Fr::from(2)], &hash, &Fr::zero())?.root()
+        );
+
+        // Rebuilding overwrites silently
+        tree.insert_batch(&BTreeMap::from([(0, Fr::from(3))]), &hash)?;
+        assert_eq!(
+            tree.root(),
+            FuzzTree::new_sequential(&[Fr::from(3), Fr::from(2)], &hash, &Fr::zero())?.root()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn describe_path() -> Result<(), Box<dyn Error>> {
+        let hash = poseidon_bn254();
+        let tree = SparseMerkleTree::<Fr, PoseidonHash<Fr>, TREE_DEPTH>::new(
+            &BTreeMap::from([(0, Fr::from(1)), (1, Fr::from(10))]),
+            &hash,
+            &Fr::zero(),
+        )?;
+        let to_hex = |f: Fr| hex::encode(f.into_bigint().to_bytes_le());
+
+        let proof = tree.generate_membership_proof(1);
+        let description = proof.describe(&Fr::from(10), &hash)?;
+        let lines = description.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), TREE_DEPTH + 1);
+        assert_eq!(
+            lines[0],
+            format!(
+                "level 0: left {}, right {} (right)",
+                to_hex(Fr::from(1)),
+                to_hex(Fr::from(10))
+            )
+        );
+        assert!(lines[1].ends_with("(left)"));
+        assert_eq!(lines[TREE_DEPTH], "index: 1");
+
+        let description = proof.describe(&Fr::from(11), &hash)?;
+        assert_eq!(description.lines().count(), 1);
+        assert!(description.ends_with("(not consistent)"));
+
+        Ok(())
+    }
+
+    /// Hasher failing on a `poison` left input, to inject errors mid level.
+    #[derive(Debug, Clone, PartialEq)]
+    struct PoisonedHash;
+
+    impl TwoToOneCRHScheme for PoisonedHash {
+        type Input = Fr;
+        type Output = Fr;
+        type Parameters = Fr;
+
+        fn setup<R: ark_std::rand::Rng>(_r: &mut R) -> Result<Fr, ark_crypto_primitives::Error> {
+            unimplemented!()
+        }
+
+        fn evaluate<T: Borrow<Fr>>(
+            poison: &Fr,
+            left_input: T,
+            right_input: T,
+        ) -> Result<Fr, ark_crypto_primitives::Error> {
+            let (left, right) = (*left_input.borrow(), *right_input.borrow());
+            match left == *poison {
+                true => Err("poisoned input".into()),
+                false => Ok(left.double() + right + Fr::from(1)),
+            }
+        }
+
+        fn compress<T: Borrow<Fr>>(
+            poison: &Fr,
+            left_input: T,
+            right_input: T,
+        ) -> Result<Fr, ark_crypto_primitives::Error> {
+            Self::evaluate(poison, left_input, right_input)
+        }
+    }
+
+    #[test]
+    fn insert_batch_failure_leaves_tree_unchanged() -> Result<(), Box<dyn Error>> {
+        let poison = Fr::from(666);
+        let mut tree = SparseMerkleTree::<Fr, PoisonedHash, FUZZ_DEPTH>::new(
+            &BTreeMap::from([(3, Fr::from(3))]),
+            &poison,
+            &Fr::zero(),
+        )?;
+        let before = tree.clone();
+
+        // The first leaf hashes fine, the second fails on the same level
+        assert!(tree
+            .insert_batch(&BTreeMap::from([(0, Fr::from(1)), (4, poison)]), &poison)
+            .is_err());
+        assert_eq!(tree.root(), before.root());
+        assert_eq!(tree, before);
+
+        tree.insert_batch(&BTreeMap::from([(0, Fr::from(1))]), &poison)?;
+        assert_ne!(tree.root(), before.root());
+
+        Ok(())
+    }
 }
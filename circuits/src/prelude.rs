@@ -0,0 +1,23 @@
+//! Everything needed to build and prove the Bn254 circuits, so that integrators can
+//! `use circuits::prelude::*`.
+//!
+//! ```
+//! use circuits::prelude::*;
+//!
+//! let hasher = poseidon_bn254();
+//! let (circuit, tree) = MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty(&hasher);
+//!
+//! let path: Path<_, PoseidonHash<_>, { TREE_DEPTH }> = tree.generate_membership_proof(0);
+//! assert_eq!(circuit.old_note_path.path, path.path);
+//! ```
+
+pub use crate::{
+    encoding::{
+        address_to_field, asset_bitmap, encode_swap, hash_bytes, parse_deposit_event, swap_aux,
+        DepositEvent, SwapRoute, MAX_ROUTES,
+    },
+    merkle_tree::{Path, SparseMerkleTree},
+    poseidon::PoseidonHash,
+    types::*,
+    utils::poseidon_bn254,
+};
@@ -0,0 +1,75 @@
+//! Note commitment vectors of `vectors/notes.json`, shared by the circuit and contract test
+//! suites so both commit notes the same way.
+
+use std::{error::Error, str::FromStr};
+
+use ark_bn254::Fr;
+use serde_json::Value;
+
+use crate::N_ASSETS;
+
+/// A note with its fixed inputs and expected hashes under [`crate::utils::poseidon_bn254`].
+#[derive(Debug, Clone)]
+pub struct NoteVector {
+    pub name: String,
+    pub address: String,
+    pub address_field: Fr,
+    pub nullifier: Fr,
+    pub blinding: Fr,
+    pub amounts: [u128; N_ASSETS],
+    pub balance_root: Fr,
+    pub identifier: Fr,
+    pub note: Fr,
+    pub nullifier_hash: Fr,
+}
+
+impl NoteVector {
+    pub fn balances(&self) -> [Fr; N_ASSETS] {
+        self.amounts.map(Fr::from)
+    }
+}
+
+/// Parses the note vectors, failing if they weren't generated for `N_ASSETS`.
+pub fn note_vectors() -> Result<Vec<NoteVector>, Box<dyn Error>> {
+    let vectors: Value = serde_json::from_str(include_str!("../vectors/notes.json"))?;
+    if vectors["n_assets"] != N_ASSETS {
+        return Err("Vectors asset count mismatch".into());
+    }
+
+    let field = |value: &Value| -> Result<Fr, Box<dyn Error>> {
+        Fr::from_str(value.as_str().ok_or("field is not a string")?)
+            .map_err(|_| "invalid field element".into())
+    };
+    let string = |value: &Value| -> Result<String, Box<dyn Error>> {
+        Ok(value.as_str().ok_or("expected a string")?.to_string())
+    };
+
+    vectors["vectors"]
+        .as_array()
+        .ok_or("missing vectors")?
+        .iter()
+        .map(|vector| {
+            let mut amounts = [0; N_ASSETS];
+            let balances = vector["balances"].as_array().ok_or("missing balances")?;
+            if balances.len() != N_ASSETS {
+                return Err("Vector balance count mismatch".into());
+            }
+            for (amount, balance) in amounts.iter_mut().zip(balances) {
+                *amount = u128::from_str(balance.as_str().ok_or("invalid balance")?)?;
+            }
+
+            Ok(NoteVector {
+                name: string(&vector["name"])?,
+                address: string(&vector["address"])?,
+                address_field: field(&vector["address_field"])?,
+                nullifier: field(&vector["nullifier"])?,
+                blinding: field(&vector["blinding"])?,
+                amounts,
+                balance_root: field(&vector["balance_root"])?,
+                identifier: field(&vector["identifier"])?,
+                note: field(&vector["note"])?,
+                nullifier_hash: field(&vector["nullifier_hash"])?,
+            })
+        })
+        .collect()
+}
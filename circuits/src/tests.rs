@@ -1,20 +1,47 @@
 use std::{collections::BTreeMap, error::Error, println};
 
 use ark_bn254::Fr;
+use ark_crypto_primitives::{
+    crh::{CRHSchemeGadget, TwoToOneCRHSchemeGadget},
+    sponge::poseidon::PoseidonConfig,
+};
 use ark_ff::PrimeField;
-use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+use ark_r1cs_std::{
+    fields::fp::FpVar,
+    prelude::{AllocVar, Boolean, EqGadget},
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError},
+};
 use ark_std::{test_rng, UniformRand, Zero};
 
 use crate::{
-    poseidon::PoseidonHash, utils::poseidon_bn254, MainCircuitBn254, MigrationCircuitBn254,
-    SplittedSettleCircuitBn254, SplittedSpendCircuitBn254, N_ASSETS, TREE_DEPTH,
+    circuit::{gadgets::calculate_balance_root, same_owner::OwnedNote},
+    encoding::asset_bitmap,
+    merkle_tree::{Path, PathVar},
+    poseidon::PoseidonHash,
+    test_vectors::note_vectors,
+    utils::poseidon_bn254,
+    AssetBitmapCircuitBn254, BalanceThresholdCircuitBn254, MainCircuitBn254, MigrationCircuitBn254,
+    OwnershipProofCircuitBn254, PoseidonConfigVar, SameOwnerCircuitBn254, SplitCircuitBn254,
+    SplittedSettleCircuitBn254, SplittedSpendCircuitBn254, N_ASSETS, N_SPLIT_NOTES, TREE_DEPTH,
 };
 
 type TestMain = MainCircuitBn254<3, 10>;
 type ProdMain = MainCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
 type TestMigration = MigrationCircuitBn254<3, 10, 25>;
+type TestSplit = SplitCircuitBn254<3, 10, 2>;
+type ProdSplit = SplitCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }, { N_SPLIT_NOTES }>;
+type TestThreshold = BalanceThresholdCircuitBn254<3, 10>;
+type ProdThreshold = BalanceThresholdCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type TestSameOwner = SameOwnerCircuitBn254<3, 10>;
+type ProdSameOwner = SameOwnerCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type TestOwnership = OwnershipProofCircuitBn254<3, 10>;
+type ProdOwnership = OwnershipProofCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
 type ProdSplittedSpend = SplittedSpendCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
 type ProdSplittedSettle = SplittedSettleCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type TestBitmap = AssetBitmapCircuitBn254<3>;
 
 #[test]
 pub fn num_constraints() -> Result<(), Box<dyn Error>> {
@@ -44,6 +71,38 @@ pub fn num_constraints() -> Result<(), Box<dyn Error>> {
         cs.num_constraints() + cs.num_instance_variables()
     );
 
+    let cs = ConstraintSystem::new_ref();
+    ProdSplit::empty_without_tree(&poseidon).generate_constraints(cs.clone())?;
+
+    println!(
+        "Split Constraints {}",
+        cs.num_constraints() + cs.num_instance_variables()
+    );
+
+    let cs = ConstraintSystem::new_ref();
+    ProdThreshold::empty_without_tree(&poseidon).generate_constraints(cs.clone())?;
+
+    println!(
+        "Balance Threshold Constraints {}",
+        cs.num_constraints() + cs.num_instance_variables()
+    );
+
+    let cs = ConstraintSystem::new_ref();
+    ProdSameOwner::empty_without_tree(&poseidon).generate_constraints(cs.clone())?;
+
+    println!(
+        "Same Owner Constraints {}",
+        cs.num_constraints() + cs.num_instance_variables()
+    );
+
+    let cs = ConstraintSystem::new_ref();
+    ProdOwnership::empty_without_tree(&poseidon).generate_constraints(cs.clone())?;
+
+    println!(
+        "Ownership Proof Constraints {}",
+        cs.num_constraints() + cs.num_instance_variables()
+    );
+
     let cs = ConstraintSystem::new_ref();
     ProdSplittedSpend::empty_without_tree(&poseidon).generate_constraints(cs.clone())?;
 
@@ -60,9 +119,74 @@ pub fn num_constraints() -> Result<(), Box<dyn Error>> {
         cs.num_constraints() + cs.num_instance_variables()
     );
 
+    let breakdown = gadget_constraints(&poseidon)?;
+    for (gadget, constraints) in &breakdown {
+        println!("Prod {gadget} Constraints {constraints}");
+    }
+    let (dominant, _) = breakdown
+        .iter()
+        .max_by_key(|(_, constraints)| *constraints)
+        .expect("breakdown is not empty");
+    assert_eq!(*dominant, "Membership", "membership must dominate");
+
     Ok(())
 }
 
+/// Synthesizes each gadget of the prod main circuit in its own namespace, returning the number of
+/// constraints it adds.
+fn gadget_constraints(
+    poseidon: &PoseidonConfig<Fr>,
+) -> Result<Vec<(&'static str, usize)>, Box<dyn Error>> {
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let parameters = PoseidonConfigVar::new_constant(ns!(cs, "parameters"), poseidon)?;
+    let field = || FpVar::new_witness(ns!(cs, "field"), || Ok(Fr::zero()));
+    let balances =
+        Vec::<FpVar<Fr>>::new_witness(ns!(cs, "balances"), || Ok(vec![Fr::zero(); N_ASSETS]))?;
+    let path = PathVar::<Fr, PoseidonHash<Fr>, PoseidonHash<Fr>, TREE_DEPTH>::new_witness(
+        ns!(cs, "path"),
+        || Ok(Path::empty()),
+    )?;
+    let (root, leaf, address, blinding, nullifier) =
+        (field()?, field()?, field()?, field()?, field()?);
+
+    let mut breakdown = vec![];
+    let mut before = cs.num_constraints();
+    let mut record = |gadget, cs: &ConstraintSystemRef<Fr>| {
+        breakdown.push((gadget, cs.num_constraints() - before));
+        before = cs.num_constraints();
+    };
+
+    let membership_cs = ns!(cs, "membership").cs();
+    path.check_membership(&root, &leaf, &parameters)?
+        .enforce_equal(&Boolean::TRUE)?;
+    record("Membership", &membership_cs);
+
+    let balance_root_cs = ns!(cs, "balance_root").cs();
+    let balance_root =
+        calculate_balance_root::<Fr, PoseidonHash<Fr>, PoseidonHash<Fr>>(&parameters, &balances)?;
+    record("Balance Root", &balance_root_cs);
+
+    let note_commitment_cs = ns!(cs, "note_commitment").cs();
+    let identifier = <PoseidonHash<Fr> as TwoToOneCRHSchemeGadget<_, _>>::evaluate(
+        &parameters,
+        &address,
+        &blinding,
+    )?;
+    leaf.enforce_equal(&<PoseidonHash<Fr> as CRHSchemeGadget<_, _>>::evaluate(
+        &parameters,
+        &[balance_root, identifier, nullifier],
+    )?)?;
+    record("Note Commitment", &note_commitment_cs);
+
+    let range_check_cs = ns!(cs, "range_check").cs();
+    for balance in &balances {
+        balance.enforce_smaller_or_equal_than_mod_minus_one_div_two()?;
+    }
+    record("Balances Range Check", &range_check_cs);
+
+    Ok(breakdown)
+}
+
 #[test]
 pub fn deposit_first_time() -> Result<(), Box<dyn Error>> {
     let rng = &mut test_rng();
@@ -112,6 +236,79 @@ pub fn deposit_first_time() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+pub fn vk_check() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let cs = ConstraintSystem::<Fr>::new_ref();
+
+    let circuit = TestMain::vk_check(Fr::rand(rng), Fr::rand(rng), Fr::rand(rng), &hash)?;
+    assert_eq!(
+        circuit.diff_balance_root,
+        PoseidonHash::crh(&hash, &[Fr::zero(); 3])?
+    );
+    assert_eq!(
+        (
+            circuit.aux,
+            circuit.utxo_root,
+            circuit.old_note_nullifier_hash
+        ),
+        (Fr::zero(), Fr::zero(), Fr::zero())
+    );
+    circuit.generate_constraints(cs.clone())?;
+
+    assert!(cs.is_satisfied()?, "constraints not satisfied");
+
+    Ok(())
+}
+
+#[test]
+pub fn zero_new_note() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, tree) = TestMain::empty(&hash);
+
+    let address = Fr::from_le_bytes_mod_order(b"osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+    let nullifier = Fr::rand(rng);
+    let diff_balances = [Fr::from(100), Fr::zero(), Fr::zero()];
+    let diff_balance_root = PoseidonHash::crh(&hash, &diff_balances)?;
+    let new_note_blinding = Fr::rand(rng);
+    let new_note = PoseidonHash::crh(
+        &hash,
+        &[
+            diff_balance_root,
+            PoseidonHash::tto_crh(&hash, address, new_note_blinding)?,
+            nullifier,
+        ],
+    )?;
+
+    for (new_note, is_satisfied) in [(new_note, true), (Fr::zero(), false)] {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestMain {
+            address,
+            nullifier,
+            aux: Fr::zero(),
+            utxo_root: tree.root(),
+            diff_balance_root,
+            diff_balances,
+            old_note_nullifier_hash: Fr::zero(),
+            old_note_identifier: Fr::zero(),
+            old_note_path: tree.generate_membership_proof(0),
+            old_note_balances: [Fr::zero(); 3],
+            new_note,
+            new_note_blinding,
+            new_note_balances: diff_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+
+        assert_eq!(cs.is_satisfied()?, is_satisfied, "new note {new_note}");
+    }
+
+    Ok(())
+}
+
 #[test]
 pub fn deposit_alot() -> Result<(), Box<dyn Error>> {
     let rng = &mut test_rng();
@@ -254,6 +451,83 @@ pub fn diff_swap_plus_fee() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+pub fn swap_fee_conservation() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestMain::empty(&hash);
+
+    let address_str = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+    let address = Fr::from_le_bytes_mod_order(address_str.as_bytes());
+    let nullifier = Fr::rand(rng);
+
+    let old_note_blinding = Fr::rand(rng);
+    let old_note_balances = [Fr::from(300), Fr::from(200), Fr::zero()];
+    let old_note_identifier = PoseidonHash::tto_crh(&hash, address, old_note_blinding)?;
+    let old_note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &old_note_balances)?,
+            old_note_identifier,
+            nullifier,
+        ],
+    )?;
+    let old_note_nullifier_hash = PoseidonHash::tto_crh(&hash, old_note, nullifier)?;
+    tree.insert_batch(&BTreeMap::from([(0, old_note)]), &hash)?;
+
+    for (case, diffs, is_satisfied) in [
+        // Swap 100 asset 2 to 200 asset 3, paying 20 asset 3 fees
+        ("fee in third asset", [0, -100, 180], true),
+        // Swap 100 asset 1 to 200 asset 3, paying 10 asset 1 fees
+        ("fee in input asset", [-110, 0, 200], true),
+        ("zero fee", [-100, 0, 200], true),
+        // Swap 300 asset 1 to 200 asset 3, paying 50 asset 1 fees over the held 300
+        ("outflow over held balance", [-350, 0, 200], false),
+    ] {
+        let diff_balances = diffs.map(Fr::from);
+        let mut new_note_balances = old_note_balances;
+        for (balance, diff) in new_note_balances.iter_mut().zip(diff_balances) {
+            *balance += diff;
+        }
+        let new_note_blinding = Fr::rand(rng);
+        let new_note = PoseidonHash::crh(
+            &hash,
+            &[
+                PoseidonHash::crh(&hash, &new_note_balances)?,
+                PoseidonHash::tto_crh(&hash, address, new_note_blinding)?,
+                nullifier,
+            ],
+        )?;
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestMain {
+            address,
+            nullifier,
+            aux: Fr::zero(),
+            utxo_root: tree.root(),
+            diff_balance_root: PoseidonHash::crh(&hash, &diff_balances)?,
+            diff_balances,
+            old_note_nullifier_hash,
+            old_note_identifier,
+            old_note_path: tree.generate_membership_proof(0),
+            old_note_balances,
+            new_note,
+            new_note_blinding,
+            new_note_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+        assert_eq!(
+            cs.is_satisfied()?,
+            is_satisfied,
+            "invalid satisfiability with {case}"
+        );
+    }
+
+    Ok(())
+}
+
 #[test]
 pub fn deposit_subsequent() -> Result<(), Box<dyn Error>> {
     let rng = &mut test_rng();
@@ -365,3 +639,455 @@ pub fn cannot_withdraw_empty() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+pub fn note_test_vectors() -> Result<(), Box<dyn Error>> {
+    let poseidon = poseidon_bn254();
+
+    for vector in note_vectors()? {
+        let name = &vector.name;
+        let address_field = Fr::from_le_bytes_mod_order(vector.address.as_bytes());
+        assert_eq!(address_field, vector.address_field, "{name}: address");
+
+        let balance_root = PoseidonHash::crh(&poseidon, &vector.balances())?;
+        assert_eq!(balance_root, vector.balance_root, "{name}: balance root");
+
+        let identifier = PoseidonHash::tto_crh(&poseidon, address_field, vector.blinding)?;
+        assert_eq!(identifier, vector.identifier, "{name}: identifier");
+
+        let note = PoseidonHash::crh(&poseidon, &[balance_root, identifier, vector.nullifier])?;
+        assert_eq!(note, vector.note, "{name}: note");
+
+        let nullifier_hash = PoseidonHash::tto_crh(&poseidon, note, vector.nullifier)?;
+        assert_eq!(
+            nullifier_hash, vector.nullifier_hash,
+            "{name}: nullifier hash"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn deposit_note_vectors() -> Result<(), Box<dyn Error>> {
+    let poseidon = poseidon_bn254();
+
+    for vector in note_vectors()? {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let circuit = ProdMain {
+            address: vector.address_field,
+            nullifier: vector.nullifier,
+            aux: Fr::zero(),
+            utxo_root: Fr::zero(),
+            diff_balance_root: vector.balance_root,
+            diff_balances: vector.balances(),
+            old_note_nullifier_hash: Fr::zero(),
+            old_note_identifier: Fr::zero(),
+            old_note_path: Path::empty(),
+            old_note_balances: [Fr::zero(); N_ASSETS],
+            new_note: vector.note,
+            new_note_blinding: vector.blinding,
+            new_note_balances: vector.balances(),
+            parameters: poseidon.clone(),
+            _hg: std::marker::PhantomData,
+        };
+        circuit.generate_constraints(cs.clone())?;
+
+        assert!(
+            cs.is_satisfied()?,
+            "{}: constraints not satisfied",
+            vector.name
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn migration_to_fewer_assets() -> Result<(), Box<dyn Error>> {
+    let poseidon = poseidon_bn254();
+
+    for result in [
+        MigrationCircuitBn254::<3, 3, 10>::empty_without_tree(&poseidon)
+            .generate_constraints(ConstraintSystem::new_ref()),
+        MigrationCircuitBn254::<3, 2, 10>::empty_without_tree(&poseidon)
+            .generate_constraints(ConstraintSystem::new_ref()),
+    ] {
+        assert!(matches!(result, Err(SynthesisError::Unsatisfiable)));
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn split_note() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestSplit::empty(&hash);
+
+    let address_str = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+    let address = Fr::from_le_bytes_mod_order(address_str.as_bytes());
+    let nullifier = Fr::rand(rng);
+
+    let old_note_blinding = Fr::rand(rng);
+    let old_note_balances = [Fr::from(100), Fr::from(200), Fr::zero()];
+    let old_note_identifier = PoseidonHash::tto_crh(&hash, address, old_note_blinding)?;
+    let old_note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &old_note_balances)?,
+            old_note_identifier,
+            nullifier,
+        ],
+    )?;
+    tree.insert_batch(&BTreeMap::from([(0, old_note)]), &hash)?;
+
+    let diff_balances = [Fr::zero(), Fr::zero(), Fr::from(50)];
+    let new_note_blindings = [Fr::rand(rng), Fr::rand(rng)];
+    let new_note_balances = [
+        [Fr::from(60), Fr::from(200), Fr::zero()],
+        [Fr::from(40), Fr::zero(), Fr::from(50)],
+    ];
+    let new_note = |balances: &[Fr; 3], blinding: Fr| -> Result<Fr, Box<dyn Error>> {
+        PoseidonHash::crh(
+            &hash,
+            &[
+                PoseidonHash::crh(&hash, balances)?,
+                PoseidonHash::tto_crh(&hash, address, blinding)?,
+                nullifier,
+            ],
+        )
+    };
+    let new_notes = [
+        new_note(&new_note_balances[0], new_note_blindings[0])?,
+        new_note(&new_note_balances[1], new_note_blindings[1])?,
+    ];
+
+    let circuit = |new_note_balances: [[Fr; 3]; 2],
+                   new_notes: [Fr; 2]|
+     -> Result<TestSplit, Box<dyn Error>> {
+        Ok(TestSplit {
+            address,
+            nullifier,
+            aux: Fr::zero(),
+            utxo_root: tree.root(),
+            diff_balance_root: PoseidonHash::crh(&hash, &diff_balances)?,
+            diff_balances,
+            old_note_nullifier_hash: PoseidonHash::tto_crh(&hash, old_note, nullifier)?,
+            old_note_identifier,
+            old_note_path: tree.generate_membership_proof(0),
+            old_note_balances,
+            new_notes,
+            new_note_blindings,
+            new_note_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        })
+    };
+
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    circuit(new_note_balances, new_notes)?.generate_constraints(cs.clone())?;
+    assert!(cs.is_satisfied()?, "constraints not satisfied");
+
+    // New notes holding more than the old note and diff combined
+    let mut inflated_balances = new_note_balances;
+    inflated_balances[1][0] = Fr::from(41);
+    let inflated_notes = [
+        new_notes[0],
+        new_note(&inflated_balances[1], new_note_blindings[1])?,
+    ];
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    circuit(inflated_balances, inflated_notes)?.generate_constraints(cs.clone())?;
+    assert!(!cs.is_satisfied()?, "inflated split must not be satisfied");
+
+    // Neither new note may be zero
+    for i in 0..2 {
+        let mut zero_notes = new_notes;
+        zero_notes[i] = Fr::zero();
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit(new_note_balances, zero_notes)?.generate_constraints(cs.clone())?;
+        assert!(
+            !cs.is_satisfied()?,
+            "zero new note {i} must not be satisfied"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn balance_threshold() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestThreshold::empty(&hash);
+
+    let address_str = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+    let address = Fr::from_le_bytes_mod_order(address_str.as_bytes());
+    let nullifier = Fr::rand(rng);
+    let note_blinding = Fr::rand(rng);
+    let note_balances = [Fr::from(100), Fr::from(200), Fr::zero()];
+    let note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &note_balances)?,
+            PoseidonHash::tto_crh(&hash, address, note_blinding)?,
+            nullifier,
+        ],
+    )?;
+    tree.insert_batch(&BTreeMap::from([(0, Fr::from(1)), (1, note)]), &hash)?;
+    let nullifier_hash = PoseidonHash::tto_crh(&hash, note, nullifier)?;
+
+    for (threshold, is_satisfied) in [(200, true), (150, true), (201, false)] {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestThreshold {
+            utxo_root: tree.root(),
+            address,
+            asset_index: Fr::from(1),
+            threshold: Fr::from(threshold),
+            nullifier_hash,
+            nullifier,
+            note_blinding,
+            note_path: tree.generate_membership_proof(1),
+            note_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+        assert_eq!(
+            cs.is_satisfied()?,
+            is_satisfied,
+            "invalid satisfiability at threshold {threshold}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn balance_threshold_spent_note() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestThreshold::empty(&hash);
+
+    let address = Fr::from_le_bytes_mod_order(b"osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+    let nullifier = Fr::rand(rng);
+    let note_blinding = Fr::rand(rng);
+    let note_balances = [Fr::from(100), Fr::from(200), Fr::zero()];
+    let note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &note_balances)?,
+            PoseidonHash::tto_crh(&hash, address, note_blinding)?,
+            nullifier,
+        ],
+    )?;
+    tree.insert_batch(&BTreeMap::from([(0, note)]), &hash)?;
+
+    // The nullifier hash revealed when the note was spent, as the main circuit computes it
+    let spent_nullifier_hash = PoseidonHash::tto_crh(&hash, note, nullifier)?;
+    let circuit = |nullifier_hash| TestThreshold {
+        utxo_root: tree.root(),
+        address,
+        asset_index: Fr::from(1),
+        threshold: Fr::from(100),
+        nullifier_hash,
+        nullifier,
+        note_blinding,
+        note_path: tree.generate_membership_proof(0),
+        note_balances,
+        parameters: hash.clone(),
+        _hg: std::marker::PhantomData,
+    };
+
+    // A proof of the spent note can only expose its spent nullifier hash, which the verifier
+    // rejects
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    circuit(spent_nullifier_hash).generate_constraints(cs.clone())?;
+    assert!(cs.is_satisfied()?);
+    assert_eq!(
+        cs.borrow()
+            .ok_or("missing constraint system")?
+            .instance_assignment[5],
+        spent_nullifier_hash
+    );
+
+    // Hiding it behind another nullifier hash is unsatisfiable
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    circuit(Fr::rand(rng)).generate_constraints(cs.clone())?;
+    assert!(!cs.is_satisfied()?);
+
+    Ok(())
+}
+
+#[test]
+pub fn same_owner() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestSameOwner::empty(&hash);
+
+    let owner = Fr::from_le_bytes_mod_order(b"osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+    let other = Fr::from_le_bytes_mod_order(b"osmo1cyyzpxplxdzkeea7kwsydadg87357qnahakaks");
+
+    // Notes of the owner with different blindings, and a note of another address
+    let mut notes = vec![];
+    for address in [owner, owner, other] {
+        let blinding = Fr::rand(rng);
+        let nullifier = Fr::rand(rng);
+        let balances = [Fr::from(100), Fr::zero(), Fr::rand(rng)];
+        let identifier = PoseidonHash::tto_crh(&hash, address, blinding)?;
+        let commitment = PoseidonHash::crh(
+            &hash,
+            &[PoseidonHash::crh(&hash, &balances)?, identifier, nullifier],
+        )?;
+        notes.push((
+            OwnedNote {
+                identifier,
+                nullifier,
+                blinding,
+                path: Path::empty(),
+                balances,
+            },
+            commitment,
+        ));
+    }
+    tree.insert_batch(
+        &BTreeMap::from_iter((0..).zip(notes.iter().map(|(_, commitment)| *commitment))),
+        &hash,
+    )?;
+
+    let note_at = |index: usize| OwnedNote {
+        path: tree.generate_membership_proof(index as u64),
+        ..notes[index].0.clone()
+    };
+    for (address, first, second, is_satisfied) in [
+        (owner, 0, 1, true),
+        (owner, 0, 2, false),
+        (other, 0, 2, false),
+    ] {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestSameOwner {
+            utxo_root: tree.root(),
+            address,
+            first_note: note_at(first),
+            second_note: note_at(second),
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+        assert_eq!(
+            cs.is_satisfied()?,
+            is_satisfied,
+            "invalid satisfiability of notes {first} and {second}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn ownership_proof() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+    let (_, mut tree) = TestOwnership::empty(&hash);
+
+    let address = Fr::from_le_bytes_mod_order(b"osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+    let other = Fr::from_le_bytes_mod_order(b"osmo1cyyzpxplxdzkeea7kwsydadg87357qnahakaks");
+    let nullifier = Fr::rand(rng);
+    let note_blinding = Fr::rand(rng);
+    let note_balances = [Fr::from(100), Fr::zero(), Fr::rand(rng)];
+    let note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &note_balances)?,
+            PoseidonHash::tto_crh(&hash, address, note_blinding)?,
+            nullifier,
+        ],
+    )?;
+    tree.insert_batch(&BTreeMap::from([(0, Fr::from(1)), (1, note)]), &hash)?;
+
+    let challenge = Fr::rand(rng);
+    let challenge_tag = PoseidonHash::tto_crh(&hash, nullifier, challenge)?;
+    assert_ne!(
+        challenge_tag,
+        PoseidonHash::tto_crh(&hash, note, nullifier)?,
+        "challenge tag must not be the nullifier hash"
+    );
+
+    for (address, challenge, is_satisfied) in [
+        (address, challenge, true),
+        (address, Fr::rand(rng), false),
+        (other, challenge, false),
+    ] {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestOwnership {
+            utxo_root: tree.root(),
+            address,
+            challenge,
+            challenge_tag,
+            nullifier,
+            note_blinding,
+            note_path: tree.generate_membership_proof(1),
+            note_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+        assert_eq!(
+            cs.is_satisfied()?,
+            is_satisfied,
+            "invalid satisfiability for challenge {challenge}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn asset_bitmap() -> Result<(), Box<dyn Error>> {
+    let rng = &mut test_rng();
+    let hash = poseidon_bn254();
+
+    let address = Fr::from_le_bytes_mod_order(b"osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+    let nullifier = Fr::rand(rng);
+    let note_blinding = Fr::rand(rng);
+    let note_balances = [Fr::from(100), Fr::zero(), Fr::rand(rng)];
+    let note = PoseidonHash::crh(
+        &hash,
+        &[
+            PoseidonHash::crh(&hash, &note_balances)?,
+            PoseidonHash::tto_crh(&hash, address, note_blinding)?,
+            nullifier,
+        ],
+    )?;
+    assert_eq!(asset_bitmap(&note_balances), 0b101);
+
+    for (bitmap, is_satisfied) in [
+        (0b101, true),
+        // An empty asset claimed as held
+        (0b111, false),
+        // A held asset left out
+        (0b001, false),
+        // A bit beyond the assets
+        (0b1101, false),
+    ] {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        TestBitmap {
+            note,
+            bitmap: Fr::from(bitmap),
+            address,
+            nullifier,
+            note_blinding,
+            note_balances,
+            parameters: hash.clone(),
+            _hg: std::marker::PhantomData,
+        }
+        .generate_constraints(cs.clone())?;
+        assert_eq!(
+            cs.is_satisfied()?,
+            is_satisfied,
+            "invalid satisfiability for bitmap {bitmap:#b}"
+        );
+    }
+
+    Ok(())
+}
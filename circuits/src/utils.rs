@@ -2,6 +2,12 @@ use ark_crypto_primitives::sponge::poseidon::PoseidonConfig;
 use ark_ff::PrimeField;
 
 /// Poseidon width = 3 and alpha = 5 for BN254
+///
+/// [`crate::poseidon::PoseidonHash::crh`] absorbs its input into a sponge of rate 2, permuting once
+/// every 2 elements, so these parameters hash any number of elements, such as the `N_ASSETS`
+/// balances of a balance root, in `ceil(n / 2)` permutations. Inputs are only unambiguous for a
+/// fixed number of elements.
+///
 /// Generated from `sage generate_params_poseidon.sage 1 0 254 3 5 128 0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001`
 pub fn poseidon_bn254() -> PoseidonConfig<ark_bn254::Fr> {
     let round_constants = [
@@ -254,8 +260,11 @@ mod tests {
         },
         TwoToOneCRHSchemeGadget,
     };
-    use ark_r1cs_std::{fields::fp::FpVar, prelude::AllocVar};
+    use ark_r1cs_std::{fields::fp::FpVar, prelude::AllocVar, R1CSVar};
     use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::{circuit::gadgets::calculate_balance_root, poseidon::PoseidonHash};
 
     use super::poseidon_bn254;
 
@@ -282,4 +291,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn poseidon_widths() -> Result<(), Box<dyn Error>> {
+        let rng = &mut test_rng();
+
+        for n in [3, 7, 12] {
+            let poseidon = poseidon_bn254();
+            let inputs = (0..n).map(|_| ark_bn254::Fr::rand(rng)).collect::<Vec<_>>();
+            let hash = PoseidonHash::crh(&poseidon, &inputs)?;
+
+            let cs = ConstraintSystem::<ark_bn254::Fr>::new_ref();
+            let input_vars = Vec::<FpVar<_>>::new_witness(cs.clone(), || Ok(inputs.clone()))?;
+            let hash_var = calculate_balance_root::<_, PoseidonHash<_>, PoseidonHash<_>>(
+                &CRHParametersVar {
+                    parameters: poseidon.clone(),
+                },
+                &input_vars,
+            )?;
+            assert_eq!(
+                hash_var.value()?,
+                hash,
+                "gadget mismatch hashing {n} elements"
+            );
+            assert!(cs.is_satisfied()?);
+
+            // Every absorbed element affects the hash
+            let mut changed = inputs.clone();
+            changed[n - 1] += ark_bn254::Fr::from(1);
+            assert_ne!(PoseidonHash::crh(&poseidon, &changed)?, hash);
+        }
+
+        Ok(())
+    }
 }
@@ -1,7 +1,9 @@
 use ark_relations::r1cs::SynthesisError;
 use ark_serialize::SerializationError;
 use base64::DecodeError;
+use circuits::encoding::FieldError;
 use cosmwasm_std::StdError;
+use cw_controllers::AdminError;
 use cw_merkle_tree::MerkleTreeError;
 use thiserror::Error;
 
@@ -10,12 +12,27 @@ pub enum ContractError {
     #[error("CosmWasm: {0}")]
     Std(#[from] StdError),
 
+    #[error("Admin: {0}")]
+    Admin(#[from] AdminError),
+
     #[error("Merkle Tree: {0}")]
     Merkle(#[from] MerkleTreeError),
 
+    #[error("Invalid Verifying Key: {0}")]
+    InvalidVerifyingKey(String),
+
+    #[error("Verifying key has {actual} public inputs instead of {expected}")]
+    VkPublicInputsMismatch { expected: usize, actual: usize },
+
+    #[error("Verifying key was not built for {0} assets")]
+    VkAssetsMismatch(usize),
+
     #[error("Decode Base64: {0}")]
     Decode(#[from] DecodeError),
 
+    #[error("Field element encoding is not canonical")]
+    NonCanonicalField,
+
     #[error("Ark Serialization: {0}")]
     Serialization(String),
 
@@ -25,21 +42,90 @@ pub enum ContractError {
     #[error("Invalid Proof")]
     InvalidProof,
 
+    #[error("At most {0} indices can be queried at once")]
+    TooManyIndices(u64),
+
     #[error("Nullifer is already used")]
     UsedNullifier,
 
+    #[error("At most {0} notes fit in the tree")]
+    TooManyNotes(u64),
+
+    #[error("Note must not be zero")]
+    ZeroNote,
+
+    #[error("Note is already in the tree")]
+    DuplicateNote,
+
+    #[error("Splitting is not supported")]
+    SplitNotSupported,
+
+    #[error("Asset bitmaps are not supported")]
+    AssetBitmapNotSupported,
+
+    #[error("Invalid number of new notes, expected {0}")]
+    InvalidNoteCount(usize),
+
     #[error("Execution time exceed timeout")]
     AlreadyTimeout,
 
     #[error("Unknown Asset Denom {0}")]
     UnknownAsset(String),
 
+    #[error("Invalid Asset Index {0}")]
+    InvalidAssetIndex(usize),
+
+    #[error("Asset Denom {0} is already configured")]
+    DuplicateAsset(String),
+
+    #[error("Asset Denom must not be empty")]
+    EmptyDenom,
+
+    #[error("Contract holds a balance of {denom}")]
+    NonZeroAssetBalance { denom: String },
+
+    #[error("None of the attached funds are of a configured asset")]
+    NoMatchingAssets,
+
+    #[error("Each payout must send non-zero amounts of distinct assets")]
+    InvalidPayout,
+
+    #[error("Deposit of {denom} is below the minimum")]
+    BelowMinimum { denom: String },
+
+    #[error("Insufficient funds attached")]
+    InsufficientFunds,
+
+    #[error("Funds must not be attached to this message")]
+    UnexpectedFunds,
+
+    #[error("Tag is longer than {0} bytes")]
+    TagTooLong(usize),
+
+    #[error("Expected {0} public inputs")]
+    InvalidPublicInputs(usize),
+
     #[error("Invalid Asset Swap Route")]
     InvalidSwapRoute,
 
+    #[error("At most {0} swap routes are supported")]
+    TooManyRoutes(usize),
+
     #[error("Invalid Asset Swap Denom")]
     InvalidSwapDenom,
 
+    #[error("Contract holds less than the swapped amount of {denom}")]
+    InsufficientContractBalance { denom: String },
+
+    #[error("Cannot spend from the empty tree root")]
+    EmptyTree,
+
+    #[error("Proof must be bound to one of the latest {0} blocks")]
+    StaleProof(u64),
+
+    #[error("Proof is bound to the future height {0}")]
+    FutureBoundHeight(u64),
+
     #[error("Invalid UTXO Tree Root")]
     InvalidRoot,
 
@@ -49,10 +135,31 @@ pub enum ContractError {
     #[error("Only callable by this contract")]
     NotContract,
 
+    #[error("No pending swap to transfer excess from")]
+    NothingToTransfer,
+
+    #[error("Nothing to claim")]
+    NothingToClaim,
+
+    #[error("Swap Failed: {0}")]
+    SwapFailed(String),
+
+    #[error("Unknown Reply Id {0}")]
+    UnknownReplyId(u64),
+
     #[error("{0}")]
     Custom(String),
 }
 
+impl From<FieldError> for ContractError {
+    fn from(value: FieldError) -> Self {
+        match value {
+            FieldError::Decode(e) => Self::Decode(e),
+            FieldError::NonCanonical => Self::NonCanonicalField,
+        }
+    }
+}
+
 impl From<SerializationError> for ContractError {
     fn from(value: SerializationError) -> Self {
         Self::Serialization(value.to_string())
@@ -1,16 +1,18 @@
 use ark_bn254::Fr;
 use ark_crypto_primitives::sponge::poseidon::PoseidonConfig;
 use ark_ff::{BigInteger, PrimeField};
-use circuits::poseidon::PoseidonHash;
+use circuits::{encoding::decode_field, poseidon::PoseidonHash};
 use cw_merkle_tree::{Hasher, HasherError};
 
+use crate::error::ContractError;
+
 #[derive(Debug, Clone)]
 pub struct PoseidonHasher<'a>(pub &'a PoseidonConfig<Fr>);
 
 impl<'a> Hasher<String> for PoseidonHasher<'a> {
     fn hash_two(&self, left: &String, right: &String) -> Result<String, HasherError> {
         let hashed = PoseidonHash::tto_crh(
-            &self.0,
+            self.0,
             Fr::from_le_bytes_mod_order(
                 &base64::decode(left).map_err(|_| HasherError::custom("left hash decode error"))?,
             ),
@@ -23,3 +25,22 @@ impl<'a> Hasher<String> for PoseidonHasher<'a> {
         Ok(base64::encode(hashed.into_bigint().to_bytes_le()))
     }
 }
+
+impl<'a> PoseidonHasher<'a> {
+    /// Hashes any number of base64 encoded field elements into a single one.
+    pub fn hash_many(&self, inputs: &[String]) -> Result<String, ContractError> {
+        let inputs = inputs
+            .iter()
+            .map(|input| decode_field(input))
+            .collect::<Result<Vec<Fr>, _>>()?;
+        let hashed = PoseidonHash::crh(self.0, &inputs)?;
+        Ok(base64::encode(hashed.into_bigint().to_bytes_le()))
+    }
+
+    /// Computes the nullifier hash `H_tto_crh(note, nullifier)` revealed when spending the base64
+    /// encoded `note` of `nullifier`, rejecting non-canonical encodings of either.
+    pub fn nullifier_hash(&self, note: &str, nullifier: &str) -> Result<String, ContractError> {
+        let hashed = PoseidonHash::tto_crh(self.0, decode_field(note)?, decode_field(nullifier)?)?;
+        Ok(base64::encode(hashed.into_bigint().to_bytes_le()))
+    }
+}
@@ -14,27 +14,61 @@ use std::{
 };
 
 use ark_bn254::{Bn254, Fr};
-use ark_crypto_primitives::snark::SNARK;
-use ark_ff::{BigInteger, PrimeField, ToConstraintField};
+use ark_crypto_primitives::{snark::SNARK, sponge::poseidon::PoseidonConfig};
+use ark_ff::{BigInteger, PrimeField};
 use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, Proof, VerifyingKey};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::Zero;
-use circuits::{poseidon::PoseidonHash, utils::poseidon_bn254, TREE_DEPTH};
+use circuits::{
+    encoding::{self, decode_field, MAX_ROUTES},
+    poseidon::PoseidonHash,
+    utils::poseidon_bn254,
+    N_ASSETS, TREE_DEPTH,
+};
 use cosmwasm_std::{
-    entry_point, to_binary, to_vec, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order,
-    QueryResponse, Response, Uint128, WasmMsg,
+    entry_point, to_binary, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, QueryResponse,
+    Reply, Response, StdError, Storage, SubMsg, SubMsgResult, Uint128, WasmMsg,
 };
 use cw_merkle_tree::MerkleTree;
 use cw_storage_plus::Bound;
 use error::ContractError;
 use hasher::PoseidonHasher;
-use msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, NotesResponse, QueryMsg};
-use state::{ADMIN, ASSETS, LATEST_SWAP, MAIN_CIRCUIT_VK, NULLIFIER, TREE};
+use msg::{
+    AccountingDriftResponse, AssetAccounting, AssetBitmap, ConfigResponse, ExecuteMsg,
+    HealthResponse, IndexedNotesResponse, InstantiateMsg, MigrateMsg, NotesPageResponse,
+    NotesResponse, QueryMsg, SpentNullifiersResponse,
+};
+use osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountIn;
+use state::{
+    ACCOUNTED_TOTAL, ADMIN, ALL_ROOTS, ASSETS, ASSET_BITMAP_CIRCUIT_VK, CLAIMABLE, DECIMALS,
+    LAST_PROOF, LATEST_SWAP, MAIN_CIRCUIT_VK, MAIN_CIRCUIT_VK_COMPRESSED, MAX_PROOF_AGE,
+    MIN_DEPOSIT, NOTE_EXISTS, NULLIFIER, PENDING_EXCESS, ROOT_HISTORY, SPLIT_CIRCUIT_VK,
+    STORE_PROOFS, TRACK_ALL_ROOTS, TREE, UNIQUE_NOTES,
+};
+
+/// Reply id of the osmosis swap submessage dispatched by [`ExecuteMsg::Swap`].
+pub const SWAP_REPLY_ID: u64 = 1;
+
+/// Reply id of the excess transfer submessage dispatched by [`ExecuteMsg::TransferExcess`].
+pub const TRANSFER_EXCESS_REPLY_ID: u64 = 2;
+
+/// Number of public inputs of the main circuit, `[aux, root, diff_balance_root, nullifier_hash,
+/// identifier, new_note]`.
+pub const MAIN_CIRCUIT_PUBLIC_INPUTS: usize = 6;
+
+/// Maximum length in bytes of a deposit tag.
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// Default and maximum number of items returned by paginated queries.
+pub const QUERY_LIMIT: u64 = 100;
+
+/// Recommended page size of [`QueryMsg::NotesPage`], keeping pages well within query gas limits.
+pub const NOTES_PAGE_SIZE: u64 = 50;
 
 #[entry_point]
 pub fn instantiate(
     mut deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
@@ -42,7 +76,67 @@ pub fn instantiate(
 
     ADMIN.set(deps.branch(), Some(info.sender))?;
     ASSETS.save(deps.storage, &msg.assets)?;
-    MAIN_CIRCUIT_VK.save(deps.storage, &base64::decode(msg.main_circuit_vk)?)?;
+    if let Some(decimals) = msg.decimals {
+        DECIMALS.save(deps.storage, &decimals)?;
+    }
+    let main_circuit_vk = base64::decode(msg.main_circuit_vk)?;
+    let vk = validate_vk(&main_circuit_vk)?;
+    (vk.gamma_abc_g1.len() == MAIN_CIRCUIT_PUBLIC_INPUTS + 1)
+        .then_some(())
+        .ok_or(ContractError::VkPublicInputsMismatch {
+            expected: MAIN_CIRCUIT_PUBLIC_INPUTS,
+            actual: vk.gamma_abc_g1.len().saturating_sub(1),
+        })?;
+    if let Some(check) = msg.main_circuit_vk_check {
+        let proof = Proof::deserialize_compressed_unchecked(&base64::decode(&check.proof)?[..])?;
+        Groth16::<Bn254, LibsnarkReduction>::verify(
+            &vk,
+            &[
+                Fr::zero(),
+                Fr::zero(),
+                PoseidonHash::crh(&hasher, &[Fr::zero(); N_ASSETS])?,
+                Fr::zero(),
+                Fr::zero(),
+                decode_field(&check.new_note)?,
+            ],
+            &proof,
+        )?
+        .then_some(())
+        .ok_or(ContractError::VkAssetsMismatch(N_ASSETS))?;
+    }
+    let compress_vk = msg.compress_vk.unwrap_or_default();
+    MAIN_CIRCUIT_VK_COMPRESSED.save(deps.storage, &compress_vk)?;
+    if compress_vk {
+        let mut bytes = vec![];
+        vk.serialize_compressed(&mut bytes)
+            .expect("failed to serialize");
+        MAIN_CIRCUIT_VK.save(deps.storage, &bytes)?;
+    } else {
+        MAIN_CIRCUIT_VK.save(deps.storage, &main_circuit_vk)?;
+    }
+    UNIQUE_NOTES.save(deps.storage, &msg.unique_notes.unwrap_or_default())?;
+    STORE_PROOFS.save(deps.storage, &msg.store_proofs.unwrap_or_default())?;
+    TRACK_ALL_ROOTS.save(deps.storage, &msg.track_all_roots.unwrap_or_default())?;
+    if let Some(max_proof_age) = msg.max_proof_age {
+        MAX_PROOF_AGE.save(deps.storage, &max_proof_age)?;
+    }
+    for (denom, amount) in msg.min_deposits.unwrap_or_default() {
+        msg.assets
+            .contains(&denom)
+            .then_some(())
+            .ok_or_else(|| ContractError::UnknownAsset(denom.clone()))?;
+        MIN_DEPOSIT.save(deps.storage, &denom, &amount)?;
+    }
+    if let Some(split_circuit_vk) = msg.split_circuit_vk {
+        let split_circuit_vk = base64::decode(split_circuit_vk)?;
+        validate_vk(&split_circuit_vk)?;
+        SPLIT_CIRCUIT_VK.save(deps.storage, &split_circuit_vk)?;
+    }
+    if let Some(asset_bitmap_circuit_vk) = msg.asset_bitmap_circuit_vk {
+        let asset_bitmap_circuit_vk = base64::decode(asset_bitmap_circuit_vk)?;
+        validate_vk(&asset_bitmap_circuit_vk)?;
+        ASSET_BITMAP_CIRCUIT_VK.save(deps.storage, &asset_bitmap_circuit_vk)?;
+    }
 
     let mut bytes = vec![];
     Fr::zero()
@@ -56,12 +150,27 @@ pub fn instantiate(
         &PoseidonHasher(&hasher),
     )?;
 
+    record_root(
+        deps.storage,
+        env.block.height,
+        &TREE.get_latest_root(deps.storage)?,
+    )?;
+
+    let genesis_notes = msg.genesis_notes.unwrap_or_default();
+    (genesis_notes.len() as u64 <= 1 << TREE_DEPTH)
+        .then_some(())
+        .ok_or(ContractError::TooManyNotes(1 << TREE_DEPTH))?;
+    for note in genesis_notes {
+        insert_note(deps.storage, env.block.height, &note, &hasher)?;
+    }
+    seed_accounted_totals(deps, &env)?;
+
     Ok(Response::new())
 }
 
 #[entry_point]
 pub fn execute(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
@@ -73,18 +182,127 @@ pub fn execute(
             identifier,
             new_note,
             proof,
+            tag,
+            bound_height,
+            asset_bitmap,
+        } => {
+            if let Some(tag) = &tag {
+                (tag.len() <= MAX_TAG_LENGTH)
+                    .then_some(())
+                    .ok_or(ContractError::TagTooLong(MAX_TAG_LENGTH))?;
+            }
+
+            if let Some(asset_bitmap) = &asset_bitmap {
+                verify_asset_bitmap(deps.storage, &new_note, asset_bitmap)?;
+            }
+
+            let aux = deposit_aux(deps.storage, &env, bound_height)?;
+            let (index, new_root, evicted_root) = deposit(
+                deps,
+                env.block.height,
+                aux,
+                &root,
+                &nullifier_hash,
+                &identifier,
+                &new_note,
+                &proof,
+                &info.funds,
+            )?;
+
+            Ok(Response::new()
+                .add_attributes([
+                    ("index", &index.to_string()),
+                    ("new_root", &new_root),
+                    ("leaf", &new_note),
+                ])
+                .add_attributes(evicted_root.map(|r| ("evicted_root", r)))
+                .add_attributes(tag.map(|t| ("tag", t)))
+                .add_attributes(asset_bitmap.map(|b| ("asset_bitmap", b.bitmap.to_string()))))
+        }
+        ExecuteMsg::CatchUpDeposit {
+            intervening,
+            root,
+            nullifier_hash,
+            identifier,
+            new_note,
+            proof,
+            bound_height,
+        } => {
+            let mut response = Response::new();
+            let mut remaining_funds = info.funds;
+            for deposited in intervening {
+                remaining_funds = subtract_funds(remaining_funds, &deposited.funds)?;
+                let aux = deposit_aux(deps.storage, &env, deposited.bound_height)?;
+                let (index, new_root, evicted_root) = deposit(
+                    deps.branch(),
+                    env.block.height,
+                    aux,
+                    &deposited.root,
+                    &deposited.nullifier_hash,
+                    &deposited.identifier,
+                    &deposited.new_note,
+                    &deposited.proof,
+                    &deposited.funds,
+                )?;
+                response = response
+                    .add_attributes([
+                        ("index", &index.to_string()),
+                        ("new_root", &new_root),
+                        ("leaf", &deposited.new_note),
+                    ])
+                    .add_attributes(evicted_root.map(|r| ("evicted_root", r)));
+            }
+
+            let aux = deposit_aux(deps.storage, &env, bound_height)?;
+            let (index, new_root, evicted_root) = deposit(
+                deps,
+                env.block.height,
+                aux,
+                &root,
+                &nullifier_hash,
+                &identifier,
+                &new_note,
+                &proof,
+                &remaining_funds,
+            )?;
+
+            Ok(response
+                .add_attributes([
+                    ("index", &index.to_string()),
+                    ("new_root", &new_root),
+                    ("leaf", &new_note),
+                ])
+                .add_attributes(evicted_root.map(|r| ("evicted_root", r))))
+        }
+        ExecuteMsg::Split {
+            root,
+            nullifier_hash,
+            identifier,
+            new_notes,
+            proof,
         } => {
             let assets = ASSETS.load(deps.storage)?;
             let hasher = poseidon_bn254();
             let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(
-                &MAIN_CIRCUIT_VK.load(deps.storage)?[..],
+                &SPLIT_CIRCUIT_VK
+                    .may_load(deps.storage)?
+                    .ok_or(ContractError::SplitNotSupported)?[..],
             )?;
+
+            // Public inputs are [aux, root, diff_balance_root, nullifier_hash, identifier] and
+            // then one per new note
+            let n_notes = vk.gamma_abc_g1.len().saturating_sub(6);
+            (new_notes.len() == n_notes)
+                .then_some(())
+                .ok_or(ContractError::InvalidNoteCount(n_notes))?;
+
+            store_proof(deps.storage, "split", &proof)?;
             let proof = Proof::deserialize_compressed_unchecked(&base64::decode(&proof)?[..])?;
-            let nullifier_hash = Fr::from_le_bytes_mod_order(&base64::decode(&nullifier_hash)?);
+            let nullifier_hash = decode_field::<Fr>(&nullifier_hash)?;
 
-            let tree_root = Fr::from_le_bytes_mod_order(&base64::decode(&root)?);
+            let tree_root = decode_field::<Fr>(&root)?;
             if tree_root != Fr::zero() {
-                let tree_root_normalized = base64::encode(&tree_root.into_bigint().to_bytes_le());
+                let tree_root_normalized = base64::encode(tree_root.into_bigint().to_bytes_le());
                 TREE.is_valid_root(deps.storage, &tree_root_normalized)?
                     .then_some(())
                     .ok_or(ContractError::InvalidRoot)?;
@@ -100,44 +318,41 @@ pub fn execute(
                 NULLIFIER.save(deps.storage, &nullifier_normalized, &())?;
             }
 
-            let funds_map =
-                BTreeMap::from_iter(info.funds.into_iter().map(|e| (e.denom, e.amount)));
-            let diff_balance_root = PoseidonHash::crh(
-                &hasher,
-                &assets
-                    .iter()
-                    .map(|a| {
-                        funds_map
-                            .get(a)
-                            .map(|f| Fr::from(f.u128()))
-                            .unwrap_or_default()
-                    })
-                    .collect::<Vec<_>>(),
-            )?;
+            check_funds_assets(&assets, &info.funds)?;
+            check_min_deposit(deps.storage, &info.funds)?;
+            let diff_balance_root = funds_diff_balance_root(&hasher, &assets, &info.funds)?;
 
-            let is_valid = Groth16::<Bn254, LibsnarkReduction>::verify(
-                &vk,
-                &[
-                    Fr::zero(),
-                    tree_root,
-                    diff_balance_root,
-                    nullifier_hash,
-                    Fr::from_le_bytes_mod_order(&base64::decode(&identifier)?),
-                    Fr::from_le_bytes_mod_order(&base64::decode(&new_note)?),
-                ],
-                &proof,
-            )?;
-
-            let (index, new_root) =
-                TREE.insert(deps.storage, new_note.to_string(), &PoseidonHasher(&hasher))?;
+            let mut public_inputs = vec![
+                Fr::zero(),
+                tree_root,
+                diff_balance_root,
+                nullifier_hash,
+                decode_field(&identifier)?,
+            ];
+            for new_note in &new_notes {
+                public_inputs.push(decode_field(new_note)?);
+            }
+            Groth16::<Bn254, LibsnarkReduction>::verify(&vk, &public_inputs, &proof)?
+                .then_some(())
+                .ok_or(ContractError::InvalidProof)?;
+            for coin in &info.funds {
+                update_accounted_total(deps.storage, &coin.denom, coin.amount, true)?;
+            }
 
-            is_valid.then_some(()).ok_or(ContractError::InvalidProof)?;
+            let mut response = Response::new();
+            for new_note in new_notes {
+                let (index, new_root, evicted_root) =
+                    insert_note(deps.storage, env.block.height, &new_note, &hasher)?;
+                response = response
+                    .add_attributes([
+                        ("index", index.to_string()),
+                        ("new_root", new_root),
+                        ("leaf", new_note),
+                    ])
+                    .add_attributes(evicted_root.map(|r| ("evicted_root", r)));
+            }
 
-            Ok(Response::new().add_attributes([
-                ("index", &index.to_string()),
-                ("new_root", &new_root),
-                ("leaf", &new_note),
-            ]))
+            Ok(response)
         }
         ExecuteMsg::Swap {
             mut swap_argument,
@@ -148,22 +363,10 @@ pub fn execute(
             proof,
             timeout,
         } => {
-            let hasher = poseidon_bn254();
+            check_no_funds(&info.funds)?;
 
-            // Normalize swap argument and then calculate aux
-            swap_argument.sender = String::new();
-            let aux = (&to_vec(&swap_argument)
-                .expect("Failed to serialize swap args")
-                .into_iter()
-                .chain(
-                    to_vec(&timeout)
-                        .expect("Failed to serialize timeout")
-                        .into_iter(),
-                )
-                .collect::<Vec<_>>())
-                .to_field_elements()
-                .and_then(|e| PoseidonHash::crh(&hasher, &e).ok())
-                .expect("Failed to hash aux");
+            let hasher = poseidon_bn254();
+            let aux = swap_aux(&hasher, &swap_argument, timeout)?;
 
             if let Some(timeout) = timeout {
                 (env.block.time.seconds() <= timeout)
@@ -171,27 +374,48 @@ pub fn execute(
                     .ok_or(ContractError::AlreadyTimeout)?;
             }
 
+            swap_argument
+                .routes
+                .iter()
+                .all(|r| r.pool_id != 0 && !r.token_out_denom.is_empty())
+                .then_some(())
+                .ok_or(ContractError::InvalidSwapRoute)?;
+
+            // Denoms only differing in case are treated as the same asset, so the in and out
+            // denoms are replaced by their listed form once for every lookup and the swap itself
             let assets = ASSETS.load(deps.storage)?;
+            let listed = |denom: &mut String| {
+                if let Some(asset) = assets.iter().find(|a| a.eq_ignore_ascii_case(denom)) {
+                    denom.clone_from(asset);
+                }
+            };
             let in_asset = swap_argument
                 .token_in
-                .as_ref()
+                .as_mut()
                 .ok_or(ContractError::InvalidSwapRoute)?;
-            let in_denom = &in_asset.denom;
+            listed(&mut in_asset.denom);
+            let in_denom = in_asset.denom.clone();
             let in_amount = Uint128::from_str(&in_asset.amount)?;
-            let out_denom = &swap_argument
+            let out_route = swap_argument
                 .routes
-                .last()
-                .ok_or(ContractError::InvalidSwapRoute)?
-                .token_out_denom;
+                .last_mut()
+                .ok_or(ContractError::InvalidSwapRoute)?;
+            listed(&mut out_route.token_out_denom);
+            let out_denom = out_route.token_out_denom.clone();
             let out_amount = Uint128::from_str(&swap_argument.token_out_min_amount)?;
             let funds_map = BTreeMap::from_iter([
-                (in_denom, Fr::from(in_amount.u128()).neg()),
-                (out_denom, Fr::from(out_amount.u128())),
+                (&in_denom, Fr::from(in_amount.u128()).neg()),
+                (&out_denom, Fr::from(out_amount.u128())),
             ]);
 
             (in_denom != out_denom)
                 .then_some(())
                 .ok_or(ContractError::InvalidSwapDenom)?;
+            // An unlisted input would be dropped from the diff balances
+            assets
+                .contains(&in_denom)
+                .then_some(())
+                .ok_or_else(|| ContractError::UnknownAsset(in_denom.clone()))?;
 
             let diff_balance_root = PoseidonHash::crh(
                 &hasher,
@@ -201,71 +425,81 @@ pub fn execute(
                     .collect::<Vec<_>>(),
             )?;
 
-            let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(
-                &MAIN_CIRCUIT_VK.load(deps.storage)?[..],
-            )?;
+            let vk = load_main_vk(deps.storage)?;
+            store_proof(deps.storage, "swap", &proof)?;
             let proof = Proof::deserialize_compressed_unchecked(&base64::decode(&proof)?[..])?;
-            let nullifier_hash = Fr::from_le_bytes_mod_order(&base64::decode(&nullifier_hash)?);
+            let nullifier_hash = decode_field::<Fr>(&nullifier_hash)?;
             let nullifier_normalized = nullifier_hash.into_bigint().to_bytes_le();
             NULLIFIER
                 .has(deps.storage, &nullifier_normalized)
                 .not()
                 .then_some(())
                 .ok_or(ContractError::UsedNullifier)?;
-            NULLIFIER.save(deps.storage, &nullifier_normalized, &())?;
 
-            let tree_root = Fr::from_le_bytes_mod_order(&base64::decode(&root)?);
-            let tree_root_normalized = base64::encode(&tree_root.into_bigint().to_bytes_le());
-            TREE.is_valid_root(deps.storage, &tree_root_normalized)?
+            let tree_root = decode_field::<Fr>(&root)?;
+            check_spent_root(deps.storage, &tree_root)?;
+
+            // The swap would otherwise fail opaquely on the chain if custody drifted
+            (deps
+                .querier
+                .query_balance(&env.contract.address, &in_denom)?
+                .amount
+                >= in_amount)
                 .then_some(())
-                .ok_or(ContractError::InvalidRoot)?;
+                .ok_or_else(|| ContractError::InsufficientContractBalance {
+                    denom: in_denom.clone(),
+                })?;
 
-            let is_valid = Groth16::<Bn254, LibsnarkReduction>::verify(
+            // Nothing is written to the tree or the pending swap before the proof is checked
+            Groth16::<Bn254, LibsnarkReduction>::verify(
                 &vk,
                 &[
                     aux,
                     tree_root,
                     diff_balance_root,
                     nullifier_hash,
-                    Fr::from_le_bytes_mod_order(&base64::decode(&identifier)?),
-                    Fr::from_le_bytes_mod_order(&base64::decode(&new_note)?),
+                    decode_field(&identifier)?,
+                    decode_field(&new_note)?,
                 ],
                 &proof,
-            )?;
+            )?
+            .then_some(())
+            .ok_or(ContractError::InvalidProof)?;
+            NULLIFIER.save(deps.storage, &nullifier_normalized, &())?;
 
-            let (index, new_root) =
-                TREE.insert(deps.storage, new_note.to_string(), &PoseidonHasher(&hasher))?;
+            let (index, new_root, evicted_root) =
+                insert_note(deps.storage, env.block.height, &new_note, &hasher)?;
 
-            is_valid.then_some(()).ok_or(ContractError::InvalidProof)?;
+            // The excess above `out_amount` is refunded, so it never backs the note
+            update_accounted_total(deps.storage, &in_denom, in_amount, false)?;
+            update_accounted_total(deps.storage, &out_denom, out_amount, true)?;
 
             // Save latest swap for excess coin transfer
             LATEST_SWAP.save(
                 deps.storage,
                 &(
                     deps.querier
-                        .query_balance(&env.contract.address, out_denom)?,
+                        .query_balance(&env.contract.address, &out_denom)?,
                     out_amount,
                     info.sender,
                 ),
             )?;
 
+            // Excess transfer is only scheduled from the reply once the swap succeeded
             Ok(Response::new()
-                .add_message(
-                    osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountIn {
+                .add_submessage(SubMsg::reply_always(
+                    MsgSwapExactAmountIn {
                         sender: env.contract.address.to_string(),
                         ..swap_argument
                     },
-                )
-                .add_message(WasmMsg::Execute {
-                    contract_addr: env.contract.address.to_string(),
-                    msg: to_binary(&ExecuteMsg::TransferExcess {})?,
-                    funds: vec![],
-                })
+                    SWAP_REPLY_ID,
+                ))
                 .add_attributes([
                     ("index", &index.to_string()),
                     ("new_root", &new_root),
                     ("leaf", &new_note),
-                ]))
+                ])
+                .add_attributes(evicted_root.map(|r| ("evicted_root", r))))
         }
         ExecuteMsg::Withdraw {
             assets: withdrawn_assets,
@@ -275,73 +509,30 @@ pub fn execute(
             new_note,
             proof,
         } => {
-            let assets = ASSETS.load(deps.storage)?;
-            let hasher = poseidon_bn254();
-            let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(
-                &MAIN_CIRCUIT_VK.load(deps.storage)?[..],
-            )?;
-            let proof = Proof::deserialize_compressed_unchecked(&base64::decode(&proof)?[..])?;
-            let nullifier_hash = Fr::from_le_bytes_mod_order(&base64::decode(&nullifier_hash)?);
+            check_no_funds(&info.funds)?;
 
-            let nullifier_normalized = nullifier_hash.into_bigint().to_bytes_le();
-            NULLIFIER
-                .has(deps.storage, &nullifier_normalized)
-                .not()
-                .then_some(())
-                .ok_or(ContractError::UsedNullifier)?;
-            NULLIFIER.save(deps.storage, &nullifier_normalized, &())?;
-
-            let tree_root = Fr::from_le_bytes_mod_order(&base64::decode(&root)?);
-            let tree_root_normalized = base64::encode(&tree_root.into_bigint().to_bytes_le());
-            TREE.is_valid_root(deps.storage, &tree_root_normalized)?
-                .then_some(())
-                .ok_or(ContractError::InvalidRoot)?;
-
-            let diff_balance_root = PoseidonHash::crh(
-                &hasher,
-                &assets
-                    .iter()
-                    .map(|a| {
-                        withdrawn_assets
-                            .get(a)
-                            .map(|f| Fr::from(f.u128()).neg())
-                            .unwrap_or_default()
-                    })
-                    .collect::<Vec<_>>(),
-            )?;
-            let blinding = Fr::from_le_bytes_mod_order(&base64::decode(&blinding)?);
-            let address = Fr::from_le_bytes_mod_order(info.sender.as_bytes());
-            let identifier = PoseidonHash::tto_crh(&hasher, address, blinding)?;
-
-            let is_valid = Groth16::<Bn254, LibsnarkReduction>::verify(
-                &vk,
-                &[
-                    Fr::zero(),
-                    tree_root,
-                    diff_balance_root,
-                    nullifier_hash,
-                    identifier,
-                    Fr::from_le_bytes_mod_order(&base64::decode(&new_note)?),
-                ],
+            let assets = ASSETS.load(deps.storage)?;
+            let (index, new_root, evicted_root) = withdraw(
+                deps.storage,
+                env.block.height,
+                info.sender.as_str(),
+                &withdrawn_assets,
+                &root,
+                &nullifier_hash,
+                &blinding,
+                &new_note,
                 &proof,
             )?;
 
-            let (index, new_root) =
-                TREE.insert(deps.storage, new_note.to_string(), &PoseidonHasher(&hasher))?;
-
-            is_valid.then_some(()).ok_or(ContractError::InvalidProof)?;
-
             Ok(Response::new()
                 .add_message(BankMsg::Send {
                     to_address: info.sender.to_string(),
                     amount: assets
                         .into_iter()
                         .filter_map(|a| {
-                            withdrawn_assets.get(&a).and_then(|v| {
-                                Some(Coin {
-                                    denom: a,
-                                    amount: *v,
-                                })
+                            withdrawn_assets.get(&a).map(|v| Coin {
+                                denom: a,
+                                amount: *v,
                             })
                         })
                         .collect(),
@@ -350,14 +541,77 @@ pub fn execute(
                     ("index", &index.to_string()),
                     ("new_root", &new_root),
                     ("leaf", &new_note),
-                ]))
+                ])
+                .add_attributes(evicted_root.map(|r| ("evicted_root", r))))
+        }
+        ExecuteMsg::WithdrawSplit {
+            payouts,
+            root,
+            nullifier_hash,
+            blinding,
+            new_note,
+            proof,
+        } => {
+            check_no_funds(&info.funds)?;
+
+            let assets = ASSETS.load(deps.storage)?;
+            let mut withdrawn_assets = BTreeMap::<String, Uint128>::new();
+            let mut messages = vec![];
+            for (recipient, coins) in payouts {
+                let recipient = deps.api.addr_validate(&recipient)?;
+                let mut denoms = coins.iter().map(|c| c.denom.as_str()).collect::<Vec<_>>();
+                denoms.sort_unstable();
+                denoms.dedup();
+                (!coins.is_empty()
+                    && denoms.len() == coins.len()
+                    && coins.iter().all(|c| !c.amount.is_zero()))
+                .then_some(())
+                .ok_or(ContractError::InvalidPayout)?;
+
+                for coin in &coins {
+                    assets
+                        .contains(&coin.denom)
+                        .then_some(())
+                        .ok_or_else(|| ContractError::UnknownAsset(coin.denom.clone()))?;
+                    let total = withdrawn_assets.entry(coin.denom.clone()).or_default();
+                    *total = total.checked_add(coin.amount).map_err(StdError::from)?;
+                }
+                messages.push(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: coins,
+                });
+            }
+
+            let (index, new_root, evicted_root) = withdraw(
+                deps.storage,
+                env.block.height,
+                info.sender.as_str(),
+                &withdrawn_assets,
+                &root,
+                &nullifier_hash,
+                &blinding,
+                &new_note,
+                &proof,
+            )?;
+
+            Ok(Response::new()
+                .add_messages(messages)
+                .add_attributes([
+                    ("index", &index.to_string()),
+                    ("new_root", &new_root),
+                    ("leaf", &new_note),
+                ])
+                .add_attributes(evicted_root.map(|r| ("evicted_root", r))))
         }
         ExecuteMsg::TransferExcess {} => {
             (info.sender == env.contract.address)
                 .then_some(())
                 .ok_or(ContractError::NotContract)?;
 
-            let (balance, amount, recipient) = LATEST_SWAP.load(deps.storage)?;
+            // Only set by the swap currently being executed, cleared here or on a failed swap
+            let (balance, amount, recipient) = LATEST_SWAP
+                .may_load(deps.storage)?
+                .ok_or(ContractError::NothingToTransfer)?;
             let current_balance = deps
                 .querier
                 .query_balance(&env.contract.address, &balance.denom)?;
@@ -365,42 +619,170 @@ pub fn execute(
 
             let min_balance = balance.amount + amount;
             match current_balance.amount.cmp(&min_balance) {
-                Ordering::Greater => Ok(Response::new().add_message(BankMsg::Send {
-                    to_address: recipient.to_string(),
-                    amount: vec![Coin {
+                Ordering::Greater => {
+                    let excess = Coin {
                         amount: current_balance.amount - min_balance,
                         denom: balance.denom,
-                    }],
-                })),
+                    };
+                    PENDING_EXCESS.save(deps.storage, &(recipient.clone(), excess.clone()))?;
+
+                    // A rejected transfer is kept claimable instead of reverting the swap, the
+                    // pending excess being cleared by the reply either way
+                    Ok(Response::new().add_submessage(SubMsg::reply_always(
+                        BankMsg::Send {
+                            to_address: recipient.to_string(),
+                            amount: vec![excess],
+                        },
+                        TRANSFER_EXCESS_REPLY_ID,
+                    )))
+                }
                 Ordering::Equal => Ok(Response::new()),
                 Ordering::Less => Err(ContractError::MinimumSwapBalanceNotMet),
             }
         }
+        ExecuteMsg::Claim {} => {
+            let claimable = CLAIMABLE
+                .prefix(&info.sender)
+                .range(deps.storage, None, None, Order::Ascending)
+                .collect::<Result<Vec<_>, _>>()?;
+            (!claimable.is_empty())
+                .then_some(())
+                .ok_or(ContractError::NothingToClaim)?;
+            for (denom, _) in &claimable {
+                CLAIMABLE.remove(deps.storage, (&info.sender, denom));
+            }
+
+            Ok(Response::new().add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: claimable
+                    .into_iter()
+                    .map(|(denom, amount)| Coin { denom, amount })
+                    .collect(),
+            }))
+        }
+        ExecuteMsg::UpdateAdmin { new_admin } => {
+            let new_admin = new_admin
+                .map(|admin| deps.api.addr_validate(&admin))
+                .transpose()?;
+            Ok(ADMIN.execute_update_admin(deps, info, new_admin)?)
+        }
+        ExecuteMsg::RenameAsset { index, new_denom } => {
+            ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+            check_no_funds(&info.funds)?;
+
+            (!new_denom.is_empty())
+                .then_some(())
+                .ok_or(ContractError::EmptyDenom)?;
+            // Swaps resolve denoms case-insensitively, so no two slots may differ only in case
+            let mut assets = ASSETS.load(deps.storage)?;
+            (!assets
+                .iter()
+                .any(|denom| denom.eq_ignore_ascii_case(&new_denom)))
+            .then_some(())
+            .ok_or_else(|| ContractError::DuplicateAsset(new_denom.clone()))?;
+            let old_denom = assets
+                .get(index)
+                .cloned()
+                .ok_or(ContractError::InvalidAssetIndex(index))?;
+            for denom in [&old_denom, &new_denom] {
+                deps.querier
+                    .query_balance(&env.contract.address, denom)?
+                    .amount
+                    .is_zero()
+                    .then_some(())
+                    .ok_or_else(|| ContractError::NonZeroAssetBalance {
+                        denom: denom.clone(),
+                    })?;
+            }
+
+            assets[index] = new_denom.clone();
+            ASSETS.save(deps.storage, &assets)?;
+            if let Some(min_amount) = MIN_DEPOSIT.may_load(deps.storage, &old_denom)? {
+                MIN_DEPOSIT.remove(deps.storage, &old_denom);
+                MIN_DEPOSIT.save(deps.storage, &new_denom, &min_amount)?;
+            }
+
+            Ok(
+                Response::new()
+                    .add_attributes([("old_denom", old_denom), ("new_denom", new_denom)]),
+            )
+        }
+    }
+}
+
+#[entry_point]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        SWAP_REPLY_ID => match msg.result {
+            SubMsgResult::Ok(_) => Ok(Response::new().add_message(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                msg: to_binary(&ExecuteMsg::TransferExcess {})?,
+                funds: vec![],
+            })),
+            SubMsgResult::Err(err) => {
+                LATEST_SWAP.remove(deps.storage);
+                Err(ContractError::SwapFailed(err))
+            }
+        },
+        TRANSFER_EXCESS_REPLY_ID => {
+            let (recipient, excess) = PENDING_EXCESS.load(deps.storage)?;
+            PENDING_EXCESS.remove(deps.storage);
+            match msg.result {
+                SubMsgResult::Ok(_) => Ok(Response::new()),
+                SubMsgResult::Err(err) => {
+                    CLAIMABLE.update(
+                        deps.storage,
+                        (&recipient, &excess.denom),
+                        |claimable| -> Result<_, ContractError> {
+                            Ok(claimable.unwrap_or_default() + excess.amount)
+                        },
+                    )?;
+
+                    Ok(Response::new().add_attributes([
+                        ("claimable_recipient", recipient.as_str()),
+                        ("claimable", &excess.to_string()),
+                        ("transfer_error", &err),
+                    ]))
+                }
+            }
+        }
+        id => Err(ContractError::UnknownReplyId(id)),
     }
 }
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<QueryResponse, ContractError> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<QueryResponse, ContractError> {
     match msg {
         QueryMsg::Admin {} => Ok(to_binary(&ADMIN.get(deps)?)?),
         QueryMsg::Assets {} => Ok(to_binary(&ASSETS.load(deps.storage)?)?),
+        QueryMsg::Config {} => Ok(to_binary(&ConfigResponse {
+            assets: ASSETS.load(deps.storage)?,
+            decimals: DECIMALS.may_load(deps.storage)?,
+            unique_notes: UNIQUE_NOTES.may_load(deps.storage)?.unwrap_or_default(),
+            store_proofs: STORE_PROOFS.may_load(deps.storage)?.unwrap_or_default(),
+            max_proof_age: MAX_PROOF_AGE.may_load(deps.storage)?,
+            track_all_roots: TRACK_ALL_ROOTS.may_load(deps.storage)?.unwrap_or_default(),
+        })?),
+        QueryMsg::Health {} => Ok(to_binary(&HealthResponse {
+            admin_set: ADMIN.get(deps)?.is_some(),
+            vk_stored: MAIN_CIRCUIT_VK.may_load(deps.storage)?.is_some(),
+            assets_count: ASSETS
+                .may_load(deps.storage)?
+                .map(|assets| assets.len())
+                .unwrap_or_default(),
+            tree_root_present: TREE.get_latest_root(deps.storage).is_ok(),
+        })?),
         QueryMsg::Root {} => Ok(to_binary(&TREE.get_latest_root(deps.storage)?)?),
         QueryMsg::Notes {
             limit,
             start_after,
             is_ascending,
         } => {
-            let bound = match is_ascending.unwrap_or(true) {
-                true => (start_after.map(Bound::exclusive), None, Order::Ascending),
-                false => (None, start_after.map(Bound::exclusive), Order::Descending),
-            };
-            let notes = TREE
-                .tree
-                .leafs
-                .range(deps.storage, bound.0, bound.1, bound.2)
-                .take(limit.unwrap_or(100) as usize)
-                .map(|e| -> Result<_, ContractError> { Ok(e?.1) })
-                .collect::<Result<Vec<_>, _>>()?;
+            let limit = limit.unwrap_or(QUERY_LIMIT).min(QUERY_LIMIT);
+            let notes = notes_page(deps.storage, Some(limit), start_after, is_ascending)?
+                .into_iter()
+                .map(|(_, note)| note)
+                .collect::<Vec<_>>();
             let latest_index = start_after.unwrap_or_default() + notes.len() as u64;
 
             Ok(to_binary(&NotesResponse {
@@ -408,13 +790,671 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<QueryResponse, Cont
                 latest_index,
             })?)
         }
+        QueryMsg::IndexedNotes {
+            limit,
+            start_after,
+            is_ascending,
+        } => {
+            let limit = limit.unwrap_or(QUERY_LIMIT).min(QUERY_LIMIT);
+            Ok(to_binary(&IndexedNotesResponse {
+                notes: notes_page(deps.storage, Some(limit), start_after, is_ascending)?,
+            })?)
+        }
+        QueryMsg::NotesPage {
+            cursor,
+            page_size,
+            is_ascending,
+        } => {
+            let page_size = page_size.unwrap_or(NOTES_PAGE_SIZE).clamp(1, QUERY_LIMIT);
+            // One more leaf tells whether there is a next page
+            let mut notes = notes_page(deps.storage, Some(page_size + 1), cursor, is_ascending)?;
+            let has_next = notes.len() as u64 > page_size;
+            notes.truncate(page_size as usize);
+            let next_cursor = has_next
+                .then(|| notes.last().map(|(index, _)| *index))
+                .flatten();
+
+            Ok(to_binary(&NotesPageResponse { notes, next_cursor })?)
+        }
+        QueryMsg::NotesAt { indices } => {
+            (indices.len() as u64 <= QUERY_LIMIT)
+                .then_some(())
+                .ok_or(ContractError::TooManyIndices(QUERY_LIMIT))?;
+            let notes = indices
+                .into_iter()
+                .map(|index| TREE.tree.leafs.may_load(deps.storage, index))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(to_binary(&notes)?)
+        }
         QueryMsg::NullifierUsed { nullifier_hash } => Ok(to_binary(
             &NULLIFIER.has(deps.storage, &base64::decode(&nullifier_hash)?),
         )?),
+        QueryMsg::SimulateDeposit {
+            root,
+            nullifier_hash,
+            identifier,
+            new_note,
+            proof,
+            funds,
+            bound_height,
+        } => {
+            verify_deposit(
+                deps,
+                deposit_aux(deps.storage, &env, bound_height)?,
+                &root,
+                &nullifier_hash,
+                &identifier,
+                &new_note,
+                &proof,
+                &funds,
+            )?;
+            Ok(to_binary(&true)?)
+        }
+        QueryMsg::VerifyRaw {
+            public_inputs,
+            proof,
+        } => {
+            (public_inputs.len() == MAIN_CIRCUIT_PUBLIC_INPUTS)
+                .then_some(())
+                .ok_or(ContractError::InvalidPublicInputs(
+                    MAIN_CIRCUIT_PUBLIC_INPUTS,
+                ))?;
+            let public_inputs = public_inputs
+                .iter()
+                .map(|input| decode_field(input))
+                .collect::<Result<Vec<Fr>, _>>()?;
+
+            let vk = load_main_vk(deps.storage)?;
+            let proof = Proof::deserialize_compressed_unchecked(&base64::decode(proof)?[..])?;
+
+            Ok(to_binary(&Groth16::<Bn254, LibsnarkReduction>::verify(
+                &vk,
+                &public_inputs,
+                &proof,
+            )?)?)
+        }
+        QueryMsg::AccountingDrift {} => {
+            let assets = ASSETS
+                .load(deps.storage)?
+                .into_iter()
+                .map(|denom| -> Result<_, ContractError> {
+                    Ok(AssetAccounting {
+                        actual: deps
+                            .querier
+                            .query_balance(&env.contract.address, &denom)?
+                            .amount,
+                        accounted: ACCOUNTED_TOTAL
+                            .may_load(deps.storage, &denom)?
+                            .unwrap_or_default(),
+                        denom,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(to_binary(&AccountingDriftResponse { assets })?)
+        }
+        QueryMsg::Claimable { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            let claimable = CLAIMABLE
+                .prefix(&address)
+                .range(deps.storage, None, None, Order::Ascending)
+                .map(|e| -> Result<_, ContractError> {
+                    let (denom, amount) = e?;
+                    Ok(Coin { denom, amount })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(to_binary(&claimable)?)
+        }
+        QueryMsg::LastProof { action } => {
+            Ok(to_binary(&LAST_PROOF.may_load(deps.storage, &action)?)?)
+        }
+        QueryMsg::ComputeAux {
+            swap_argument,
+            timeout,
+        } => Ok(to_binary(&base64::encode(
+            swap_aux(&poseidon_bn254(), &swap_argument, timeout)?
+                .into_bigint()
+                .to_bytes_le(),
+        ))?),
+        QueryMsg::ComputeNote {
+            balance_root,
+            identifier,
+            nullifier,
+        } => Ok(to_binary(&base64::encode(
+            PoseidonHash::crh(
+                &poseidon_bn254(),
+                &[
+                    decode_field(&balance_root)?,
+                    decode_field(&identifier)?,
+                    decode_field(&nullifier)?,
+                ],
+            )?
+            .into_bigint()
+            .to_bytes_le(),
+        ))?),
+        QueryMsg::NullifierHashOf { note, nullifier } => Ok(to_binary(
+            &PoseidonHasher(&poseidon_bn254()).nullifier_hash(&note, &nullifier)?,
+        )?),
+        QueryMsg::RootCreatedAt { root } => Ok(to_binary(&ALL_ROOTS.may_load(
+            deps.storage,
+            &decode_field::<Fr>(&root)?.into_bigint().to_bytes_le(),
+        )?)?),
+        QueryMsg::SpentNullifiers { limit, start_after } => {
+            let start_after = start_after.map(base64::decode).transpose()?;
+            let nullifiers = NULLIFIER
+                .keys(
+                    deps.storage,
+                    start_after.as_deref().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.unwrap_or(QUERY_LIMIT).min(QUERY_LIMIT) as usize)
+                .map(|e| -> Result<_, ContractError> { Ok(base64::encode(e?)) })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(to_binary(&SpentNullifiersResponse { nullifiers })?)
+        }
+    }
+}
+
+/// Verifies a deposit of `funds` bound to `aux` without updating any state, returning the
+/// nullifier hash.
+#[allow(clippy::too_many_arguments)]
+fn verify_deposit(
+    deps: Deps,
+    aux: Fr,
+    root: &str,
+    nullifier_hash: &str,
+    identifier: &str,
+    new_note: &str,
+    proof: &str,
+    funds: &[Coin],
+) -> Result<Fr, ContractError> {
+    let assets = ASSETS.load(deps.storage)?;
+    let hasher = poseidon_bn254();
+    let vk = load_main_vk(deps.storage)?;
+    let proof = Proof::deserialize_compressed_unchecked(&base64::decode(proof)?[..])?;
+    let nullifier_hash = decode_field::<Fr>(nullifier_hash)?;
+
+    let tree_root = decode_field::<Fr>(root)?;
+    if tree_root != Fr::zero() {
+        let tree_root_normalized = base64::encode(tree_root.into_bigint().to_bytes_le());
+        TREE.is_valid_root(deps.storage, &tree_root_normalized)?
+            .then_some(())
+            .ok_or(ContractError::InvalidRoot)?;
     }
+
+    if nullifier_hash != Fr::zero() {
+        NULLIFIER
+            .has(deps.storage, &nullifier_hash.into_bigint().to_bytes_le())
+            .not()
+            .then_some(())
+            .ok_or(ContractError::UsedNullifier)?;
+    }
+
+    ensure_unique_note(deps.storage, new_note)?;
+
+    check_funds_assets(&assets, funds)?;
+    check_min_deposit(deps.storage, funds)?;
+    let diff_balance_root = funds_diff_balance_root(&hasher, &assets, funds)?;
+
+    Groth16::<Bn254, LibsnarkReduction>::verify(
+        &vk,
+        &[
+            aux,
+            tree_root,
+            diff_balance_root,
+            nullifier_hash,
+            decode_field(identifier)?,
+            decode_field(new_note)?,
+        ],
+        &proof,
+    )?
+    .then_some(())
+    .ok_or(ContractError::InvalidProof)?;
+
+    Ok(nullifier_hash)
+}
+
+/// Verifies that `asset_bitmap` is the one of `new_note`, whose public inputs are
+/// `[new_note, bitmap]`.
+fn verify_asset_bitmap(
+    storage: &dyn Storage,
+    new_note: &str,
+    asset_bitmap: &AssetBitmap,
+) -> Result<(), ContractError> {
+    let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(
+        &ASSET_BITMAP_CIRCUIT_VK
+            .may_load(storage)?
+            .ok_or(ContractError::AssetBitmapNotSupported)?[..],
+    )?;
+    let proof = Proof::deserialize_compressed_unchecked(&base64::decode(&asset_bitmap.proof)?[..])?;
+
+    Groth16::<Bn254, LibsnarkReduction>::verify(
+        &vk,
+        &[decode_field(new_note)?, Fr::from(asset_bitmap.bitmap)],
+        &proof,
+    )?
+    .then_some(())
+    .ok_or(ContractError::InvalidProof)
+}
+
+/// Verifies and applies a deposit of `funds` bound to `aux`, returning the inserted note index, the
+/// new root and the root evicted from the history if any.
+#[allow(clippy::too_many_arguments)]
+fn deposit(
+    deps: DepsMut,
+    height: u64,
+    aux: Fr,
+    root: &str,
+    nullifier_hash: &str,
+    identifier: &str,
+    new_note: &str,
+    proof: &str,
+    funds: &[Coin],
+) -> Result<(u64, String, Option<String>), ContractError> {
+    let hasher = poseidon_bn254();
+    let nullifier_hash = verify_deposit(
+        deps.as_ref(),
+        aux,
+        root,
+        nullifier_hash,
+        identifier,
+        new_note,
+        proof,
+        funds,
+    )?;
+    store_proof(deps.storage, "deposit", proof)?;
+    for coin in funds {
+        update_accounted_total(deps.storage, &coin.denom, coin.amount, true)?;
+    }
+
+    if nullifier_hash != Fr::zero() {
+        NULLIFIER.save(
+            deps.storage,
+            &nullifier_hash.into_bigint().to_bytes_le(),
+            &(),
+        )?;
+    }
+
+    insert_note(deps.storage, height, new_note, &hasher)
+}
+
+/// Verifies the withdrawal of `withdrawn_assets` from the note of `sender`, spending its
+/// nullifier and inserting the new note, returning its index, the new root and any evicted root.
+#[allow(clippy::too_many_arguments)]
+fn withdraw(
+    storage: &mut dyn Storage,
+    height: u64,
+    sender: &str,
+    withdrawn_assets: &BTreeMap<String, Uint128>,
+    root: &str,
+    nullifier_hash: &str,
+    blinding: &str,
+    new_note: &str,
+    proof: &str,
+) -> Result<(u64, String, Option<String>), ContractError> {
+    let assets = ASSETS.load(storage)?;
+    let hasher = poseidon_bn254();
+    let vk = load_main_vk(storage)?;
+    store_proof(storage, "withdraw", proof)?;
+    let proof = Proof::deserialize_compressed_unchecked(&base64::decode(proof)?[..])?;
+    let nullifier_hash = decode_field::<Fr>(nullifier_hash)?;
+
+    let nullifier_normalized = nullifier_hash.into_bigint().to_bytes_le();
+    NULLIFIER
+        .has(storage, &nullifier_normalized)
+        .not()
+        .then_some(())
+        .ok_or(ContractError::UsedNullifier)?;
+    NULLIFIER.save(storage, &nullifier_normalized, &())?;
+
+    let tree_root = decode_field::<Fr>(root)?;
+    check_spent_root(storage, &tree_root)?;
+
+    let diff_balance_root = PoseidonHash::crh(
+        &hasher,
+        &assets
+            .iter()
+            .map(|a| {
+                withdrawn_assets
+                    .get(a)
+                    .map(|f| Fr::from(f.u128()).neg())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>(),
+    )?;
+    let blinding = decode_field(blinding)?;
+    let address = encoding::address_to_field(sender);
+    let identifier = PoseidonHash::tto_crh(&hasher, address, blinding)?;
+
+    let is_valid = Groth16::<Bn254, LibsnarkReduction>::verify(
+        &vk,
+        &[
+            Fr::zero(),
+            tree_root,
+            diff_balance_root,
+            nullifier_hash,
+            identifier,
+            decode_field(new_note)?,
+        ],
+        &proof,
+    )?;
+
+    let (index, new_root, evicted_root) = insert_note(storage, height, new_note, &hasher)?;
+
+    is_valid.then_some(()).ok_or(ContractError::InvalidProof)?;
+
+    for (denom, amount) in withdrawn_assets {
+        update_accounted_total(storage, denom, *amount, false)?;
+    }
+
+    Ok((index, new_root, evicted_root))
+}
+
+/// Calculates the `aux` public input of a deposit proof bound to `bound_height`, which must be
+/// within the maximum proof age of the current block if one is set.
+fn deposit_aux(
+    storage: &dyn Storage,
+    env: &Env,
+    bound_height: Option<u64>,
+) -> Result<Fr, ContractError> {
+    let max_proof_age = MAX_PROOF_AGE.may_load(storage)?;
+    let bound_height = match (bound_height, max_proof_age) {
+        (Some(bound_height), _) => bound_height,
+        (None, Some(max_proof_age)) => return Err(ContractError::StaleProof(max_proof_age)),
+        (None, None) => return Ok(Fr::zero()),
+    };
+
+    (bound_height <= env.block.height)
+        .then_some(())
+        .ok_or(ContractError::FutureBoundHeight(bound_height))?;
+    if let Some(max_proof_age) = max_proof_age {
+        (env.block.height - bound_height <= max_proof_age)
+            .then_some(())
+            .ok_or(ContractError::StaleProof(max_proof_age))?;
+    }
+
+    Ok(Fr::from(bound_height))
+}
+
+/// Adds or subtracts `amount` of `denom` from its accounted total, ignoring denoms that are not
+/// one of the assets as they never back any note.
+fn update_accounted_total(
+    storage: &mut dyn Storage,
+    denom: &str,
+    amount: Uint128,
+    is_add: bool,
+) -> Result<(), ContractError> {
+    if !ASSETS.load(storage)?.iter().any(|a| a == denom) {
+        return Ok(());
+    }
+
+    // Saturates rather than failing a valid spend, the drift is reported by
+    // `QueryMsg::AccountingDrift` instead
+    ACCOUNTED_TOTAL.update(storage, denom, |total| -> Result<_, ContractError> {
+        let total = total.unwrap_or_default();
+        Ok(match is_add {
+            true => total.saturating_add(amount),
+            false => total.saturating_sub(amount),
+        })
+    })?;
+    Ok(())
+}
+
+/// Seeds the accounted total of every asset without one from the contract's balance, which covers
+/// the funds backing genesis notes and deposits made before totals were tracked.
+fn seed_accounted_totals(deps: DepsMut, env: &Env) -> Result<(), ContractError> {
+    for denom in ASSETS.load(deps.storage)? {
+        if !ACCOUNTED_TOTAL.has(deps.storage, &denom) {
+            let balance = deps
+                .querier
+                .query_balance(&env.contract.address, &denom)?
+                .amount;
+            ACCOUNTED_TOTAL.save(deps.storage, &denom, &balance)?;
+        }
+    }
+    Ok(())
+}
+
+/// Subtracts the `spent` coins from `funds`, failing if any of them is not attached.
+fn subtract_funds(mut funds: Vec<Coin>, spent: &[Coin]) -> Result<Vec<Coin>, ContractError> {
+    for coin in spent {
+        let attached = funds
+            .iter_mut()
+            .find(|c| c.denom == coin.denom)
+            .ok_or(ContractError::InsufficientFunds)?;
+        attached.amount = attached
+            .amount
+            .checked_sub(coin.amount)
+            .map_err(|_| ContractError::InsufficientFunds)?;
+    }
+    funds.retain(|c| !c.amount.is_zero());
+
+    Ok(funds)
+}
+
+/// Returns a page of `(index, note)` leaves, starting after the index `start_after` in the given
+/// order.
+fn notes_page(
+    storage: &dyn Storage,
+    limit: Option<u64>,
+    start_after: Option<u64>,
+    is_ascending: Option<bool>,
+) -> Result<Vec<(u64, String)>, ContractError> {
+    let bound = match is_ascending.unwrap_or(true) {
+        true => (start_after.map(Bound::exclusive), None, Order::Ascending),
+        false => (None, start_after.map(Bound::exclusive), Order::Descending),
+    };
+    Ok(TREE
+        .tree
+        .leafs
+        .range(storage, bound.0, bound.1, bound.2)
+        .take(limit.unwrap_or(QUERY_LIMIT) as usize)
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Keeps `proof` as the latest one of `action` if proofs are stored.
+///
+/// Nothing is persisted unless the whole message succeeds, so this may be called before the proof
+/// is verified.
+fn store_proof(storage: &mut dyn Storage, action: &str, proof: &str) -> Result<(), ContractError> {
+    if STORE_PROOFS.may_load(storage)?.unwrap_or_default() {
+        LAST_PROOF.save(storage, action, &proof.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Rejects `tree_root` unless it is in the root history. Unlike deposits, a zero root is never
+/// valid since there is no note to spend from an empty tree.
+fn check_spent_root(storage: &dyn Storage, tree_root: &Fr) -> Result<(), ContractError> {
+    tree_root
+        .is_zero()
+        .not()
+        .then_some(())
+        .ok_or(ContractError::EmptyTree)?;
+    TREE.is_valid_root(
+        storage,
+        &base64::encode(tree_root.into_bigint().to_bytes_le()),
+    )?
+    .then_some(())
+    .ok_or(ContractError::InvalidRoot)
+}
+
+/// Rejects any `funds` attached to messages spending from the contract's custody, which would
+/// otherwise be stranded.
+fn check_no_funds(funds: &[Coin]) -> Result<(), ContractError> {
+    funds
+        .is_empty()
+        .then_some(())
+        .ok_or(ContractError::UnexpectedFunds)
+}
+
+/// Rejects any nonzero deposited coin of `funds` below the minimum deposit of its asset.
+fn check_min_deposit(storage: &dyn Storage, funds: &[Coin]) -> Result<(), ContractError> {
+    for coin in funds.iter().filter(|c| !c.amount.is_zero()) {
+        if let Some(min_amount) = MIN_DEPOSIT.may_load(storage, &coin.denom)? {
+            (coin.amount >= min_amount).then_some(()).ok_or_else(|| {
+                ContractError::BelowMinimum {
+                    denom: coin.denom.clone(),
+                }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects `funds` if none of them are of the `assets`, as they would be locked in the contract
+/// while the diff balance root only commits to zero balances.
+fn check_funds_assets(assets: &[String], funds: &[Coin]) -> Result<(), ContractError> {
+    (funds.is_empty() || funds.iter().any(|c| assets.contains(&c.denom)))
+        .then_some(())
+        .ok_or(ContractError::NoMatchingAssets)
+}
+
+/// Sums the `funds` amounts of each of the `assets` in field space, so that summing multiple
+/// coins of the same denom never overflows `u128`.
+pub fn funds_balances(assets: &[String], funds: &[Coin]) -> Vec<Fr> {
+    let mut funds_map = BTreeMap::<&str, Fr>::new();
+    for coin in funds {
+        *funds_map.entry(&coin.denom).or_default() += Fr::from(coin.amount.u128());
+    }
+
+    assets
+        .iter()
+        .map(|a| funds_map.get(a.as_str()).copied().unwrap_or_default())
+        .collect()
+}
+
+/// Loads the stored main circuit verifying key, decompressing it if it was stored compressed.
+fn load_main_vk(storage: &dyn Storage) -> Result<VerifyingKey<Bn254>, ContractError> {
+    let vk = MAIN_CIRCUIT_VK.load(storage)?;
+    Ok(
+        match MAIN_CIRCUIT_VK_COMPRESSED
+            .may_load(storage)?
+            .unwrap_or_default()
+        {
+            true => VerifyingKey::deserialize_compressed_unchecked(&vk[..])?,
+            false => VerifyingKey::deserialize_uncompressed_unchecked(&vk[..])?,
+        },
+    )
+}
+
+/// Deserializes an accepted verifying key with its points checked to be valid, which is skipped
+/// when loading the stored key to verify proofs.
+fn validate_vk(vk: &[u8]) -> Result<VerifyingKey<Bn254>, ContractError> {
+    VerifyingKey::<Bn254>::deserialize_uncompressed(vk)
+        .map_err(|e| ContractError::InvalidVerifyingKey(e.to_string()))
+}
+
+/// Calculates the diff balance root of depositing `funds`, in the order of `assets`.
+fn funds_diff_balance_root(
+    hasher: &PoseidonConfig<Fr>,
+    assets: &[String],
+    funds: &[Coin],
+) -> Result<Fr, ContractError> {
+    Ok(PoseidonHash::crh(hasher, &funds_balances(assets, funds))?)
+}
+
+/// Rejects `new_note` if unique notes are enabled and it is already in the tree, returning its
+/// normalized bytes to be marked as existing if enabled.
+fn ensure_unique_note(
+    storage: &dyn Storage,
+    new_note: &str,
+) -> Result<Option<Vec<u8>>, ContractError> {
+    if !UNIQUE_NOTES.may_load(storage)?.unwrap_or_default() {
+        return Ok(None);
+    }
+
+    let note_normalized = decode_field::<Fr>(new_note)?.into_bigint().to_bytes_le();
+    NOTE_EXISTS
+        .has(storage, &note_normalized)
+        .not()
+        .then_some(())
+        .ok_or(ContractError::DuplicateNote)?;
+
+    Ok(Some(note_normalized))
+}
+
+/// Inserts `new_note` into the tree at block `height`, rejecting duplicates if unique notes are
+/// enabled.
+///
+/// Zero notes are rejected as they are indistinguishable from empty leaves.
+fn insert_note(
+    storage: &mut dyn Storage,
+    height: u64,
+    new_note: &str,
+    hasher: &PoseidonConfig<Fr>,
+) -> Result<(u64, String, Option<String>), ContractError> {
+    decode_field::<Fr>(new_note)?
+        .is_zero()
+        .not()
+        .then_some(())
+        .ok_or(ContractError::ZeroNote)?;
+
+    if let Some(note_normalized) = ensure_unique_note(storage, new_note)? {
+        NOTE_EXISTS.save(storage, &note_normalized, &())?;
+    }
+
+    // The new root replaces the one after the current history index, once the history is full
+    let next_index = (TREE.history_index.may_load(storage)?.unwrap_or_default() + 1) % ROOT_HISTORY;
+    let evicted_root = TREE.root_index.may_load(storage, next_index)?;
+
+    let (index, new_root) = TREE.insert(storage, new_note.to_string(), &PoseidonHasher(hasher))?;
+    record_root(storage, height, &new_root)?;
+
+    Ok((index, new_root, evicted_root))
+}
+
+/// Records `root` as created at block `height`, if all roots are tracked.
+fn record_root(storage: &mut dyn Storage, height: u64, root: &str) -> Result<(), ContractError> {
+    if TRACK_ALL_ROOTS.may_load(storage)?.unwrap_or_default() {
+        ALL_ROOTS.save(
+            storage,
+            &decode_field::<Fr>(root)?.into_bigint().to_bytes_le(),
+            &height,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Calculates the `aux` public input binding a swap proof to `swap_argument` and `timeout`,
+/// rejecting swaps of more than [`MAX_ROUTES`] hops before hashing them.
+pub fn swap_aux(
+    hasher: &PoseidonConfig<Fr>,
+    swap_argument: &MsgSwapExactAmountIn,
+    timeout: Option<u64>,
+) -> Result<Fr, ContractError> {
+    (swap_argument.routes.len() <= MAX_ROUTES)
+        .then_some(())
+        .ok_or(ContractError::TooManyRoutes(MAX_ROUTES))?;
+    let token_in = swap_argument
+        .token_in
+        .as_ref()
+        .ok_or(ContractError::InvalidSwapRoute)?;
+    let routes = swap_argument
+        .routes
+        .iter()
+        .map(|r| (r.pool_id, r.token_out_denom.as_str()))
+        .collect::<Vec<_>>();
+
+    Ok(encoding::swap_aux(
+        hasher,
+        &routes,
+        &token_in.denom,
+        Uint128::from_str(&token_in.amount)?.u128(),
+        Uint128::from_str(&swap_argument.token_out_min_amount)?.u128(),
+        timeout,
+    )?)
 }
 
 #[entry_point]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    seed_accounted_totals(deps, &env)?;
     Ok(Response::new())
 }
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 
 use circuits::N_ASSETS;
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Coin, Uint128};
 use osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountIn;
 use serde::{Deserialize, Serialize};
 
@@ -9,18 +9,98 @@ use serde::{Deserialize, Serialize};
 pub struct InstantiateMsg {
     pub assets: [String; N_ASSETS],
     pub main_circuit_vk: String,
+    /// Proof checking that `main_circuit_vk` was built for `N_ASSETS` assets, which its public
+    /// input count alone can't tell. Skipped if none.
+    ///
+    /// The check only shows that the key accepts one statement over `N_ASSETS` zero balances,
+    /// not that it is the key of this circuit. Its proof is also a valid empty first time
+    /// deposit of `new_note`, which anyone can replay from the instantiation, so it should be
+    /// built from a throwaway nullifier and blinding.
+    pub main_circuit_vk_check: Option<VkCheck>,
+    /// Stores `main_circuit_vk` compressed, about halving its size and the instantiation gas at
+    /// the cost of decompressing it on every verification. Disabled by default.
+    pub compress_vk: Option<bool>,
+    /// Rejects notes that are already in the tree, at the cost of an extra storage write per
+    /// inserted note. Disabled by default.
+    pub unique_notes: Option<bool>,
+    /// Verifying key of the split circuit, splitting is disabled if none.
+    pub split_circuit_vk: Option<String>,
+    /// Verifying key of the asset bitmap circuit, deposits can't reveal their asset bitmap if
+    /// none.
+    pub asset_bitmap_circuit_vk: Option<String>,
+    /// Minimum amount of each deposited asset, assets not being deposited are exempt.
+    pub min_deposits: Option<BTreeMap<String, Uint128>>,
+    /// Whether to keep the latest verified proof of each action for auditing, disabled if none.
+    pub store_proofs: Option<bool>,
+    /// Base64 encoded notes inserted in order at instantiation, e.g. migrated from a prior
+    /// deployment.
+    pub genesis_notes: Option<Vec<String>>,
+    /// Decimals of each asset, in the order of `assets`, for clients to format amounts.
+    pub decimals: Option<[u8; N_ASSETS]>,
+    /// Maximum number of blocks the height a deposit proof is bound to may lag behind, deposits
+    /// need not be bound to any height if none.
+    pub max_proof_age: Option<u64>,
+    /// Whether to keep the creation height of every root for [`QueryMsg::RootCreatedAt`], at the
+    /// cost of an extra storage write per inserted note. Disabled if none.
+    pub track_all_roots: Option<bool>,
+}
+
+/// First time deposit of zero of every asset into `new_note`, whose diff balance root
+/// `H_crh([0; N_ASSETS])` only verifies against a VK built for `N_ASSETS` assets.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VkCheck {
+    pub new_note: String,
+    pub proof: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
+    /// Credits the attached funds into a new note.
+    ///
+    /// Depositing into an existing note spends it, revealing its nullifier hash, and creates a
+    /// new note holding the accumulated balances. Both notes share the same nullifier, which is
+    /// what links them, while the identifier is only shared if the blinding is reused.
+    ///
+    /// The optional `tag`, e.g. a referral source, is only emitted as an attribute.
+    ///
+    /// The proof may be bound to a recent `bound_height` as its `aux` public input, so that it
+    /// can't be replayed once older than the contract's maximum proof age.
+    ///
+    /// The optional `asset_bitmap` reveals which assets the new note holds, for wallets to filter
+    /// notes by asset, at the cost of that much privacy.
     Deposit {
         root: String,
         nullifier_hash: String,
         identifier: String,
         new_note: String,
         proof: String,
+        tag: Option<String>,
+        bound_height: Option<u64>,
+        asset_bitmap: Option<AssetBitmap>,
+    },
+    /// Deposits against a root projected from the `intervening` deposits, which are applied in
+    /// order beforehand. The attached funds left after the intervening ones are deposited last.
+    ///
+    /// Each proof may be bound to its own `bound_height` like [`ExecuteMsg::Deposit`].
+    CatchUpDeposit {
+        intervening: Vec<InterveningDeposit>,
+        root: String,
+        nullifier_hash: String,
+        identifier: String,
+        new_note: String,
+        proof: String,
+        bound_height: Option<u64>,
     },
+    /// Spends a note into multiple new notes, crediting the attached funds like a deposit.
+    Split {
+        root: String,
+        nullifier_hash: String,
+        identifier: String,
+        new_notes: Vec<String>,
+        proof: String,
+    },
+    /// Swaps from the note's balance held in the contract's custody, so no funds may be attached.
     Swap {
         swap_argument: MsgSwapExactAmountIn,
         root: String,
@@ -30,6 +110,7 @@ pub enum ExecuteMsg {
         proof: String,
         timeout: Option<u64>,
     },
+    /// Withdraws `assets` from the note's balance, no funds may be attached.
     Withdraw {
         assets: BTreeMap<String, Uint128>,
         root: String,
@@ -38,7 +119,50 @@ pub enum ExecuteMsg {
         new_note: String,
         proof: String,
     },
+    /// Same as [`ExecuteMsg::Withdraw`], but the withdrawn assets are the sum of the `payouts`,
+    /// each sent to its recipient.
+    WithdrawSplit {
+        payouts: Vec<(String, Vec<Coin>)>,
+        root: String,
+        nullifier_hash: String,
+        blinding: String,
+        new_note: String,
+        proof: String,
+    },
     TransferExcess {},
+    /// Sends the sender all of its excess swap outputs that were rejected when transferred.
+    Claim {},
+    /// Transfers the admin to `new_admin`, or renounces it permanently if none.
+    UpdateAdmin {
+        new_admin: Option<String>,
+    },
+    /// Replaces the denom of the asset at `index` with `new_denom`, e.g. after a token rebrand.
+    /// The contract must hold none of either denom, so that no note balance refers to them, and
+    /// `new_denom` must be non-empty and differ from every configured denom ignoring case.
+    RenameAsset {
+        index: usize,
+        new_denom: String,
+    },
+}
+
+/// Bitmap of the assets held by a new note, bit `i` being set if and only if its balance of the
+/// asset at index `i` is nonzero, with the asset bitmap circuit proof of it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssetBitmap {
+    pub bitmap: u64,
+    pub proof: String,
+}
+
+/// Deposit not yet seen by the contract, carrying the `funds` it credits.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InterveningDeposit {
+    pub root: String,
+    pub nullifier_hash: String,
+    pub identifier: String,
+    pub new_note: String,
+    pub proof: String,
+    pub funds: Vec<Coin>,
+    pub bound_height: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -46,22 +170,150 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     Admin {},
     Assets {},
+    Config {},
+    /// Whether the contract is fully configured, for monitoring.
+    Health {},
     Root {},
     Notes {
         limit: Option<u64>,
         start_after: Option<u64>,
         is_ascending: Option<bool>,
     },
+    /// Same as [`QueryMsg::Notes`], but pairs each note with its leaf index.
+    IndexedNotes {
+        limit: Option<u64>,
+        start_after: Option<u64>,
+        is_ascending: Option<bool>,
+    },
+    /// Page of at most `page_size` `(index, note)` leaves after `cursor` in the given order,
+    /// along with the cursor of the next page. Iterating from no cursor until there is no next
+    /// page visits every leaf once, [`crate::NOTES_PAGE_SIZE`] leaves at a time by default.
+    NotesPage {
+        cursor: Option<u64>,
+        page_size: Option<u64>,
+        is_ascending: Option<bool>,
+    },
+    /// Notes at each of `indices`, none for empty slots, in the same order.
+    NotesAt {
+        indices: Vec<u64>,
+    },
     NullifierUsed {
         nullifier_hash: String,
     },
+    /// Verifies a deposit of `funds` as [`ExecuteMsg::Deposit`] would, without updating any state.
+    SimulateDeposit {
+        root: String,
+        nullifier_hash: String,
+        identifier: String,
+        new_note: String,
+        proof: String,
+        funds: Vec<Coin>,
+        bound_height: Option<u64>,
+    },
+    /// Verifies `proof` against the main circuit VK with the base64 encoded `public_inputs`,
+    /// `[aux, root, diff_balance_root, nullifier_hash, identifier, new_note]`, returning whether
+    /// it is valid.
+    VerifyRaw {
+        public_inputs: Vec<String>,
+        proof: String,
+    },
+    SpentNullifiers {
+        limit: Option<u64>,
+        start_after: Option<String>,
+    },
+    /// Contract balance and accounted total of each asset, the balance dropping below the
+    /// accounted total meaning some notes are no longer backed.
+    AccountingDrift {},
+    /// Excess swap outputs claimable by `address` through [`ExecuteMsg::Claim`].
+    Claimable {
+        address: String,
+    },
+    /// Latest verified base64 proof of `action`, one of `deposit`, `split`, `swap` or `withdraw`,
+    /// if proofs are stored.
+    LastProof {
+        action: String,
+    },
+    /// Computes the base64 encoded `aux` public input [`ExecuteMsg::Swap`] binds the proof to.
+    ComputeAux {
+        swap_argument: MsgSwapExactAmountIn,
+        timeout: Option<u64>,
+    },
+    /// Computes the base64 encoded note commitment `H_crh(balance_root, identifier, nullifier)`
+    /// from its base64 encoded components.
+    ComputeNote {
+        balance_root: String,
+        identifier: String,
+        nullifier: String,
+    },
+    /// Computes the base64 encoded nullifier hash `H_tto_crh(note, nullifier)` a spend of the
+    /// base64 encoded `note` reveals.
+    NullifierHashOf {
+        note: String,
+        nullifier: String,
+    },
+    /// Block height `root` was created at, even once evicted from the root history. None if it
+    /// was never a root or roots aren't tracked.
+    RootCreatedAt {
+        root: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MigrateMsg {}
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConfigResponse {
+    pub assets: [String; N_ASSETS],
+    /// Decimals of each asset in the order of `assets`, if provided at instantiation.
+    pub decimals: Option<[u8; N_ASSETS]>,
+    pub unique_notes: bool,
+    pub store_proofs: bool,
+    pub max_proof_age: Option<u64>,
+    pub track_all_roots: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AssetAccounting {
+    pub denom: String,
+    pub actual: Uint128,
+    pub accounted: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccountingDriftResponse {
+    /// Accounting of each asset, in the order of the contract's assets.
+    pub assets: Vec<AssetAccounting>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HealthResponse {
+    pub admin_set: bool,
+    pub vk_stored: bool,
+    pub assets_count: usize,
+    pub tree_root_present: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct NotesResponse {
     pub notes: Vec<String>,
     pub latest_index: u64,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexedNotesResponse {
+    /// `(index, note)` pairs in the requested order.
+    pub notes: Vec<(u64, String)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NotesPageResponse {
+    /// `(index, note)` pairs in the requested order.
+    pub notes: Vec<(u64, String)>,
+    /// Cursor of the next page, none once the last leaf was returned.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpentNullifiersResponse {
+    pub nullifiers: Vec<String>,
+}
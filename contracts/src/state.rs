@@ -8,10 +8,33 @@ use crate::hasher::PoseidonHasher;
 
 pub const ADMIN: Admin = Admin::new("admin");
 pub const MAIN_CIRCUIT_VK: Item<Vec<u8>> = Item::new("main_circuit_vk");
+pub const MAIN_CIRCUIT_VK_COMPRESSED: Item<bool> = Item::new("main_circuit_vk_compressed");
+pub const SPLIT_CIRCUIT_VK: Item<Vec<u8>> = Item::new("split_circuit_vk");
+pub const ASSET_BITMAP_CIRCUIT_VK: Item<Vec<u8>> = Item::new("asset_bitmap_circuit_vk");
 pub const NULLIFIER: Map<&[u8], ()> = Map::new("nullifier");
+pub const UNIQUE_NOTES: Item<bool> = Item::new("unique_notes");
+pub const NOTE_EXISTS: Map<&[u8], ()> = Map::new("note_exists");
 pub const ASSETS: Item<[String; N_ASSETS]> = Item::new("assets");
+pub const DECIMALS: Item<[u8; N_ASSETS]> = Item::new("decimals");
+pub const MIN_DEPOSIT: Map<&str, Uint128> = Map::new("min_deposit");
+/// Amount of each asset backing the notes, which the contract's balance must never drop below.
+pub const ACCOUNTED_TOTAL: Map<&str, Uint128> = Map::new("accounted_total");
+pub const STORE_PROOFS: Item<bool> = Item::new("store_proofs");
+/// Maximum number of blocks the height a deposit proof is bound to may lag behind.
+pub const MAX_PROOF_AGE: Item<u64> = Item::new("max_proof_age");
+pub const TRACK_ALL_ROOTS: Item<bool> = Item::new("track_all_roots");
+/// Block height every root was created at, keyed by its little endian bytes.
+pub const ALL_ROOTS: Map<&[u8], u64> = Map::new("all_roots");
+/// Latest verified proof of each action, bounded by the fixed set of actions.
+pub const LAST_PROOF: Map<&str, String> = Map::new("last_proof");
 pub const LATEST_SWAP: Item<(Coin, Uint128, Addr)> = Item::new("latest_swap");
-pub const TREE: SparseMerkleTreeWithHistoryBounded<String, PoseidonHasher, 100> =
+/// Excess of the latest swap being sent to its recipient, cleared by the transfer reply.
+pub const PENDING_EXCESS: Item<(Addr, Coin)> = Item::new("pending_excess");
+/// Excess swap outputs the recipient rejected, claimable by the recipient.
+pub const CLAIMABLE: Map<(&Addr, &str), Uint128> = Map::new("claimable");
+/// Number of latest roots proofs can be built against.
+pub const ROOT_HISTORY: u32 = 100;
+pub const TREE: SparseMerkleTreeWithHistoryBounded<String, PoseidonHasher, ROOT_HISTORY> =
     SparseMerkleTreeWithHistoryBounded::new(
         "t_hashes",
         "t_leafs",
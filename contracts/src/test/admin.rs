@@ -0,0 +1,235 @@
+use std::error::Error;
+
+use circuits::N_ASSETS;
+use cosmwasm_std::{Addr, Coin};
+use cw_controllers::AdminError;
+use cw_multi_test::Executor;
+
+use crate::{
+    error::ContractError,
+    msg::{ExecuteMsg, QueryMsg},
+    test::{init, new_note, prove_first_deposit, serialize_to_base64, ADMIN, ASSETS, USER_1},
+};
+
+#[test]
+fn transfer_admin() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateAdmin {
+                new_admin: Some(USER_1.to_string()),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::Admin(AdminError::NotAdmin {}))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    app.execute_contract(
+        ADMIN.clone(),
+        addr.clone(),
+        &ExecuteMsg::UpdateAdmin {
+            new_admin: Some(USER_1.to_string()),
+        },
+        &[],
+    )?;
+    let admin: Option<Addr> = app.wrap().query_wasm_smart(&addr, &QueryMsg::Admin {})?;
+    assert_eq!(admin.as_ref(), Some(&*USER_1), "Admin not transferred");
+
+    // Previous admin lost its rights
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateAdmin {
+                new_admin: Some(ADMIN.to_string()),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::Admin(AdminError::NotAdmin {}))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn renounce_admin() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    app.execute_contract(
+        ADMIN.clone(),
+        addr.clone(),
+        &ExecuteMsg::UpdateAdmin { new_admin: None },
+        &[],
+    )?;
+    let admin: Option<Addr> = app.wrap().query_wasm_smart(&addr, &QueryMsg::Admin {})?;
+    assert_eq!(admin, None, "Admin not renounced");
+
+    // Nobody can claim the admin back once renounced
+    for sender in [&*ADMIN, &*USER_1] {
+        let err = app
+            .execute_contract(
+                sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::UpdateAdmin {
+                    new_admin: Some(sender.to_string()),
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::Admin(AdminError::NotAdmin {}))
+            ),
+            "Unexpected error {err:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rename_asset() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+    let rename = ExecuteMsg::RenameAsset {
+        index: 6,
+        new_denom: "uatom2".to_string(),
+    };
+
+    let err = app
+        .execute_contract(USER_1.clone(), addr.clone(), &rename, &[])
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::Admin(AdminError::NotAdmin {}))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    app.execute_contract(ADMIN.clone(), addr.clone(), &rename, &[])?;
+    let assets: [String; N_ASSETS] = app.wrap().query_wasm_smart(&addr, &QueryMsg::Assets {})?;
+    assert_eq!(assets[..6], ASSETS[..6]);
+    assert_eq!(assets[6], "uatom2");
+
+    // Deposits of the new denom are credited to the renamed slot
+    let funds = vec![Coin::new(500_000, "uatom2")];
+    app.init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &USER_1, funds.clone())
+    })?;
+    let note = new_note(&hasher, &mut rng, &USER_1, [0, 0, 0, 0, 0, 0, 500_000])?;
+    app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        },
+        &funds,
+    )?;
+    assert_eq!(
+        app.wrap().query_balance(&addr, "uatom2")?.amount.u128(),
+        500_000
+    );
+
+    // The slot can't be renamed anymore while notes hold it
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr.clone(),
+            &ExecuteMsg::RenameAsset {
+                index: 6,
+                new_denom: "uatom3".to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::NonZeroAssetBalance { denom }) if denom == "uatom2"
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr.clone(),
+            &ExecuteMsg::RenameAsset {
+                index: 0,
+                new_denom: "uatom2".to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::DuplicateAsset(denom)) if denom == "uatom2"
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    // Swaps match denoms ignoring case, so a case variant of a listed denom is a duplicate
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr.clone(),
+            &ExecuteMsg::RenameAsset {
+                index: 0,
+                new_denom: ASSETS[1].to_uppercase(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::DuplicateAsset(denom)) if *denom == ASSETS[1].to_uppercase()
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr,
+            &ExecuteMsg::RenameAsset {
+                index: 0,
+                new_denom: String::new(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::EmptyDenom)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
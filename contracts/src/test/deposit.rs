@@ -2,16 +2,28 @@ use std::{collections::BTreeMap, error::Error};
 
 use ark_bn254::{Bn254, Fr};
 use ark_crypto_primitives::snark::SNARK;
-use ark_ff::PrimeField;
-use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, Proof};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::{UniformRand, Zero};
-use circuits::{merkle_tree::Path, poseidon::PoseidonHash, N_ASSETS};
-use cosmwasm_std::Coin;
-use cw_multi_test::Executor;
+use circuits::{
+    encoding, merkle_tree::Path, poseidon::PoseidonHash, test_vectors, utils::poseidon_bn254,
+    AssetBitmapCircuitBn254, N_ASSETS,
+};
+use cosmwasm_std::{Addr, Coin, Uint128};
+use cw_multi_test::{App, Executor};
+use rand::rngs::OsRng;
 
 use crate::{
-    msg::{ExecuteMsg, QueryMsg},
-    test::{init, serialize_to_base64, Circuit, KEY, USER_1},
+    error::ContractError,
+    funds_balances,
+    msg::{AssetBitmap, ExecuteMsg, InterveningDeposit, NotesResponse, QueryMsg},
+    test::{
+        deposit_funds, deposit_new_note, init, init_with, new_note, prove_first_deposit,
+        prove_first_deposit_with_aux, prove_transition, serialize_to_base64, wasm_attribute,
+        Circuit, Note, ASSETS, KEY, USER_1,
+    },
+    MAX_TAG_LENGTH,
 };
 
 #[test]
@@ -64,6 +76,9 @@ fn deposit_first_time() -> Result<(), Box<dyn Error>> {
             identifier: String::new(),
             new_note: serialize_to_base64(&new_note),
             proof: serialize_to_base64(&proof),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(uosmo_amount, "uosmo")],
     )?;
@@ -136,6 +151,9 @@ fn deposit_subsequent_diff_asset() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(uosmo_amount, "uosmo")],
     )?;
@@ -186,6 +204,9 @@ fn deposit_subsequent_diff_asset() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(uusdc_amount, "uusdc")],
     )?;
@@ -258,6 +279,9 @@ fn deposit_subsequent_same_asset() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(uosmo_amount, "uosmo")],
     )?;
@@ -308,6 +332,9 @@ fn deposit_subsequent_same_asset() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(new_uosmo_amount, "uosmo")],
     )?;
@@ -380,6 +407,9 @@ fn deposit_alot() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(uosmo_amount, "uosmo")],
     )?;
@@ -432,6 +462,9 @@ fn deposit_alot() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(new_uosmo_amount, "uosmo")],
     )?;
@@ -491,9 +524,982 @@ fn deposit_alot() -> Result<(), Box<dyn Error>> {
                 },
                 &mut rng,
             )?),
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
         },
         &[Coin::new(new_uosmo_amount, "uosmo")],
     )?;
 
     Ok(())
 }
+
+#[test]
+fn deposit_duplicate_note() -> Result<(), Box<dyn Error>> {
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    for unique_notes in [None, Some(true)] {
+        let (mut app, addr, _, hasher, mut rng) = init_with(|msg| msg.unique_notes = unique_notes)?;
+
+        let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+        let msg = ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        };
+
+        app.execute_contract(USER_1.clone(), addr.clone(), &msg, &deposit_funds(amounts))?;
+        let result =
+            app.execute_contract(USER_1.clone(), addr.clone(), &msg, &deposit_funds(amounts));
+
+        match unique_notes {
+            Some(true) => {
+                let err = result.unwrap_err();
+                assert!(
+                    matches!(
+                        err.downcast_ref::<ContractError>(),
+                        Some(ContractError::DuplicateNote)
+                    ),
+                    "Unexpected error {err:?}"
+                );
+            }
+            _ => {
+                result?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn funds_sum_overflowing_u128() {
+    let assets = ASSETS.map(String::from);
+    let funds = [
+        Coin::new(u128::MAX, ASSETS[0]),
+        Coin::new(u128::MAX, ASSETS[0]),
+        Coin::new(1, ASSETS[0]),
+        Coin::new(u128::MAX, ASSETS[3]),
+    ];
+
+    let balances = funds_balances(&assets, &funds);
+
+    let mut expected = [Fr::zero(); N_ASSETS];
+    expected[0] = Fr::from(u128::MAX) + Fr::from(u128::MAX) + Fr::from(1u128);
+    expected[3] = Fr::from(u128::MAX);
+    assert_eq!(balances, expected, "Invalid summed balances");
+}
+
+#[test]
+fn simulate_deposit() -> Result<(), Box<dyn Error>> {
+    let (app, addr, _, hasher, mut rng) = init()?;
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+    let proof = prove_first_deposit(&hasher, &mut rng, &note)?;
+    let simulate = |funds: Vec<Coin>| {
+        app.wrap().query_wasm_smart::<bool>(
+            &addr,
+            &QueryMsg::SimulateDeposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: proof.clone(),
+                funds,
+                bound_height: None,
+            },
+        )
+    };
+
+    assert!(
+        simulate(deposit_funds(amounts))?,
+        "Valid deposit must simulate"
+    );
+
+    // Funds not matching the proven balances
+    let err = simulate(deposit_funds([400_000, 0, 0, 0, 0, 0, 0])).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&ContractError::InvalidProof.to_string()),
+        "Unexpected error {err:?}"
+    );
+
+    let notes: NotesResponse = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::Notes {
+            limit: None,
+            start_after: None,
+            is_ascending: None,
+        },
+    )?;
+    assert!(notes.notes.is_empty(), "Simulation must not insert notes");
+
+    Ok(())
+}
+
+#[test]
+fn verify_raw_deposit() -> Result<(), Box<dyn Error>> {
+    let (app, addr, _, hasher, mut rng) = init()?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, [500_000, 0, 0, 0, 0, 0, 0])?;
+    let proof = prove_first_deposit(&hasher, &mut rng, &note)?;
+    let verify = |new_note: Fr| {
+        app.wrap().query_wasm_smart::<bool>(
+            &addr,
+            &QueryMsg::VerifyRaw {
+                public_inputs: [
+                    Fr::zero(),
+                    Fr::zero(),
+                    PoseidonHash::crh(&hasher, &note.balances).expect("Failed to hash"),
+                    Fr::zero(),
+                    Fr::zero(),
+                    new_note,
+                ]
+                .iter()
+                .map(serialize_to_base64)
+                .collect(),
+                proof: proof.clone(),
+            },
+        )
+    };
+
+    assert!(verify(note.commitment)?, "Deposit inputs must verify");
+    assert!(!verify(Fr::rand(&mut rng))?, "Other note must not verify");
+
+    let err = app
+        .wrap()
+        .query_wasm_smart::<bool>(
+            &addr,
+            &QueryMsg::VerifyRaw {
+                public_inputs: vec![serialize_to_base64(&Fr::zero())],
+                proof: proof.clone(),
+            },
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&ContractError::InvalidPublicInputs(6).to_string()),
+        "Unexpected error {err:?}"
+    );
+
+    // The modulus reduces to the zero aux but isn't its canonical encoding
+    let mut public_inputs = vec![base64::encode(Fr::MODULUS.to_bytes_le())];
+    public_inputs.extend(
+        [
+            Fr::zero(),
+            PoseidonHash::crh(&hasher, &note.balances)?,
+            Fr::zero(),
+            Fr::zero(),
+            note.commitment,
+        ]
+        .iter()
+        .map(serialize_to_base64),
+    );
+    let err = app
+        .wrap()
+        .query_wasm_smart::<bool>(
+            &addr,
+            &QueryMsg::VerifyRaw {
+                public_inputs,
+                proof,
+            },
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&ContractError::NonCanonicalField.to_string()),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_non_canonical_fields() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+    let proof = prove_first_deposit(&hasher, &mut rng, &note)?;
+
+    // The note with a trailing zero byte still reduces to the same field element
+    let mut over_length = note.commitment.into_bigint().to_bytes_le();
+    over_length.push(0);
+    // The modulus reduces to the zero root
+    let modulus = Fr::MODULUS.to_bytes_le();
+
+    for (root, new_note) in [
+        (String::new(), base64::encode(over_length)),
+        (
+            base64::encode(modulus),
+            serialize_to_base64(&note.commitment),
+        ),
+    ] {
+        let err = app
+            .execute_contract(
+                USER_1.clone(),
+                addr.clone(),
+                &ExecuteMsg::Deposit {
+                    root,
+                    nullifier_hash: String::new(),
+                    identifier: String::new(),
+                    new_note,
+                    proof: proof.clone(),
+                    tag: None,
+                    bound_height: None,
+                    asset_bitmap: None,
+                },
+                &deposit_funds(amounts),
+            )
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::NonCanonicalField)
+            ),
+            "Unexpected error {err:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn deposit_below_minimum() -> Result<(), Box<dyn Error>> {
+    let min_deposits = BTreeMap::from([
+        (ASSETS[0].to_string(), Uint128::new(100_000)),
+        (ASSETS[1].to_string(), Uint128::new(100_000)),
+    ]);
+    let (mut app, addr, _, hasher, mut rng) =
+        init_with(|msg| msg.min_deposits = Some(min_deposits))?;
+
+    for (uosmo_amount, is_accepted) in [(99_999, false), (100_000, true)] {
+        let amounts = [uosmo_amount, 0, 0, 0, 0, 0, 0];
+        let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+        let result = app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds(amounts),
+        );
+
+        match is_accepted {
+            true => {
+                result?;
+            }
+            false => {
+                let err = result.unwrap_err();
+                assert!(
+                    matches!(
+                        err.downcast_ref::<ContractError>(),
+                        Some(ContractError::BelowMinimum { denom }) if denom == ASSETS[0]
+                    ),
+                    "Unexpected error {err:?}"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn catch_up_deposit() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    // First deposit not yet seen by the contract
+    let old_amounts = [500_000, 0, 0, 0, 0, 0, 0];
+    let old_note = new_note(&hasher, &mut rng, &USER_1, old_amounts)?;
+    let intervening = InterveningDeposit {
+        root: String::new(),
+        nullifier_hash: String::new(),
+        identifier: String::new(),
+        new_note: serialize_to_base64(&old_note.commitment),
+        proof: prove_first_deposit(&hasher, &mut rng, &old_note)?,
+        funds: deposit_funds(old_amounts),
+        bound_height: None,
+    };
+    tree.insert_batch(&BTreeMap::from([(0, old_note.commitment)]), &hasher)?;
+
+    // Subsequent deposit against the projected root, one ahead of the contract
+    let new_amounts = [600_000, 0, 0, 0, 0, 0, 0];
+    let new_note = Note::new(
+        &hasher,
+        old_note.address,
+        old_note.nullifier,
+        Fr::rand(&mut rng),
+        new_amounts.map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &old_note,
+        &new_note,
+        Fr::zero(),
+    )?;
+    let root = serialize_to_base64(&tree.root());
+    tree.insert_batch(&BTreeMap::from([(1, new_note.commitment)]), &hasher)?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: root.clone(),
+                nullifier_hash: serialize_to_base64(&old_note.nullifier_hash),
+                identifier: serialize_to_base64(&old_note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof: proof.clone(),
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds([100_000, 0, 0, 0, 0, 0, 0]),
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidRoot)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::CatchUpDeposit {
+            intervening: vec![intervening],
+            root,
+            nullifier_hash: serialize_to_base64(&old_note.nullifier_hash),
+            identifier: serialize_to_base64(&old_note.identifier),
+            new_note: serialize_to_base64(&new_note.commitment),
+            proof,
+            bound_height: None,
+        },
+        &deposit_funds(new_amounts),
+    )?;
+
+    let contract_root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(
+        contract_root,
+        serialize_to_base64(&tree.root()),
+        "Invalid contract utxo root"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn catch_up_deposit_bound_height() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init_with(|msg| msg.max_proof_age = Some(10))?;
+    let bound_height = app.block_info().height;
+
+    let old_amounts = [500_000, 0, 0, 0, 0, 0, 0];
+    let old_note = new_note(&hasher, &mut rng, &USER_1, old_amounts)?;
+    let intervening = InterveningDeposit {
+        root: String::new(),
+        nullifier_hash: String::new(),
+        identifier: String::new(),
+        new_note: serialize_to_base64(&old_note.commitment),
+        proof: prove_first_deposit_with_aux(&hasher, &mut rng, &old_note, Fr::from(bound_height))?,
+        funds: deposit_funds(old_amounts),
+        bound_height: Some(bound_height),
+    };
+    tree.insert_batch(&BTreeMap::from([(0, old_note.commitment)]), &hasher)?;
+
+    let new_amounts = [600_000, 0, 0, 0, 0, 0, 0];
+    let new_note = Note::new(
+        &hasher,
+        old_note.address,
+        old_note.nullifier,
+        Fr::rand(&mut rng),
+        new_amounts.map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &old_note,
+        &new_note,
+        Fr::from(bound_height),
+    )?;
+    let root = serialize_to_base64(&tree.root());
+    tree.insert_batch(&BTreeMap::from([(1, new_note.commitment)]), &hasher)?;
+
+    let catch_up = |app: &mut App, intervening: InterveningDeposit, bound_height| {
+        app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::CatchUpDeposit {
+                intervening: vec![intervening],
+                root: root.clone(),
+                nullifier_hash: serialize_to_base64(&old_note.nullifier_hash),
+                identifier: serialize_to_base64(&old_note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof: proof.clone(),
+                bound_height,
+            },
+            &deposit_funds(new_amounts),
+        )
+    };
+
+    // Every proof must be bound to a height once a maximum age is set
+    let err = catch_up(
+        &mut app,
+        InterveningDeposit {
+            bound_height: None,
+            ..intervening.clone()
+        },
+        Some(bound_height),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::StaleProof(10))
+        ),
+        "Unexpected error {err:?}"
+    );
+    let err = catch_up(&mut app, intervening.clone(), None).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::StaleProof(10))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    catch_up(&mut app, intervening, Some(bound_height))?;
+    let contract_root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(
+        contract_root,
+        serialize_to_base64(&tree.root()),
+        "Invalid contract utxo root"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_twice_same_note() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let mut note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    let mut index = 0;
+
+    for (amounts, accumulated) in [
+        ([100_000, 0, 0, 0, 0, 0, 0], [600_000, 0, 0, 0, 0, 0, 0]),
+        ([150_000, 0, 0, 0, 0, 0, 0], [750_000, 0, 0, 0, 0, 0, 0]),
+    ] {
+        // Same nullifier and blinding, so the new note keeps the identifier
+        let new_note = Note::new(
+            &hasher,
+            note.address,
+            note.nullifier,
+            note.blinding,
+            accumulated.map(Fr::from),
+        )?;
+        assert_eq!(new_note.identifier, note.identifier, "Identifier changed");
+
+        let proof = prove_transition(
+            &tree,
+            &hasher,
+            &mut rng,
+            index,
+            &note,
+            &new_note,
+            Fr::zero(),
+        )?;
+        let response = app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                identifier: serialize_to_base64(&note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds(amounts),
+        )?;
+        index = wasm_attribute(&response, "index")
+            .ok_or("missing index attribute")?
+            .parse()?;
+        tree.insert_batch(
+            &BTreeMap::from([(index as u32, new_note.commitment)]),
+            &hasher,
+        )?;
+
+        let is_used: bool = app.wrap().query_wasm_smart(
+            &addr,
+            &QueryMsg::NullifierUsed {
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            },
+        )?;
+        assert!(is_used, "Old note must be spent");
+
+        note = new_note;
+    }
+
+    let contract_root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(
+        contract_root,
+        serialize_to_base64(&tree.root()),
+        "Invalid contract utxo root"
+    );
+    assert_eq!(
+        note.balances,
+        [750_000, 0, 0, 0, 0, 0, 0].map(Fr::from),
+        "Invalid accumulated balances"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn last_proof_round_trips() -> Result<(), Box<dyn Error>> {
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    for store_proofs in [false, true] {
+        let (mut app, addr, _, hasher, mut rng) =
+            init_with(|msg| msg.store_proofs = Some(store_proofs))?;
+
+        let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+        let proof = prove_first_deposit(&hasher, &mut rng, &note)?;
+        app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: proof.clone(),
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds(amounts),
+        )?;
+
+        let last_proof = |action: &str| {
+            app.wrap().query_wasm_smart::<Option<String>>(
+                &addr,
+                &QueryMsg::LastProof {
+                    action: action.to_string(),
+                },
+            )
+        };
+        assert_eq!(last_proof("withdraw")?, None);
+
+        let stored = last_proof("deposit")?;
+        if !store_proofs {
+            assert_eq!(stored, None, "Proofs must not be stored by default");
+            continue;
+        }
+        assert_eq!(stored.as_ref(), Some(&proof));
+
+        let stored = Proof::<Bn254>::deserialize_compressed(
+            &base64::decode(stored.expect("proof is stored"))?[..],
+        )?;
+        let is_valid = Groth16::<Bn254, LibsnarkReduction>::verify(
+            &KEY.1,
+            &[
+                Fr::zero(),
+                Fr::zero(),
+                PoseidonHash::crh(&hasher, &note.balances)?,
+                Fr::zero(),
+                Fr::zero(),
+                note.commitment,
+            ],
+            &stored,
+        )?;
+        assert!(is_valid, "Stored proof must re-verify");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn deposit_with_tag() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    let mut deposit = |tag: String| -> Result<_, Box<dyn Error>> {
+        let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+        Ok(app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                tag: Some(tag),
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds(amounts),
+        ))
+    };
+
+    // The tag is not part of the proof, so it doesn't affect verification
+    let response = deposit("frontend-a".to_string())??;
+    assert_eq!(
+        wasm_attribute(&response, "tag").as_deref(),
+        Some("frontend-a")
+    );
+
+    let err = deposit("a".repeat(MAX_TAG_LENGTH + 1))?.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::TagTooLong(MAX_TAG_LENGTH))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_stale_bound_height() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init_with(|msg| msg.max_proof_age = Some(10))?;
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+    let deposit = |app: &mut App, note: &Note, proof: String, bound_height: Option<u64>| {
+        app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof,
+                tag: None,
+                bound_height,
+                asset_bitmap: None,
+            },
+            &deposit_funds(amounts),
+        )
+    };
+
+    let bound_height = app.block_info().height;
+    let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+    let proof = prove_first_deposit_with_aux(&hasher, &mut rng, &note, Fr::from(bound_height))?;
+
+    // Proofs must be bound to a height once a maximum age is set
+    let unbound = prove_first_deposit(&hasher, &mut rng, &note)?;
+    let err = deposit(&mut app, &note, unbound, None).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::StaleProof(10))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    // The claimed height must be the one the proof is bound to
+    let err = deposit(&mut app, &note, proof.clone(), Some(bound_height - 1)).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidProof)
+        ),
+        "Unexpected error {err:?}"
+    );
+    let err = deposit(&mut app, &note, proof.clone(), Some(bound_height + 1)).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref::<ContractError>(), Some(ContractError::FutureBoundHeight(h)) if *h == bound_height + 1),
+        "Unexpected error {err:?}"
+    );
+
+    // Replaying the proof once it is older than the maximum age
+    app.update_block(|block| block.height += 11);
+    let err = deposit(&mut app, &note, proof.clone(), Some(bound_height)).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::StaleProof(10))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    app.update_block(|block| block.height -= 1);
+    deposit(&mut app, &note, proof, Some(bound_height))?;
+
+    Ok(())
+}
+
+#[test]
+fn parse_deposit_event() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+    let amounts = [500_000, 0, 0, 0, 0, 0, 0];
+
+    // A first note so that the parsed index isn't trivially zero
+    deposit_new_note(
+        &mut app, &addr, &mut tree, &hasher, &mut rng, &USER_1, amounts,
+    )?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, amounts)?;
+    let response = app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        },
+        &deposit_funds(amounts),
+    )?;
+    tree.insert_batch(&BTreeMap::from([(1, note.commitment)]), &hasher)?;
+
+    let attrs = response
+        .events
+        .iter()
+        .filter(|e| e.ty == "wasm")
+        .flat_map(|e| e.attributes.iter())
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<Vec<_>>();
+    let event = encoding::parse_deposit_event::<Fr>(&attrs)?;
+
+    assert_eq!(
+        event,
+        encoding::DepositEvent {
+            index: 1,
+            new_root: tree.root(),
+            leaf: note.commitment,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_note_vectors() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    // Vectors holding more than the initial user balance can't be funded
+    let vectors = test_vectors::note_vectors()?
+        .into_iter()
+        .filter(|vector| vector.amounts.iter().all(|amount| *amount <= 100_000_000));
+    for (index, vector) in vectors.enumerate() {
+        let note = Note::new(
+            &hasher,
+            vector.address_field,
+            vector.nullifier,
+            vector.blinding,
+            vector.balances(),
+        )?;
+        assert_eq!(note.commitment, vector.note, "{}: note", vector.name);
+
+        let response = app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&vector.note),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &deposit_funds(vector.amounts),
+        )?;
+        tree.insert_batch(&BTreeMap::from([(index as u32, vector.note)]), &hasher)?;
+
+        let attrs = response
+            .events
+            .iter()
+            .filter(|e| e.ty == "wasm")
+            .flat_map(|e| e.attributes.iter())
+            .map(|a| (a.key.clone(), a.value.clone()))
+            .collect::<Vec<_>>();
+        let event = encoding::parse_deposit_event::<Fr>(&attrs)?;
+        assert_eq!(event.leaf, vector.note, "{}: leaf", vector.name);
+        assert_eq!(event.new_root, tree.root(), "{}: root", vector.name);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn deposit_asset_bitmap() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+        AssetBitmapCircuitBn254::<{ N_ASSETS }>::empty(&hasher),
+        &mut OsRng,
+    )?;
+    let mut vk_bytes = vec![];
+    vk.serialize_uncompressed(&mut vk_bytes)?;
+
+    let amounts = [500_000, 0, 0, 0, 0, 0, 7];
+    let deposit = |app: &mut App, addr: &Addr, bitmap: u64| -> Result<_, Box<dyn Error>> {
+        let note = new_note(&hasher, &mut OsRng, &USER_1, amounts)?;
+        let bitmap_proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+            &pk,
+            AssetBitmapCircuitBn254::<{ N_ASSETS }> {
+                note: note.commitment,
+                bitmap: Fr::from(encoding::asset_bitmap(&note.balances)),
+                address: note.address,
+                nullifier: note.nullifier,
+                note_blinding: note.blinding,
+                note_balances: note.balances,
+                parameters: hasher.clone(),
+                _hg: std::marker::PhantomData,
+            },
+            &mut OsRng,
+        )?;
+
+        Ok(app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut OsRng, &note)?,
+                tag: None,
+                bound_height: None,
+                asset_bitmap: Some(AssetBitmap {
+                    bitmap,
+                    proof: serialize_to_base64(&bitmap_proof),
+                }),
+            },
+            &deposit_funds(amounts),
+        ))
+    };
+
+    let (mut app, addr, ..) = init()?;
+    let err = deposit(&mut app, &addr, 0b1000001)?.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::AssetBitmapNotSupported)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let (mut app, addr, ..) =
+        init_with(|msg| msg.asset_bitmap_circuit_vk = Some(base64::encode(&vk_bytes)))?;
+    let response = deposit(&mut app, &addr, 0b1000001)??;
+    assert_eq!(
+        wasm_attribute(&response, "asset_bitmap").as_deref(),
+        Some("65")
+    );
+
+    // The bitmap proof of the note doesn't hold for another bitmap
+    let err = deposit(&mut app, &addr, 0b0000001)?.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidProof)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_no_matching_assets() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+    let funds = vec![Coin::new(500_000, "uunlisted")];
+    app.init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &USER_1, funds.clone())
+    })?;
+
+    // The zero diff balance root of unlisted funds would otherwise verify
+    let note = new_note(&hasher, &mut rng, &USER_1, [0; N_ASSETS])?;
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr,
+            &ExecuteMsg::Deposit {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                tag: None,
+                bound_height: None,
+                asset_bitmap: None,
+            },
+            &funds,
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::NoMatchingAssets)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deposit_compressed_vk() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init_with(|msg| msg.compress_vk = Some(true))?;
+
+    let stored_vk: Vec<u8> = cosmwasm_std::from_slice(
+        &app.wrap()
+            .query_wasm_raw(&addr, b"main_circuit_vk".as_slice())?
+            .ok_or("VK must be stored")?,
+    )?;
+    assert_eq!(stored_vk.len(), KEY.1.compressed_size());
+
+    deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    let root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(root, serialize_to_base64(&tree.root()));
+
+    Ok(())
+}
@@ -1,26 +1,54 @@
+mod admin;
 mod deposit;
+mod query;
+mod split;
 mod swap;
 mod withdraw;
 
 use std::error::Error;
 
+use std::collections::BTreeMap;
+
 use ark_bn254::{Bn254, Fr};
 use ark_crypto_primitives::{snark::SNARK, sponge::poseidon::PoseidonConfig};
 use ark_ff::PrimeField;
-use ark_groth16::{Groth16, ProvingKey, VerifyingKey};
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
 use ark_serialize::CanonicalSerialize;
+use ark_std::{UniformRand, Zero};
 use circuits::{
-    merkle_tree::SparseMerkleTree, poseidon::PoseidonHash, utils::poseidon_bn254, MainCircuitBn254,
-    N_ASSETS, TREE_DEPTH,
+    merkle_tree::{compute_empty_hashes, Path, SparseMerkleTree},
+    poseidon::PoseidonHash,
+    utils::poseidon_bn254,
+    MainCircuitBn254, SplitCircuitBn254, N_ASSETS, N_SPLIT_NOTES, TREE_DEPTH,
+};
+use cosmwasm_std::{
+    from_binary,
+    testing::{mock_dependencies, mock_env, mock_info, MockStorage},
+    Addr, Coin,
 };
-use cosmwasm_std::{Addr, Coin};
-use cw_multi_test::{App, ContractWrapper, Executor};
+use cw_merkle_tree::MerkleTree;
+use cw_multi_test::{App, AppResponse, ContractWrapper, Executor};
 use lazy_static::lazy_static;
-use rand::rngs::OsRng;
+use rand::{
+    rngs::{OsRng, StdRng},
+    SeedableRng,
+};
 
-use crate::{execute, instantiate, msg::InstantiateMsg, query};
+use crate::{
+    error::ContractError,
+    execute,
+    hasher::PoseidonHasher,
+    insert_note, instantiate,
+    msg::{ExecuteMsg, InstantiateMsg, QueryMsg, VkCheck},
+    query, reply,
+    state::{ROOT_HISTORY, TRACK_ALL_ROOTS, TREE},
+    MAIN_CIRCUIT_PUBLIC_INPUTS,
+};
 
 type Circuit = MainCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+type SplitCircuit = SplitCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }, { N_SPLIT_NOTES }>;
+type Tree = SparseMerkleTree<Fr, PoseidonHash<Fr>, TREE_DEPTH>;
 
 const ASSETS: [&str; N_ASSETS] = ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"];
 
@@ -28,11 +56,19 @@ lazy_static! {
     static ref USER_1: Addr = Addr::unchecked("user_1");
     static ref ADMIN: Addr = Addr::unchecked("admin");
     static ref KEY: (ProvingKey<Bn254>, VerifyingKey<Bn254>) =
-        Groth16::<Bn254>::circuit_specific_setup(
-            Circuit::empty_without_tree(&poseidon_bn254()),
-            &mut OsRng,
-        )
-        .expect("setup failed");
+        seeded_setup(Circuit::empty_without_tree(&poseidon_bn254()));
+    static ref SPLIT_KEY: (ProvingKey<Bn254>, VerifyingKey<Bn254>) =
+        seeded_setup(SplitCircuit::empty_without_tree(&poseidon_bn254()));
+}
+
+/// Seed of the test keys, so they are the same across runs.
+const KEY_SEED: u64 = 0;
+
+fn seeded_setup<C: ConstraintSynthesizer<Fr>>(
+    circuit: C,
+) -> (ProvingKey<Bn254>, VerifyingKey<Bn254>) {
+    Groth16::<Bn254>::circuit_specific_setup(circuit, &mut StdRng::seed_from_u64(KEY_SEED))
+        .expect("setup failed")
 }
 
 fn serialize_to_base64<T: CanonicalSerialize>(value: &T) -> String {
@@ -43,16 +79,16 @@ fn serialize_to_base64<T: CanonicalSerialize>(value: &T) -> String {
     base64::encode(bytes)
 }
 
-fn init() -> Result<
-    (
-        App,
-        Addr,
-        SparseMerkleTree<Fr, PoseidonHash<Fr>, TREE_DEPTH>,
-        PoseidonConfig<Fr>,
-        OsRng,
-    ),
-    Box<dyn Error>,
-> {
+#[allow(clippy::type_complexity)]
+fn init() -> Result<(App, Addr, Tree, PoseidonConfig<Fr>, OsRng), Box<dyn Error>> {
+    init_with(|_| {})
+}
+
+/// Same as [`init`], with the instantiate message adjusted by `configure`.
+#[allow(clippy::type_complexity)]
+fn init_with(
+    configure: impl FnOnce(&mut InstantiateMsg),
+) -> Result<(App, Addr, Tree, PoseidonConfig<Fr>, OsRng), Box<dyn Error>> {
     let mut app = App::new(|r, _api, storage| {
         r.bank
             .init_balance(
@@ -62,19 +98,16 @@ fn init() -> Result<
             )
             .expect("init balance failed");
     });
-    let code = ContractWrapper::new(execute, instantiate, query);
+    let code = ContractWrapper::new(execute, instantiate, query).with_reply(reply);
     let code_id = app.store_code(Box::new(code));
     let hasher = poseidon_bn254();
     let (_, tree) = Circuit::empty(&hasher);
-    let mut vk_bytes = vec![];
-    KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    let mut msg = instantiate_msg()?;
+    configure(&mut msg);
     let addr = app.instantiate_contract(
         code_id,
         ADMIN.clone(),
-        &InstantiateMsg {
-            assets: ASSETS.map(String::from),
-            main_circuit_vk: base64::encode(vk_bytes),
-        },
+        &msg,
         &[],
         "main",
         Some(ADMIN.to_string()),
@@ -83,6 +116,223 @@ fn init() -> Result<
     Ok((app, addr, tree, hasher, OsRng))
 }
 
+/// Instantiate message of [`init`], with the test key and no optional feature enabled.
+fn instantiate_msg() -> Result<InstantiateMsg, Box<dyn Error>> {
+    let mut vk_bytes = vec![];
+    KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    Ok(InstantiateMsg {
+        assets: ASSETS.map(String::from),
+        main_circuit_vk: base64::encode(vk_bytes),
+        main_circuit_vk_check: None,
+        compress_vk: None,
+        unique_notes: None,
+        split_circuit_vk: None,
+        asset_bitmap_circuit_vk: None,
+        min_deposits: None,
+        store_proofs: None,
+        genesis_notes: None,
+        decimals: None,
+        max_proof_age: None,
+        track_all_roots: None,
+    })
+}
+
+/// Secrets and commitments of a single note, as tracked by a client.
+#[derive(Debug, Clone, Copy)]
+struct Note {
+    address: Fr,
+    nullifier: Fr,
+    blinding: Fr,
+    balances: [Fr; N_ASSETS],
+    identifier: Fr,
+    commitment: Fr,
+    nullifier_hash: Fr,
+}
+
+impl Note {
+    fn new(
+        hasher: &PoseidonConfig<Fr>,
+        address: Fr,
+        nullifier: Fr,
+        blinding: Fr,
+        balances: [Fr; N_ASSETS],
+    ) -> Result<Self, Box<dyn Error>> {
+        let identifier = PoseidonHash::tto_crh(hasher, address, blinding)?;
+        let commitment = PoseidonHash::crh(
+            hasher,
+            &[PoseidonHash::crh(hasher, &balances)?, identifier, nullifier],
+        )?;
+        let nullifier_hash = PoseidonHash::tto_crh(hasher, commitment, nullifier)?;
+
+        Ok(Self {
+            address,
+            nullifier,
+            blinding,
+            balances,
+            identifier,
+            commitment,
+            nullifier_hash,
+        })
+    }
+}
+
+/// Returns the value of the `key` attribute emitted by the contract.
+fn wasm_attribute(response: &AppResponse, key: &str) -> Option<String> {
+    response
+        .events
+        .iter()
+        .filter(|e| e.ty == "wasm")
+        .flat_map(|e| e.attributes.iter())
+        .find(|a| a.key == key)
+        .map(|a| a.value.clone())
+}
+
+/// Creates a brand new note of `sender` holding `amounts` of each asset.
+fn new_note(
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+    sender: &Addr,
+    amounts: [u128; N_ASSETS],
+) -> Result<Note, Box<dyn Error>> {
+    Note::new(
+        hasher,
+        Fr::from_le_bytes_mod_order(sender.as_bytes()),
+        Fr::rand(rng),
+        Fr::rand(rng),
+        amounts.map(Fr::from),
+    )
+}
+
+/// Proves the first time deposit of `note`, returning the base64 encoded proof.
+fn prove_first_deposit(
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+    note: &Note,
+) -> Result<String, Box<dyn Error>> {
+    prove_first_deposit_with_aux(hasher, rng, note, Fr::zero())
+}
+
+/// Same as [`prove_first_deposit`], with the proof bound to `aux`.
+fn prove_first_deposit_with_aux(
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+    note: &Note,
+    aux: Fr,
+) -> Result<String, Box<dyn Error>> {
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+        &KEY.0,
+        Circuit {
+            address: note.address,
+            nullifier: note.nullifier,
+            aux,
+            utxo_root: Fr::zero(),
+            diff_balance_root: PoseidonHash::crh(hasher, &note.balances)?,
+            diff_balances: note.balances,
+            old_note_nullifier_hash: Fr::zero(),
+            old_note_identifier: Fr::zero(),
+            old_note_path: Path::empty(),
+            old_note_balances: [Fr::zero(); N_ASSETS],
+            new_note: note.commitment,
+            new_note_blinding: note.blinding,
+            new_note_balances: note.balances,
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        },
+        rng,
+    )?;
+
+    Ok(serialize_to_base64(&proof))
+}
+
+/// Funds sent for depositing `amounts` of each asset.
+fn deposit_funds(amounts: [u128; N_ASSETS]) -> Vec<Coin> {
+    ASSETS
+        .iter()
+        .zip(amounts)
+        .filter(|(_, amount)| *amount > 0)
+        .map(|(denom, amount)| Coin::new(amount, *denom))
+        .collect()
+}
+
+/// Deposits `amounts` of each asset from `sender` into a brand new note, and mirrors the
+/// inserted leaf into the local `tree`.
+fn deposit_new_note(
+    app: &mut App,
+    addr: &Addr,
+    tree: &mut Tree,
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+    sender: &Addr,
+    amounts: [u128; N_ASSETS],
+) -> Result<Note, Box<dyn Error>> {
+    let note = new_note(hasher, rng, sender, amounts)?;
+    let proof = prove_first_deposit(hasher, rng, &note)?;
+
+    let response = app.execute_contract(
+        sender.clone(),
+        addr.clone(),
+        &ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        },
+        &deposit_funds(amounts),
+    )?;
+
+    let index = wasm_attribute(&response, "index")
+        .ok_or("missing index attribute")?
+        .parse()?;
+    tree.insert_batch(&BTreeMap::from([(index, note.commitment)]), hasher)?;
+
+    Ok(note)
+}
+
+/// Proves the transition from the `old` note at `index` of `tree` into the `new` note, returning
+/// the base64 encoded proof.
+fn prove_transition(
+    tree: &Tree,
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+    index: u64,
+    old: &Note,
+    new: &Note,
+    aux: Fr,
+) -> Result<String, Box<dyn Error>> {
+    let mut diff_balances = new.balances;
+    for (diff, old_balance) in diff_balances.iter_mut().zip(old.balances) {
+        *diff -= old_balance;
+    }
+
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+        &KEY.0,
+        Circuit {
+            address: new.address,
+            nullifier: new.nullifier,
+            aux,
+            utxo_root: tree.root(),
+            diff_balance_root: PoseidonHash::crh(hasher, &diff_balances)?,
+            diff_balances,
+            old_note_nullifier_hash: old.nullifier_hash,
+            old_note_identifier: old.identifier,
+            old_note_path: tree.generate_membership_proof(index),
+            old_note_balances: old.balances,
+            new_note: new.commitment,
+            new_note_blinding: new.blinding,
+            new_note_balances: new.balances,
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        },
+        rng,
+    )?;
+
+    Ok(serialize_to_base64(&proof))
+}
+
 #[test]
 fn correct_bytes_serialization() -> Result<(), Box<dyn Error>> {
     let f = Fr::from(12829382362812u128);
@@ -93,3 +343,331 @@ fn correct_bytes_serialization() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn same_empty_hashes() -> Result<(), Box<dyn Error>> {
+    let (app, addr, _, hasher, _) = init()?;
+
+    let (_, zeros): (Vec<String>, Vec<String>) = cosmwasm_std::from_slice(
+        &app.wrap()
+            .query_wasm_raw(&addr, b"t_hashes".as_slice())?
+            .ok_or("missing tree hashes")?,
+    )?;
+    let empty_hashes =
+        compute_empty_hashes::<Fr, PoseidonHash<Fr>, TREE_DEPTH>(&hasher, &Fr::zero())?;
+
+    assert_eq!(zeros.len(), TREE_DEPTH, "Invalid tree depth");
+    assert_eq!(
+        zeros,
+        empty_hashes.map(|h| serialize_to_base64(&h)).to_vec(),
+        "Contract and circuit empty hashes differ"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hash_many_matches_crh() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let inputs = [Fr::from(1u64), Fr::rand(&mut OsRng), Fr::rand(&mut OsRng)];
+
+    let hashed = PoseidonHasher(&hasher).hash_many(&inputs.map(|i| serialize_to_base64(&i)))?;
+
+    assert_eq!(
+        hashed,
+        serialize_to_base64(&PoseidonHash::crh(&hasher, &inputs)?),
+        "Invalid hash"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn evicted_root() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let mut storage = MockStorage::new();
+    TREE.init(
+        &mut storage,
+        TREE_DEPTH as u8,
+        serialize_to_base64(&Fr::zero()),
+        &PoseidonHasher(&hasher),
+    )?;
+
+    let mut roots = vec![];
+    for i in 0..ROOT_HISTORY as u64 + 2 {
+        let (_, new_root, evicted_root) = insert_note(
+            &mut storage,
+            i,
+            &serialize_to_base64(&Fr::from(i + 1)),
+            &hasher,
+        )?;
+
+        // The first roots are evicted in order once the history is full
+        let expected =
+            (i >= ROOT_HISTORY as u64).then(|| &roots[(i - ROOT_HISTORY as u64) as usize]);
+        assert_eq!(
+            evicted_root.as_ref(),
+            expected,
+            "Invalid evicted root at {i}"
+        );
+        roots.push(new_root);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_created_at() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let mut deps = mock_dependencies();
+    TRACK_ALL_ROOTS.save(&mut deps.storage, &true)?;
+    TREE.init(
+        &mut deps.storage,
+        TREE_DEPTH as u8,
+        serialize_to_base64(&Fr::zero()),
+        &PoseidonHasher(&hasher),
+    )?;
+
+    let mut roots = vec![];
+    for height in 0..ROOT_HISTORY as u64 + 1 {
+        let (_, new_root, _) = insert_note(
+            &mut deps.storage,
+            height,
+            &serialize_to_base64(&Fr::from(height + 1)),
+            &hasher,
+        )?;
+        roots.push(new_root);
+    }
+
+    let created_at = |root: &str| -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(from_binary(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RootCreatedAt {
+                root: root.to_string(),
+            },
+        )?)?)
+    };
+
+    // The first root is no longer in the history, but is still reported
+    assert!(!TREE.is_valid_root(&deps.storage, &roots[0])?);
+    assert_eq!(created_at(&roots[0])?, Some(0));
+    assert_eq!(
+        created_at(&roots[ROOT_HISTORY as usize])?,
+        Some(ROOT_HISTORY as u64)
+    );
+    assert_eq!(created_at(&serialize_to_base64(&Fr::from(1)))?, None);
+
+    Ok(())
+}
+
+#[test]
+fn insert_zero_note() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let mut storage = MockStorage::new();
+    TREE.init(
+        &mut storage,
+        TREE_DEPTH as u8,
+        serialize_to_base64(&Fr::zero()),
+        &PoseidonHasher(&hasher),
+    )?;
+
+    let err = insert_note(&mut storage, 0, &serialize_to_base64(&Fr::zero()), &hasher).unwrap_err();
+    assert!(
+        matches!(err, ContractError::ZeroNote),
+        "Unexpected error {err:?}"
+    );
+    insert_note(&mut storage, 0, &serialize_to_base64(&Fr::from(1)), &hasher)?;
+
+    Ok(())
+}
+
+#[test]
+fn genesis_notes() -> Result<(), Box<dyn Error>> {
+    let genesis_notes = [1, 2, 3].map(Fr::from);
+    let (app, addr, mut tree, hasher, _) = init_with(|msg| {
+        msg.genesis_notes = Some(genesis_notes.iter().map(serialize_to_base64).collect())
+    })?;
+
+    tree.insert_batch(&BTreeMap::from_iter((0..).zip(genesis_notes)), &hasher)?;
+    let root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(root, serialize_to_base64(&tree.root()));
+
+    Ok(())
+}
+
+#[test]
+fn instantiate_wrong_arity_vk() -> Result<(), Box<dyn Error>> {
+    let mut vk_bytes = vec![];
+    SPLIT_KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+
+    let err = instantiate(
+        mock_dependencies().as_mut(),
+        mock_env(),
+        mock_info(ADMIN.as_str(), &[]),
+        InstantiateMsg {
+            assets: ASSETS.map(String::from),
+            main_circuit_vk: base64::encode(vk_bytes),
+            main_circuit_vk_check: None,
+            compress_vk: None,
+            unique_notes: None,
+            split_circuit_vk: None,
+            asset_bitmap_circuit_vk: None,
+            min_deposits: None,
+            store_proofs: None,
+            genesis_notes: None,
+            decimals: None,
+            max_proof_age: None,
+            track_all_roots: None,
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            ContractError::VkPublicInputsMismatch {
+                expected: MAIN_CIRCUIT_PUBLIC_INPUTS,
+                ..
+            }
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+/// Proves the empty first time deposit checking that `pk` was built for `M_ASSETS` assets.
+fn prove_vk_check<const M_ASSETS: usize>(
+    pk: &ProvingKey<Bn254>,
+    hasher: &PoseidonConfig<Fr>,
+    rng: &mut OsRng,
+) -> Result<VkCheck, Box<dyn Error>> {
+    let circuit = MainCircuitBn254::<M_ASSETS, { TREE_DEPTH }>::vk_check(
+        Fr::from_le_bytes_mod_order(ADMIN.as_bytes()),
+        Fr::rand(rng),
+        Fr::rand(rng),
+        hasher,
+    )?;
+    let new_note = serialize_to_base64(&circuit.new_note);
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(pk, circuit, rng)?;
+
+    Ok(VkCheck {
+        new_note,
+        proof: serialize_to_base64(&proof),
+    })
+}
+
+#[test]
+fn instantiate_other_assets_vk() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let (other_pk, other_vk) = Groth16::<Bn254>::circuit_specific_setup(
+        MainCircuitBn254::<3, { TREE_DEPTH }>::empty_without_tree(&hasher),
+        &mut OsRng,
+    )?;
+    let instantiate_with = |vk: &VerifyingKey<Bn254>, check: VkCheck| {
+        let mut vk_bytes = vec![];
+        vk.serialize_uncompressed(&mut vk_bytes)
+            .expect("failed to serialize");
+        instantiate(
+            mock_dependencies().as_mut(),
+            mock_env(),
+            mock_info(ADMIN.as_str(), &[]),
+            InstantiateMsg {
+                assets: ASSETS.map(String::from),
+                main_circuit_vk: base64::encode(vk_bytes),
+                main_circuit_vk_check: Some(check),
+                compress_vk: None,
+                unique_notes: None,
+                split_circuit_vk: None,
+                asset_bitmap_circuit_vk: None,
+                min_deposits: None,
+                store_proofs: None,
+                genesis_notes: None,
+                decimals: None,
+                max_proof_age: None,
+                track_all_roots: None,
+            },
+        )
+    };
+
+    // The VK of 3 assets has as many public inputs as the one of `N_ASSETS`
+    assert_eq!(other_vk.gamma_abc_g1.len(), KEY.1.gamma_abc_g1.len());
+    let err = instantiate_with(
+        &other_vk,
+        prove_vk_check::<3>(&other_pk, &hasher, &mut OsRng)?,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::VkAssetsMismatch(N_ASSETS)),
+        "Unexpected error {err:?}"
+    );
+
+    instantiate_with(
+        &KEY.1,
+        prove_vk_check::<{ N_ASSETS }>(&KEY.0, &hasher, &mut OsRng)?,
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn seeded_setup_reproducible() {
+    let (pk, vk) = seeded_setup(Circuit::empty_without_tree(&poseidon_bn254()));
+
+    assert_eq!(serialize_to_base64(&vk), serialize_to_base64(&KEY.1));
+    assert_eq!(serialize_to_base64(&pk), serialize_to_base64(&KEY.0));
+}
+
+#[test]
+fn instantiate_corrupted_vk() -> Result<(), Box<dyn Error>> {
+    let mut vk_bytes = vec![];
+    KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    // Moves `alpha_g1` off the curve
+    vk_bytes[0] ^= 1;
+    let corrupted = base64::encode(vk_bytes);
+
+    let mut msg = InstantiateMsg {
+        assets: ASSETS.map(String::from),
+        main_circuit_vk: corrupted.clone(),
+        main_circuit_vk_check: None,
+        compress_vk: None,
+        unique_notes: None,
+        split_circuit_vk: None,
+        asset_bitmap_circuit_vk: None,
+        min_deposits: None,
+        store_proofs: None,
+        genesis_notes: None,
+        decimals: None,
+        max_proof_age: None,
+        track_all_roots: None,
+    };
+    let err = instantiate(
+        mock_dependencies().as_mut(),
+        mock_env(),
+        mock_info(ADMIN.as_str(), &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidVerifyingKey(_)),
+        "Unexpected error {err:?}"
+    );
+
+    let mut vk_bytes = vec![];
+    KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    msg.main_circuit_vk = base64::encode(vk_bytes);
+    msg.split_circuit_vk = Some(corrupted);
+    let err = instantiate(
+        mock_dependencies().as_mut(),
+        mock_env(),
+        mock_info(ADMIN.as_str(), &[]),
+        msg,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidVerifyingKey(_)),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, error::Error};
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use ark_std::{UniformRand, Zero};
+use circuits::poseidon::PoseidonHash;
+use cosmwasm_std::Uint128;
+use cw_multi_test::Executor;
+
+use crate::{
+    error::ContractError,
+    msg::{
+        ConfigResponse, ExecuteMsg, HealthResponse, IndexedNotesResponse, NotesPageResponse,
+        NotesResponse, QueryMsg, SpentNullifiersResponse,
+    },
+    test::{
+        deposit_new_note, init, init_with, new_note, prove_transition, serialize_to_base64, Note,
+        ASSETS, USER_1,
+    },
+    QUERY_LIMIT,
+};
+
+#[test]
+fn spent_nullifiers_paginated() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let mut notes = vec![];
+    for _ in 0..3 {
+        notes.push(deposit_new_note(
+            &mut app,
+            &addr,
+            &mut tree,
+            &hasher,
+            &mut rng,
+            &USER_1,
+            [500_000, 0, 0, 0, 0, 0, 0],
+        )?);
+    }
+
+    let mut spent = vec![];
+    for (index, note) in notes.iter().enumerate() {
+        let new_note = Note::new(
+            &hasher,
+            note.address,
+            note.nullifier,
+            Fr::rand(&mut rng),
+            [400_000, 0, 0, 0, 0, 0, 0].map(Fr::from),
+        )?;
+        let proof = prove_transition(
+            &tree,
+            &hasher,
+            &mut rng,
+            index as u64,
+            note,
+            &new_note,
+            Fr::zero(),
+        )?;
+        app.execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Withdraw {
+                assets: BTreeMap::from([(ASSETS[0].to_string(), Uint128::new(100_000))]),
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                blinding: serialize_to_base64(&note.blinding),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+            },
+            &[],
+        )?;
+        tree.insert_batch(
+            &BTreeMap::from([(3 + index as u32, new_note.commitment)]),
+            &hasher,
+        )?;
+        spent.push(serialize_to_base64(&note.nullifier_hash));
+    }
+    spent.sort_by_key(|n| base64::decode(n).expect("invalid base64"));
+
+    let mut paginated = vec![];
+    let mut start_after = None;
+    loop {
+        let response: SpentNullifiersResponse = app.wrap().query_wasm_smart(
+            &addr,
+            &QueryMsg::SpentNullifiers {
+                limit: Some(2),
+                start_after: start_after.clone(),
+            },
+        )?;
+        if response.nullifiers.is_empty() {
+            break;
+        }
+        assert!(response.nullifiers.len() <= 2, "Limit not applied");
+        start_after = response.nullifiers.last().cloned();
+        paginated.extend(response.nullifiers);
+    }
+
+    assert_eq!(paginated, spent, "Invalid spent nullifiers");
+
+    Ok(())
+}
+
+#[test]
+fn indexed_notes_descending() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let mut notes = vec![];
+    for index in 0..3 {
+        let note = deposit_new_note(
+            &mut app,
+            &addr,
+            &mut tree,
+            &hasher,
+            &mut rng,
+            &USER_1,
+            [500_000, 0, 0, 0, 0, 0, 0],
+        )?;
+        notes.push((index, serialize_to_base64(&note.commitment)));
+    }
+
+    let query = |start_after, is_ascending| {
+        app.wrap()
+            .query_wasm_smart::<IndexedNotesResponse>(
+                &addr,
+                &QueryMsg::IndexedNotes {
+                    limit: Some(2),
+                    start_after,
+                    is_ascending: Some(is_ascending),
+                },
+            )
+            .map(|response| response.notes)
+    };
+
+    assert_eq!(
+        query(None, false)?,
+        vec![notes[2].clone(), notes[1].clone()]
+    );
+    assert_eq!(query(Some(1), false)?, vec![notes[0].clone()]);
+    assert_eq!(query(None, true)?, notes[..2].to_vec());
+    assert_eq!(query(Some(1), true)?, notes[2..].to_vec());
+
+    Ok(())
+}
+
+#[test]
+fn notes_at_scattered_indices() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let mut notes = vec![];
+    for _ in 0..4 {
+        let note = deposit_new_note(
+            &mut app,
+            &addr,
+            &mut tree,
+            &hasher,
+            &mut rng,
+            &USER_1,
+            [500_000, 0, 0, 0, 0, 0, 0],
+        )?;
+        notes.push(serialize_to_base64(&note.commitment));
+    }
+
+    let notes_at: Vec<Option<String>> = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NotesAt {
+            indices: vec![3, 7, 0, 2, 0],
+        },
+    )?;
+    assert_eq!(
+        notes_at,
+        vec![
+            Some(notes[3].clone()),
+            None,
+            Some(notes[0].clone()),
+            Some(notes[2].clone()),
+            Some(notes[0].clone()),
+        ]
+    );
+
+    let err = app
+        .wrap()
+        .query_wasm_smart::<Vec<Option<String>>>(
+            &addr,
+            &QueryMsg::NotesAt {
+                indices: (0..=QUERY_LIMIT).collect(),
+            },
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&ContractError::TooManyIndices(QUERY_LIMIT).to_string()),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn config_decimals() -> Result<(), Box<dyn Error>> {
+    let decimals = [6, 18, 6, 6, 8, 18, 6];
+    let (app, addr, _, _, _) = init_with(|msg| msg.decimals = Some(decimals))?;
+
+    let config: ConfigResponse = app.wrap().query_wasm_smart(&addr, &QueryMsg::Config {})?;
+    assert_eq!(config.assets, ASSETS.map(String::from));
+    assert_eq!(config.decimals, Some(decimals));
+
+    let (app, addr, _, _, _) = init()?;
+    let config: ConfigResponse = app.wrap().query_wasm_smart(&addr, &QueryMsg::Config {})?;
+    assert_eq!(
+        config.decimals, None,
+        "Decimals must be none if not provided"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn health_after_instantiate() -> Result<(), Box<dyn Error>> {
+    let (app, addr, _, _, _) = init()?;
+
+    let health: HealthResponse = app.wrap().query_wasm_smart(&addr, &QueryMsg::Health {})?;
+    assert_eq!(
+        health,
+        HealthResponse {
+            admin_set: true,
+            vk_stored: true,
+            assets_count: ASSETS.len(),
+            tree_root_present: true,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn compute_note() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    let commitment: String = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::ComputeNote {
+            balance_root: serialize_to_base64(&PoseidonHash::crh(&hasher, &note.balances)?),
+            identifier: serialize_to_base64(&note.identifier),
+            nullifier: serialize_to_base64(&note.nullifier),
+        },
+    )?;
+    let notes: Vec<Option<String>> = app
+        .wrap()
+        .query_wasm_smart(&addr, &QueryMsg::NotesAt { indices: vec![0] })?;
+    assert_eq!(notes, vec![Some(commitment)]);
+
+    Ok(())
+}
+
+#[test]
+fn notes_page_visits_all() -> Result<(), Box<dyn Error>> {
+    let genesis_notes = (1..=7).map(Fr::from).collect::<Vec<_>>();
+    let (app, addr, ..) = init_with(|msg| {
+        msg.genesis_notes = Some(genesis_notes.iter().map(serialize_to_base64).collect())
+    })?;
+
+    for is_ascending in [true, false] {
+        let mut visited = vec![];
+        let mut cursor = None;
+        loop {
+            let page: NotesPageResponse = app.wrap().query_wasm_smart(
+                &addr,
+                &QueryMsg::NotesPage {
+                    cursor,
+                    page_size: Some(3),
+                    is_ascending: Some(is_ascending),
+                },
+            )?;
+            assert!(page.notes.len() <= 3, "Page exceeds its size");
+            visited.extend(page.notes);
+
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        let mut expected = genesis_notes
+            .iter()
+            .map(serialize_to_base64)
+            .enumerate()
+            .map(|(i, note)| (i as u64, note))
+            .collect::<Vec<_>>();
+        if !is_ascending {
+            expected.reverse();
+        }
+        assert_eq!(visited, expected, "Ascending {is_ascending}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn notes_limit_clamped() -> Result<(), Box<dyn Error>> {
+    let genesis_notes = (1..=QUERY_LIMIT + 1).map(Fr::from).collect::<Vec<_>>();
+    let (app, addr, ..) = init_with(|msg| {
+        msg.genesis_notes = Some(genesis_notes.iter().map(serialize_to_base64).collect())
+    })?;
+
+    for limit in [None, Some(u64::MAX)] {
+        let notes: NotesResponse = app.wrap().query_wasm_smart(
+            &addr,
+            &QueryMsg::Notes {
+                limit,
+                start_after: None,
+                is_ascending: None,
+            },
+        )?;
+        assert_eq!(notes.notes.len() as u64, QUERY_LIMIT);
+
+        let indexed: IndexedNotesResponse = app.wrap().query_wasm_smart(
+            &addr,
+            &QueryMsg::IndexedNotes {
+                limit,
+                start_after: None,
+                is_ascending: None,
+            },
+        )?;
+        assert_eq!(indexed.notes.len() as u64, QUERY_LIMIT);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn nullifier_hash_of() -> Result<(), Box<dyn Error>> {
+    let (app, addr, _, hasher, mut rng) = init()?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, [500_000, 0, 0, 0, 0, 0, 0])?;
+    let nullifier_hash: String = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NullifierHashOf {
+            note: serialize_to_base64(&note.commitment),
+            nullifier: serialize_to_base64(&note.nullifier),
+        },
+    )?;
+    assert_eq!(nullifier_hash, serialize_to_base64(&note.nullifier_hash));
+
+    // The modulus reduces to the zero nullifier but isn't its canonical encoding
+    let err = app
+        .wrap()
+        .query_wasm_smart::<String>(
+            &addr,
+            &QueryMsg::NullifierHashOf {
+                note: serialize_to_base64(&note.commitment),
+                nullifier: base64::encode(Fr::MODULUS.to_bytes_le()),
+            },
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&ContractError::NonCanonicalField.to_string()),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, error::Error};
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16};
+use ark_serialize::CanonicalSerialize;
+use ark_std::{UniformRand, Zero};
+use circuits::{poseidon::PoseidonHash, N_ASSETS};
+use cosmwasm_std::{Coin, Uint128};
+use cw_multi_test::Executor;
+
+use crate::{
+    error::ContractError,
+    msg::{AccountingDriftResponse, AssetAccounting, ExecuteMsg, QueryMsg},
+    test::{
+        deposit_new_note, init, init_with, serialize_to_base64, Note, SplitCircuit, ASSETS,
+        SPLIT_KEY, USER_1,
+    },
+};
+
+#[test]
+fn split_into_two_notes() -> Result<(), Box<dyn Error>> {
+    let mut vk_bytes = vec![];
+    SPLIT_KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    let (mut app, addr, mut tree, hasher, mut rng) =
+        init_with(|msg| msg.split_circuit_vk = Some(base64::encode(vk_bytes)))?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let mut split_note = |balances: [u128; N_ASSETS]| {
+        Note::new(
+            &hasher,
+            note.address,
+            note.nullifier,
+            Fr::rand(&mut rng),
+            balances.map(Fr::from),
+        )
+    };
+    let first = split_note([300_000, 0, 0, 0, 0, 0, 0])?;
+    let second = split_note([200_000, 0, 0, 0, 0, 0, 0])?;
+
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+        &SPLIT_KEY.0,
+        SplitCircuit {
+            address: note.address,
+            nullifier: note.nullifier,
+            aux: Fr::zero(),
+            utxo_root: tree.root(),
+            diff_balance_root: PoseidonHash::crh(&hasher, &[Fr::zero(); N_ASSETS])?,
+            diff_balances: [Fr::zero(); N_ASSETS],
+            old_note_nullifier_hash: note.nullifier_hash,
+            old_note_identifier: note.identifier,
+            old_note_path: tree.generate_membership_proof(0),
+            old_note_balances: note.balances,
+            new_notes: [first.commitment, second.commitment],
+            new_note_blindings: [first.blinding, second.blinding],
+            new_note_balances: [first.balances, second.balances],
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        },
+        &mut rng,
+    )?;
+
+    let msg = |new_notes: Vec<String>| ExecuteMsg::Split {
+        root: serialize_to_base64(&tree.root()),
+        nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+        identifier: serialize_to_base64(&note.identifier),
+        new_notes,
+        proof: serialize_to_base64(&proof),
+    };
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &msg(vec![serialize_to_base64(&first.commitment)]),
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidNoteCount(2))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let response = app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &msg(vec![
+            serialize_to_base64(&first.commitment),
+            serialize_to_base64(&second.commitment),
+        ]),
+        &[],
+    )?;
+    tree.insert_batch(
+        &BTreeMap::from([(1, first.commitment), (2, second.commitment)]),
+        &hasher,
+    )?;
+
+    let leaves = response
+        .events
+        .iter()
+        .filter(|e| e.ty == "wasm")
+        .flat_map(|e| e.attributes.iter())
+        .filter(|a| a.key == "leaf")
+        .map(|a| a.value.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        leaves,
+        vec![
+            serialize_to_base64(&first.commitment),
+            serialize_to_base64(&second.commitment),
+        ],
+        "Invalid inserted leaves"
+    );
+
+    let contract_root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(
+        contract_root,
+        serialize_to_base64(&tree.root()),
+        "Invalid final root"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn split_not_supported() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Split {
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_notes: vec![],
+                proof: String::new(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::SplitNotSupported)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn split_with_deposit_accounted() -> Result<(), Box<dyn Error>> {
+    let mut vk_bytes = vec![];
+    SPLIT_KEY.1.serialize_uncompressed(&mut vk_bytes)?;
+    let (mut app, addr, mut tree, hasher, mut rng) =
+        init_with(|msg| msg.split_circuit_vk = Some(base64::encode(vk_bytes)))?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    // 100_000 more of the first asset are deposited by the split
+    let mut split_note = |balances: [u128; N_ASSETS]| {
+        Note::new(
+            &hasher,
+            note.address,
+            note.nullifier,
+            Fr::rand(&mut rng),
+            balances.map(Fr::from),
+        )
+    };
+    let first = split_note([300_000, 0, 0, 0, 0, 0, 0])?;
+    let second = split_note([300_000, 0, 0, 0, 0, 0, 0])?;
+    let diff_balances = [100_000, 0, 0, 0, 0, 0, 0].map(Fr::from);
+
+    let proof = Groth16::<Bn254, LibsnarkReduction>::prove(
+        &SPLIT_KEY.0,
+        SplitCircuit {
+            address: note.address,
+            nullifier: note.nullifier,
+            aux: Fr::zero(),
+            utxo_root: tree.root(),
+            diff_balance_root: PoseidonHash::crh(&hasher, &diff_balances)?,
+            diff_balances,
+            old_note_nullifier_hash: note.nullifier_hash,
+            old_note_identifier: note.identifier,
+            old_note_path: tree.generate_membership_proof(0),
+            old_note_balances: note.balances,
+            new_notes: [first.commitment, second.commitment],
+            new_note_blindings: [first.blinding, second.blinding],
+            new_note_balances: [first.balances, second.balances],
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        },
+        &mut rng,
+    )?;
+    app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::Split {
+            root: serialize_to_base64(&tree.root()),
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            identifier: serialize_to_base64(&note.identifier),
+            new_notes: vec![
+                serialize_to_base64(&first.commitment),
+                serialize_to_base64(&second.commitment),
+            ],
+            proof: serialize_to_base64(&proof),
+        },
+        &[Coin::new(100_000, ASSETS[0])],
+    )?;
+
+    let response: AccountingDriftResponse = app
+        .wrap()
+        .query_wasm_smart(&addr, &QueryMsg::AccountingDrift {})?;
+    assert_eq!(
+        response.assets[0],
+        AssetAccounting {
+            denom: ASSETS[0].to_string(),
+            actual: Uint128::new(600_000),
+            accounted: Uint128::new(600_000),
+        }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,922 @@
+use std::{collections::BTreeMap, error::Error};
+
+use ark_bn254::{Bn254, Fr};
+use ark_ff::PrimeField;
+use ark_groth16::Proof;
+use ark_std::{UniformRand, Zero};
+use circuits::{encoding, utils::poseidon_bn254};
+use cosmwasm_std::{
+    from_binary,
+    testing::{mock_dependencies_with_balance, mock_env, mock_info},
+    Addr, BankMsg, Coin, CosmosMsg, Deps, Reply, ReplyOn, SubMsgResponse, SubMsgResult, Uint128,
+};
+use cw_multi_test::Executor;
+use osmosis_std::types::{
+    cosmos::base::v1beta1::Coin as OsmosisCoin,
+    osmosis::{gamm::v1beta1::MsgSwapExactAmountIn, poolmanager::v1beta1::SwapAmountInRoute},
+};
+use rand::rngs::OsRng;
+
+use crate::{
+    error::ContractError,
+    execute, instantiate,
+    msg::{ExecuteMsg, QueryMsg},
+    query, reply,
+    state::{LATEST_SWAP, PENDING_EXCESS},
+    swap_aux,
+    test::{
+        deposit_new_note, init, instantiate_msg, new_note, prove_first_deposit, prove_transition,
+        serialize_to_base64, Circuit, Note, ADMIN, ASSETS, USER_1,
+    },
+    SWAP_REPLY_ID, TRANSFER_EXCESS_REPLY_ID,
+};
+
+fn swap_argument(
+    in_denom: &str,
+    in_amount: u128,
+    out_denom: &str,
+    out_min: u128,
+) -> MsgSwapExactAmountIn {
+    MsgSwapExactAmountIn {
+        sender: String::new(),
+        routes: vec![SwapAmountInRoute {
+            pool_id: 1,
+            token_out_denom: out_denom.to_string(),
+        }],
+        token_in: Some(OsmosisCoin {
+            denom: in_denom.to_string(),
+            amount: in_amount.to_string(),
+        }),
+        token_out_min_amount: out_min.to_string(),
+    }
+}
+
+#[test]
+fn swap_failed_reverts() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &note,
+        &new_note,
+        swap_aux(&hasher, &swap_argument, None)?,
+    )?;
+
+    let root_before: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+
+    // Multi-test can't execute the osmosis swap, so the swap submessage always fails
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument,
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                identifier: serialize_to_base64(&note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::SwapFailed(_))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let root_after: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(root_before, root_after, "Tree must not be updated");
+
+    let is_used: bool = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NullifierUsed {
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+        },
+    )?;
+    assert!(!is_used, "Nullifier must not be spent");
+
+    assert!(
+        app.wrap()
+            .query_wasm_raw(&addr, b"latest_swap".as_slice())?
+            .is_none(),
+        "Latest swap must be cleaned up"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_saves_latest_swap() -> Result<(), Box<dyn Error>> {
+    let env = mock_env();
+    let hasher = poseidon_bn254();
+    let mut rng = OsRng;
+    // The contract already holds 7 of the output asset before the swap
+    let mut deps =
+        mock_dependencies_with_balance(&[Coin::new(500_000, ASSETS[0]), Coin::new(7, ASSETS[6])]);
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(ADMIN.as_str(), &[]),
+        instantiate_msg()?,
+    )?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, [500_000, 0, 0, 0, 0, 0, 0])?;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(USER_1.as_str(), &[Coin::new(500_000, ASSETS[0])]),
+        ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        },
+    )?;
+    let (_, mut tree) = Circuit::empty(&hasher);
+    tree.insert_batch(&BTreeMap::from([(0, note.commitment)]), &hasher)?;
+
+    let swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &note,
+        &new_note,
+        swap_aux(&hasher, &swap_argument, None)?,
+    )?;
+    let response = execute(
+        deps.as_mut(),
+        env,
+        mock_info(USER_1.as_str(), &[]),
+        ExecuteMsg::Swap {
+            swap_argument,
+            root: serialize_to_base64(&tree.root()),
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            identifier: serialize_to_base64(&note.identifier),
+            new_note: serialize_to_base64(&new_note.commitment),
+            proof,
+            timeout: None,
+        },
+    )?;
+    assert_eq!(response.messages[0].id, SWAP_REPLY_ID);
+
+    // The output balance before the swap, the minimum output and the recipient of the excess
+    assert_eq!(
+        LATEST_SWAP.load(deps.as_ref().storage)?,
+        (
+            Coin::new(7, ASSETS[6]),
+            Uint128::new(50_000),
+            USER_1.clone()
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_invalid_proof() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    // Bound to another aux than the one of the swap argument
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+
+    let root_before: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument,
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                identifier: serialize_to_base64(&note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidProof)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    let root_after: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(root_before, root_after, "Tree must not be updated");
+
+    let is_used: bool = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NullifierUsed {
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+        },
+    )?;
+    assert!(!is_used, "Nullifier must not be spent");
+
+    assert!(
+        app.wrap()
+            .query_wasm_raw(&addr, b"latest_swap".as_slice())?
+            .is_none(),
+        "Latest swap must not be saved"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn aux_multi_hop() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+
+    let one_hop = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let mut three_hop = one_hop.clone();
+    three_hop.routes = [(1, ASSETS[3]), (7, ASSETS[2]), (3, ASSETS[6])]
+        .into_iter()
+        .map(|(pool_id, denom)| SwapAmountInRoute {
+            pool_id,
+            token_out_denom: denom.to_string(),
+        })
+        .collect();
+
+    // Client side computation from the raw swap parameters
+    for (swap_argument, routes, timeout) in [
+        (&one_hop, vec![(1, ASSETS[6])], None),
+        (
+            &three_hop,
+            vec![(1, ASSETS[3]), (7, ASSETS[2]), (3, ASSETS[6])],
+            Some(123),
+        ),
+    ] {
+        assert_eq!(
+            swap_aux(&hasher, swap_argument, timeout)?,
+            encoding::swap_aux(&hasher, &routes, ASSETS[0], 100_000, 50_000, timeout)?,
+            "Client and contract aux mismatch"
+        );
+    }
+
+    let three_hop_aux = swap_aux(&hasher, &three_hop, None)?;
+    assert_ne!(swap_aux(&hasher, &one_hop, None)?, three_hop_aux);
+
+    let mut changed_mid_route = three_hop.clone();
+    changed_mid_route.routes[1].token_out_denom = ASSETS[4].to_string();
+    assert_ne!(swap_aux(&hasher, &changed_mid_route, None)?, three_hop_aux);
+
+    let mut reordered = three_hop.clone();
+    reordered.routes.swap(0, 1);
+    assert_ne!(swap_aux(&hasher, &reordered, None)?, three_hop_aux);
+
+    // Sender is overwritten by the contract, so it must not affect aux
+    let mut with_sender = three_hop;
+    with_sender.sender = USER_1.to_string();
+    assert_eq!(swap_aux(&hasher, &with_sender, None)?, three_hop_aux);
+
+    Ok(())
+}
+
+#[test]
+fn transfer_excess_without_swap() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    let err = app
+        .execute_contract(
+            addr.clone(),
+            addr.clone(),
+            &ExecuteMsg::TransferExcess {},
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::NothingToTransfer)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn compute_aux() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let timeout = Some(app.block_info().time.seconds() + 60);
+    let aux: String = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::ComputeAux {
+            swap_argument: swap_argument.clone(),
+            timeout,
+        },
+    )?;
+    assert_eq!(
+        aux,
+        serialize_to_base64(&swap_aux(&hasher, &swap_argument, timeout)?),
+        "Invalid aux"
+    );
+
+    // A proof bound to the queried aux passes verification, failing only on the swap itself
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &note,
+        &new_note,
+        Fr::from_le_bytes_mod_order(&base64::decode(aux)?),
+    )?;
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument,
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                identifier: serialize_to_base64(&note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+                timeout,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::SwapFailed(_))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_with_funds() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &note,
+        &new_note,
+        swap_aux(&hasher, &swap_argument, None)?,
+    )?;
+
+    // The input asset comes from the note, so attaching it would strand these funds
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument,
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                identifier: serialize_to_base64(&note.identifier),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+                timeout: None,
+            },
+            &[Coin::new(100_000, ASSETS[0])],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::UnexpectedFunds)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_same_denom_mixed_case() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument: swap_argument(&ASSETS[0].to_uppercase(), 100_000, ASSETS[0], 50_000),
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: String::new(),
+                proof: String::new(),
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidSwapDenom)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_mixed_case_denoms_listed() -> Result<(), Box<dyn Error>> {
+    let env = mock_env();
+    let hasher = poseidon_bn254();
+    let mut rng = OsRng;
+    let mut deps =
+        mock_dependencies_with_balance(&[Coin::new(500_000, ASSETS[0]), Coin::new(7, ASSETS[6])]);
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(ADMIN.as_str(), &[]),
+        instantiate_msg()?,
+    )?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, [500_000, 0, 0, 0, 0, 0, 0])?;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(USER_1.as_str(), &[Coin::new(500_000, ASSETS[0])]),
+        ExecuteMsg::Deposit {
+            root: String::new(),
+            nullifier_hash: String::new(),
+            identifier: String::new(),
+            new_note: serialize_to_base64(&note.commitment),
+            proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            tag: None,
+            bound_height: None,
+            asset_bitmap: None,
+        },
+    )?;
+    let (_, mut tree) = Circuit::empty(&hasher);
+    tree.insert_batch(&BTreeMap::from([(0, note.commitment)]), &hasher)?;
+
+    // The proof commits to the denoms as sent, while the diff balances use the listed assets
+    let swap_argument = swap_argument(
+        &ASSETS[0].to_uppercase(),
+        100_000,
+        &ASSETS[6].to_uppercase(),
+        50_000,
+    );
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 50_000].map(Fr::from),
+    )?;
+    let proof = prove_transition(
+        &tree,
+        &hasher,
+        &mut rng,
+        0,
+        &note,
+        &new_note,
+        swap_aux(&hasher, &swap_argument, None)?,
+    )?;
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info(USER_1.as_str(), &[]),
+        ExecuteMsg::Swap {
+            swap_argument,
+            root: serialize_to_base64(&tree.root()),
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            identifier: serialize_to_base64(&note.identifier),
+            new_note: serialize_to_base64(&new_note.commitment),
+            proof,
+            timeout: None,
+        },
+    )?;
+
+    // The output balance is looked up under the listed denom
+    assert_eq!(
+        LATEST_SWAP.load(deps.as_ref().storage)?,
+        (
+            Coin::new(7, ASSETS[6]),
+            Uint128::new(50_000),
+            USER_1.clone()
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_empty_tree() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+
+    // A proof against the zero root, as for a first deposit
+    let note = new_note(&hasher, &mut rng, &USER_1, [0, 0, 0, 0, 0, 0, 50_000])?;
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument: swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000),
+                root: serialize_to_base64(&Fr::zero()),
+                nullifier_hash: serialize_to_base64(&Fr::zero()),
+                identifier: serialize_to_base64(&Fr::zero()),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::EmptyTree)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_unknown_token_in() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+
+    let note = new_note(&hasher, &mut rng, &USER_1, [0, 0, 0, 0, 0, 0, 50_000])?;
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument: swap_argument("uunlisted", 100_000, ASSETS[6], 50_000),
+                root: serialize_to_base64(&Fr::zero()),
+                nullifier_hash: serialize_to_base64(&Fr::zero()),
+                identifier: serialize_to_base64(&Fr::zero()),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::UnknownAsset(denom)) if denom == "uunlisted"
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_exceeding_contract_balance() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument: swap_argument(ASSETS[0], 500_001, ASSETS[6], 50_000),
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: String::new(),
+                // Never verified, the balance is checked first
+                proof: serialize_to_base64(&Proof::<Bn254>::default()),
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InsufficientContractBalance { denom }) if denom == ASSETS[0]
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_malformed_route() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, _, _) = init()?;
+
+    let mut zero_pool = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    zero_pool.routes[0].pool_id = 0;
+    let mut empty_denom = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    empty_denom.routes.insert(
+        0,
+        SwapAmountInRoute {
+            pool_id: 2,
+            token_out_denom: String::new(),
+        },
+    );
+
+    for swap_argument in [zero_pool, empty_denom] {
+        let err = app
+            .execute_contract(
+                USER_1.clone(),
+                addr.clone(),
+                &ExecuteMsg::Swap {
+                    swap_argument,
+                    root: String::new(),
+                    nullifier_hash: String::new(),
+                    identifier: String::new(),
+                    new_note: String::new(),
+                    proof: String::new(),
+                    timeout: None,
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::InvalidSwapRoute)
+            ),
+            "Unexpected error {err:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rejected_excess_claimable() -> Result<(), Box<dyn Error>> {
+    let env = mock_env();
+    let recipient = Addr::unchecked("recipient");
+    let mut deps = mock_dependencies_with_balance(&[Coin::new(1_100, ASSETS[6])]);
+
+    // Swap of at least 1000 out of 1100 received, with no prior balance
+    LATEST_SWAP.save(
+        deps.as_mut().storage,
+        &(
+            Coin::new(0, ASSETS[6]),
+            Uint128::new(1_000),
+            recipient.clone(),
+        ),
+    )?;
+    let response = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(env.contract.address.as_str(), &[]),
+        ExecuteMsg::TransferExcess {},
+    )?;
+    assert_eq!(response.messages[0].id, TRANSFER_EXCESS_REPLY_ID);
+    assert_eq!(response.messages[0].reply_on, ReplyOn::Always);
+
+    // The recipient rejects the excess transfer
+    reply(
+        deps.as_mut(),
+        env.clone(),
+        Reply {
+            id: TRANSFER_EXCESS_REPLY_ID,
+            result: SubMsgResult::Err("rejected".to_string()),
+        },
+    )?;
+    let claimable = |deps: Deps| -> Result<Vec<Coin>, Box<dyn Error>> {
+        Ok(from_binary(&query(
+            deps,
+            env.clone(),
+            QueryMsg::Claimable {
+                address: recipient.to_string(),
+            },
+        )?)?)
+    };
+    assert_eq!(claimable(deps.as_ref())?, vec![Coin::new(100, ASSETS[6])]);
+
+    let response = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(recipient.as_str(), &[]),
+        ExecuteMsg::Claim {},
+    )?;
+    assert_eq!(
+        response.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin::new(100, ASSETS[6])],
+        })
+    );
+    assert!(
+        claimable(deps.as_ref())?.is_empty(),
+        "Claimed excess must be cleared"
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(recipient.as_str(), &[]),
+        ExecuteMsg::Claim {},
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::NothingToClaim),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn delivered_excess_cleared() -> Result<(), Box<dyn Error>> {
+    let env = mock_env();
+    let recipient = Addr::unchecked("recipient");
+    let mut deps = mock_dependencies_with_balance(&[Coin::new(1_100, ASSETS[6])]);
+
+    LATEST_SWAP.save(
+        deps.as_mut().storage,
+        &(
+            Coin::new(0, ASSETS[6]),
+            Uint128::new(1_000),
+            recipient.clone(),
+        ),
+    )?;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(env.contract.address.as_str(), &[]),
+        ExecuteMsg::TransferExcess {},
+    )?;
+    assert!(PENDING_EXCESS.may_load(deps.as_ref().storage)?.is_some());
+
+    // The excess is delivered, so nothing is left pending nor claimable
+    reply(
+        deps.as_mut(),
+        env.clone(),
+        Reply {
+            id: TRANSFER_EXCESS_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        },
+    )?;
+    assert!(PENDING_EXCESS.may_load(deps.as_ref().storage)?.is_none());
+    let claimable: Vec<Coin> = from_binary(&query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Claimable {
+            address: recipient.to_string(),
+        },
+    )?)?;
+    assert!(claimable.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn swap_too_many_routes() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, _) = init()?;
+
+    let mut swap_argument = swap_argument(ASSETS[0], 100_000, ASSETS[6], 50_000);
+    swap_argument.routes = (1..=encoding::MAX_ROUTES as u64 + 1)
+        .map(|pool_id| SwapAmountInRoute {
+            pool_id,
+            token_out_denom: ASSETS[6].to_string(),
+        })
+        .collect();
+
+    // Rejected before hashing the routes or verifying anything else
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Swap {
+                swap_argument: swap_argument.clone(),
+                root: String::new(),
+                nullifier_hash: String::new(),
+                identifier: String::new(),
+                new_note: String::new(),
+                proof: String::new(),
+                timeout: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::TooManyRoutes(encoding::MAX_ROUTES))
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    swap_argument.routes.pop();
+    swap_aux(&hasher, &swap_argument, None)?;
+
+    Ok(())
+}
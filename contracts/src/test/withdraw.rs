@@ -0,0 +1,411 @@
+use std::{collections::BTreeMap, error::Error};
+
+use ark_bn254::Fr;
+use ark_std::{UniformRand, Zero};
+use cosmwasm_std::{
+    testing::{mock_dependencies_with_balance, mock_env, mock_info},
+    Coin, Deps, Uint128,
+};
+use cw_multi_test::{App, Executor};
+
+use crate::{
+    error::ContractError,
+    instantiate, migrate,
+    msg::{AccountingDriftResponse, AssetAccounting, ExecuteMsg, MigrateMsg, QueryMsg},
+    state::ACCOUNTED_TOTAL,
+    test::{
+        deposit_new_note, init, instantiate_msg, new_note, prove_first_deposit, prove_transition,
+        serialize_to_base64, wasm_attribute, Note, ADMIN, ASSETS, USER_1,
+    },
+    update_accounted_total,
+};
+
+#[test]
+fn withdraw_from_other_sender() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 0].map(Fr::from),
+    )?;
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+
+    // The identifier is derived from `ADMIN` instead of `USER_1` the proof was built for
+    let err = app
+        .execute_contract(
+            ADMIN.clone(),
+            addr.clone(),
+            &ExecuteMsg::Withdraw {
+                assets: BTreeMap::from([(ASSETS[0].to_string(), Uint128::new(100_000))]),
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                blinding: serialize_to_base64(&note.blinding),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidProof)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_with_funds() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 0].map(Fr::from),
+    )?;
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Withdraw {
+                assets: BTreeMap::from([(ASSETS[0].to_string(), Uint128::new(100_000))]),
+                root: serialize_to_base64(&tree.root()),
+                nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+                blinding: serialize_to_base64(&note.blinding),
+                new_note: serialize_to_base64(&new_note.commitment),
+                proof,
+            },
+            &[Coin::new(100_000, ASSETS[0])],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::UnexpectedFunds)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_empty_tree() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, _, hasher, mut rng) = init()?;
+
+    // A proof against the zero root, as for a first deposit
+    let note = new_note(&hasher, &mut rng, &USER_1, [0, 0, 0, 0, 0, 0, 0])?;
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &ExecuteMsg::Withdraw {
+                assets: BTreeMap::from([(ASSETS[0].to_string(), Uint128::new(100_000))]),
+                root: serialize_to_base64(&Fr::zero()),
+                nullifier_hash: serialize_to_base64(&Fr::zero()),
+                blinding: serialize_to_base64(&note.blinding),
+                new_note: serialize_to_base64(&note.commitment),
+                proof: prove_first_deposit(&hasher, &mut rng, &note)?,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::EmptyTree)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn accounting_deposit_withdraw() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+    let accounting = |app: &App| -> Result<AssetAccounting, Box<dyn Error>> {
+        let response: AccountingDriftResponse = app
+            .wrap()
+            .query_wasm_smart(&addr, &QueryMsg::AccountingDrift {})?;
+        assert_eq!(response.assets.len(), ASSETS.len());
+        Ok(response.assets[0].clone())
+    };
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 0, 0, 0, 0, 0, 0],
+    )?;
+    assert_eq!(
+        accounting(&app)?,
+        AssetAccounting {
+            denom: ASSETS[0].to_string(),
+            actual: Uint128::new(500_000),
+            accounted: Uint128::new(500_000),
+        }
+    );
+
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [400_000, 0, 0, 0, 0, 0, 0].map(Fr::from),
+    )?;
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+    app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::Withdraw {
+            assets: BTreeMap::from([(ASSETS[0].to_string(), Uint128::new(100_000))]),
+            root: serialize_to_base64(&tree.root()),
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            blinding: serialize_to_base64(&note.blinding),
+            new_note: serialize_to_base64(&new_note.commitment),
+            proof,
+        },
+        &[],
+    )?;
+    assert_eq!(
+        accounting(&app)?,
+        AssetAccounting {
+            denom: ASSETS[0].to_string(),
+            actual: Uint128::new(400_000),
+            accounted: Uint128::new(400_000),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_partial() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+    let bank_balance = |app: &App, denom: &str| -> Result<Uint128, Box<dyn Error>> {
+        Ok(app.wrap().query_balance(USER_1.clone(), denom)?.amount)
+    };
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 300_000, 0, 0, 0, 0, 0],
+    )?;
+    let balances_before = [ASSETS[0], ASSETS[1]].map(|denom| bank_balance(&app, denom));
+
+    // Withdraw part of the second asset only
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [500_000, 200_000, 0, 0, 0, 0, 0].map(Fr::from),
+    )?;
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+    let response = app.execute_contract(
+        USER_1.clone(),
+        addr.clone(),
+        &ExecuteMsg::Withdraw {
+            assets: BTreeMap::from([(ASSETS[1].to_string(), Uint128::new(100_000))]),
+            root: serialize_to_base64(&tree.root()),
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+            blinding: serialize_to_base64(&note.blinding),
+            new_note: serialize_to_base64(&new_note.commitment),
+            proof,
+        },
+        &[],
+    )?;
+
+    // Only the withdrawn amount is sent to the user
+    let [first_before, second_before] = balances_before;
+    assert_eq!(bank_balance(&app, ASSETS[0])?, first_before?);
+    assert_eq!(
+        bank_balance(&app, ASSETS[1])?,
+        second_before? + Uint128::new(100_000)
+    );
+
+    // The new note committing to the reduced balances is inserted
+    let index: u32 = wasm_attribute(&response, "index")
+        .ok_or("missing index attribute")?
+        .parse()?;
+    let notes: Vec<Option<String>> = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NotesAt {
+            indices: vec![index.into()],
+        },
+    )?;
+    assert_eq!(notes, vec![Some(serialize_to_base64(&new_note.commitment))]);
+    tree.insert_batch(&BTreeMap::from([(index, new_note.commitment)]), &hasher)?;
+    let root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(root, serialize_to_base64(&tree.root()));
+
+    let is_used: bool = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::NullifierUsed {
+            nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+        },
+    )?;
+    assert!(is_used, "Nullifier must be spent");
+
+    let response: AccountingDriftResponse = app
+        .wrap()
+        .query_wasm_smart(&addr, &QueryMsg::AccountingDrift {})?;
+    assert_eq!(
+        response.assets[..2],
+        [
+            AssetAccounting {
+                denom: ASSETS[0].to_string(),
+                actual: Uint128::new(500_000),
+                accounted: Uint128::new(500_000),
+            },
+            AssetAccounting {
+                denom: ASSETS[1].to_string(),
+                actual: Uint128::new(200_000),
+                accounted: Uint128::new(200_000),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_to_multiple_recipients() -> Result<(), Box<dyn Error>> {
+    let (mut app, addr, mut tree, hasher, mut rng) = init()?;
+    let recipients = ["recipient_1", "recipient_2", "recipient_3"];
+
+    let note = deposit_new_note(
+        &mut app,
+        &addr,
+        &mut tree,
+        &hasher,
+        &mut rng,
+        &USER_1,
+        [500_000, 300_000, 0, 0, 0, 0, 0],
+    )?;
+    let new_note = Note::new(
+        &hasher,
+        note.address,
+        note.nullifier,
+        Fr::rand(&mut rng),
+        [350_000, 200_000, 0, 0, 0, 0, 0].map(Fr::from),
+    )?;
+    let proof = prove_transition(&tree, &hasher, &mut rng, 0, &note, &new_note, Fr::zero())?;
+    let withdraw_split = |payouts: &[(&str, Vec<Coin>)]| ExecuteMsg::WithdrawSplit {
+        payouts: payouts
+            .iter()
+            .map(|(recipient, coins)| (recipient.to_string(), coins.clone()))
+            .collect(),
+        root: serialize_to_base64(&tree.root()),
+        nullifier_hash: serialize_to_base64(&note.nullifier_hash),
+        blinding: serialize_to_base64(&note.blinding),
+        new_note: serialize_to_base64(&new_note.commitment),
+        proof: proof.clone(),
+    };
+    let payouts = [
+        (recipients[0], vec![Coin::new(100_000, ASSETS[0])]),
+        (
+            recipients[1],
+            vec![Coin::new(30_000, ASSETS[0]), Coin::new(60_000, ASSETS[1])],
+        ),
+        (
+            recipients[2],
+            vec![Coin::new(20_000, ASSETS[0]), Coin::new(40_000, ASSETS[1])],
+        ),
+    ];
+
+    // Payouts summing to more than the proven withdrawal are rejected
+    let mut over_withdrawn = payouts.clone();
+    over_withdrawn[0].1[0].amount += Uint128::one();
+    let err = app
+        .execute_contract(
+            USER_1.clone(),
+            addr.clone(),
+            &withdraw_split(&over_withdrawn),
+            &[],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<ContractError>(),
+            Some(ContractError::InvalidProof)
+        ),
+        "Unexpected error {err:?}"
+    );
+
+    app.execute_contract(USER_1.clone(), addr.clone(), &withdraw_split(&payouts), &[])?;
+    for (recipient, coins) in payouts {
+        for coin in coins {
+            assert_eq!(app.wrap().query_balance(recipient, &coin.denom)?, coin);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn accounting_seeded_and_saturating() -> Result<(), Box<dyn Error>> {
+    let env = mock_env();
+    // The contract already holds the funds backing genesis notes when instantiated
+    let mut deps = mock_dependencies_with_balance(&[Coin::new(500_000, ASSETS[0])]);
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(ADMIN.as_str(), &[]),
+        instantiate_msg()?,
+    )?;
+    let accounted = |deps: Deps| ACCOUNTED_TOTAL.load(deps.storage, ASSETS[0]);
+    assert_eq!(accounted(deps.as_ref())?, Uint128::new(500_000));
+
+    // Deployments from before totals were tracked are seeded on migrate
+    ACCOUNTED_TOTAL.remove(deps.as_mut().storage, ASSETS[0]);
+    migrate(deps.as_mut(), env, MigrateMsg {})?;
+    assert_eq!(accounted(deps.as_ref())?, Uint128::new(500_000));
+
+    // Spending more than accounted doesn't fail the spend
+    update_accounted_total(
+        deps.as_mut().storage,
+        ASSETS[0],
+        Uint128::new(600_000),
+        false,
+    )?;
+    assert_eq!(accounted(deps.as_ref())?, Uint128::zero());
+
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! Native proof generation for the protocol, producing messages ready to be executed on the
+//! contract.
+
+use std::{collections::BTreeMap, ops::Neg, str::FromStr};
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::{snark::SNARK, sponge::poseidon::PoseidonConfig};
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::{rand::RngCore, Zero};
+use base64::DecodeError;
+use circuits::{
+    encoding::{self, decode_field, FieldError},
+    merkle_tree::{MerkleError, Path, SparseMerkleTree},
+    poseidon::PoseidonHash,
+    utils::poseidon_bn254,
+    MainCircuitBn254, N_ASSETS, TREE_DEPTH,
+};
+use contracts::{funds_balances, msg::ExecuteMsg};
+use cosmwasm_std::Coin;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub type Circuit = MainCircuitBn254<{ N_ASSETS }, { TREE_DEPTH }>;
+pub type Tree = SparseMerkleTree<Fr, PoseidonHash<Fr>, { TREE_DEPTH }>;
+
+#[derive(Error, Debug)]
+pub enum ProverError {
+    #[error("Decode Base64: {0}")]
+    Decode(#[from] DecodeError),
+
+    #[error("Ark Serialization: {0}")]
+    Serialization(#[from] SerializationError),
+
+    #[error("Field: {0}")]
+    Field(#[from] FieldError),
+
+    #[error("Merkle Tree: {0}")]
+    Merkle(#[from] MerkleError),
+
+    #[error("Synthesis: {0}")]
+    Synthesis(#[from] SynthesisError),
+
+    #[error("Invalid amount {0}")]
+    InvalidAmount(String),
+
+    #[error("Insufficient balance of asset {0}")]
+    InsufficientBalance(usize),
+
+    #[error("Note not found at index {0} in provided tree")]
+    NoteNotFound(u64),
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("Decode Base64: {0}")]
+    Decode(#[from] DecodeError),
+
+    #[error("Ark Serialization: {0}")]
+    Serialization(#[from] SerializationError),
+
+    #[error("Field: {0}")]
+    Field(#[from] FieldError),
+
+    #[error("Synthesis: {0}")]
+    Synthesis(#[from] SynthesisError),
+
+    #[error("Hash: {0}")]
+    Hash(String),
+
+    #[error("Not a deposit message")]
+    NotDeposit,
+
+    #[error("Invalid proof")]
+    InvalidProof,
+}
+
+impl From<Box<dyn ark_std::error::Error>> for VerifyError {
+    fn from(value: Box<dyn ark_std::error::Error>) -> Self {
+        Self::Hash(value.to_string())
+    }
+}
+
+impl From<Box<dyn ark_std::error::Error>> for ProverError {
+    fn from(value: Box<dyn ark_std::error::Error>) -> Self {
+        Self::Custom(value.to_string())
+    }
+}
+
+/// Account state of the latest note, with field elements encoded in base64.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountWitness {
+    pub address: String,
+    pub nullifier: String,
+    pub blinding: String,
+    pub balances: [String; N_ASSETS],
+    pub index: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetDiff {
+    pub asset_index: usize,
+    pub is_add: bool,
+    pub amount: String,
+}
+
+/// Input of a deposit proof.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DepositWitness {
+    pub account: AccountWitness,
+    pub diffs: Vec<AssetDiff>,
+    pub tree_notes: Vec<String>,
+    /// Blinding of the new note, randomized if none.
+    pub new_blinding: Option<String>,
+    /// Recent block height to bind the proof to, required by contracts with a maximum proof age.
+    pub bound_height: Option<u64>,
+}
+
+/// Circuit of a note transition together with its public inputs.
+pub struct Transition {
+    pub circuit: Circuit,
+    pub root: Fr,
+    pub diff_balance_root: Fr,
+    pub nullifier_hash: Fr,
+    pub identifier: Fr,
+    pub new_note: Fr,
+}
+
+pub fn encode_field(value: &Fr) -> Result<String, ProverError> {
+    Ok(serialize_to_base64(value)?)
+}
+
+pub fn serialize_to_base64<T: CanonicalSerialize>(value: &T) -> Result<String, SerializationError> {
+    let mut bytes = vec![];
+    value.serialize_compressed(&mut bytes)?;
+    Ok(base64::encode(bytes))
+}
+
+fn parse_amount(amount: &str) -> Result<u128, ProverError> {
+    u128::from_str(amount).map_err(|_| ProverError::InvalidAmount(amount.to_string()))
+}
+
+/// Builds the tree from the ordered list of base64 encoded notes.
+pub fn tree_from_notes(notes: &[String], hasher: &PoseidonConfig<Fr>) -> Result<Tree, ProverError> {
+    let leaves = notes
+        .iter()
+        .enumerate()
+        .map(|(i, l)| Ok((i as u32, decode_field::<Fr>(l)?)))
+        .collect::<Result<BTreeMap<_, _>, ProverError>>()?;
+    Ok(Tree::new(&leaves, hasher, &Fr::zero())?)
+}
+
+/// Rebuilds the tree from the full ordered list of base64 encoded notes, as returned by the
+/// contract's `Notes` query, and checks that its root is the contract's `claimed_root`.
+pub fn verify_contract_root(notes: &[String], claimed_root: &str) -> bool {
+    let hasher = poseidon_bn254();
+    let claimed_root = match decode_field::<Fr>(claimed_root) {
+        Ok(root) => root,
+        Err(_) => return false,
+    };
+
+    tree_from_notes(notes, &hasher)
+        .map(|tree| tree.root() == claimed_root)
+        .unwrap_or(false)
+}
+
+/// Builds the transition from the account's latest note into a new note with `diffs` applied.
+pub fn build_transition(
+    hasher: &PoseidonConfig<Fr>,
+    account: &AccountWitness,
+    diffs: &[AssetDiff],
+    tree_notes: &[String],
+    new_blinding: Fr,
+    aux: Fr,
+) -> Result<Transition, ProverError> {
+    let address = encoding::address_to_field(&account.address);
+    let nullifier = decode_field::<Fr>(&account.nullifier)?;
+    let blinding = decode_field::<Fr>(&account.blinding)?;
+
+    let mut old_balances = [0u128; N_ASSETS];
+    for (balance, amount) in old_balances.iter_mut().zip(account.balances.iter()) {
+        *balance = parse_amount(amount)?;
+    }
+
+    let mut new_balances = old_balances;
+    let mut diff_balances = [Fr::zero(); N_ASSETS];
+    for diff in diffs {
+        let amount = parse_amount(&diff.amount)?;
+        let balance = new_balances
+            .get_mut(diff.asset_index)
+            .ok_or(ProverError::Custom(format!(
+                "Invalid asset index {}",
+                diff.asset_index
+            )))?;
+        match diff.is_add {
+            true => {
+                *balance = balance
+                    .checked_add(amount)
+                    .ok_or(ProverError::InvalidAmount(diff.amount.clone()))?;
+                diff_balances[diff.asset_index] = Fr::from(amount);
+            }
+            false => {
+                *balance = balance
+                    .checked_sub(amount)
+                    .ok_or(ProverError::InsufficientBalance(diff.asset_index))?;
+                diff_balances[diff.asset_index] = Fr::from(amount).neg();
+            }
+        }
+    }
+    let diff_balance_root = PoseidonHash::crh(hasher, &diff_balances)?;
+
+    // Calculate old note
+    let old_note_balances = old_balances.map(Fr::from);
+    let old_note_identifier = PoseidonHash::tto_crh(hasher, address, blinding)?;
+    let old_note = PoseidonHash::crh(
+        hasher,
+        &[
+            PoseidonHash::crh(hasher, &old_note_balances)?,
+            old_note_identifier,
+            nullifier,
+        ],
+    )?;
+
+    // Calculate old note path, nullifier hash and root
+    let (old_note_path, old_note_nullifier_hash, root) = match account.index {
+        Some(index) => {
+            let tree = tree_from_notes(tree_notes, hasher)?;
+            let path = tree.generate_membership_proof(index);
+            let root = tree.root();
+            path.check_membership(&root, &old_note, hasher)
+                .ok()
+                .filter(|is_member| *is_member)
+                .ok_or(ProverError::NoteNotFound(index))?;
+            (
+                path,
+                PoseidonHash::tto_crh(hasher, old_note, nullifier)?,
+                root,
+            )
+        }
+        None => (Path::empty(), Fr::zero(), Fr::zero()),
+    };
+
+    // Calculate new note
+    let new_note_balances = new_balances.map(Fr::from);
+    let new_note = PoseidonHash::crh(
+        hasher,
+        &[
+            PoseidonHash::crh(hasher, &new_note_balances)?,
+            PoseidonHash::tto_crh(hasher, address, new_blinding)?,
+            nullifier,
+        ],
+    )?;
+
+    Ok(Transition {
+        circuit: Circuit {
+            address,
+            nullifier,
+            aux,
+            utxo_root: root,
+            diff_balance_root,
+            diff_balances,
+            old_note_nullifier_hash,
+            old_note_identifier,
+            old_note_path,
+            old_note_balances,
+            new_note,
+            new_note_blinding: new_blinding,
+            new_note_balances,
+            parameters: hasher.clone(),
+            _hg: std::marker::PhantomData,
+        },
+        root,
+        diff_balance_root,
+        nullifier_hash: old_note_nullifier_hash,
+        identifier: old_note_identifier,
+        new_note,
+    })
+}
+
+pub fn prove<R: RngCore + ark_std::rand::CryptoRng>(
+    pk: &ProvingKey<Bn254>,
+    circuit: Circuit,
+    rng: &mut R,
+) -> Result<Proof<Bn254>, ProverError> {
+    Ok(Groth16::<Bn254, LibsnarkReduction>::prove(
+        pk, circuit, rng,
+    )?)
+}
+
+/// Proves the deposit described by `witness`, returning the message to execute on the contract.
+pub fn deposit_msg<R: RngCore + ark_std::rand::CryptoRng>(
+    hasher: &PoseidonConfig<Fr>,
+    pk: &ProvingKey<Bn254>,
+    witness: &DepositWitness,
+    rng: &mut R,
+) -> Result<ExecuteMsg, ProverError> {
+    let new_blinding = match &witness.new_blinding {
+        Some(blinding) => decode_field(blinding)?,
+        None => <Fr as ark_std::UniformRand>::rand(rng),
+    };
+    let transition = build_transition(
+        hasher,
+        &witness.account,
+        &witness.diffs,
+        &witness.tree_notes,
+        new_blinding,
+        witness.bound_height.map(Fr::from).unwrap_or_default(),
+    )?;
+    let proof = prove(pk, transition.circuit, rng)?;
+
+    Ok(ExecuteMsg::Deposit {
+        root: encode_field(&transition.root)?,
+        nullifier_hash: encode_field(&transition.nullifier_hash)?,
+        identifier: encode_field(&transition.identifier)?,
+        new_note: encode_field(&transition.new_note)?,
+        proof: serialize_to_base64(&proof)?,
+        tag: None,
+        bound_height: witness.bound_height,
+        asset_bitmap: None,
+    })
+}
+
+/// Verifies the proof of a deposit `msg` sent with `funds` against `vk`, reconstructing the public
+/// inputs exactly as the contract does with its `assets`.
+///
+/// Only the proof is checked, the root history, used nullifiers and minimum deposits are still
+/// checked by the contract on execution.
+pub fn verify_deposit(
+    vk: &VerifyingKey<Bn254>,
+    assets: &[String],
+    msg: &ExecuteMsg,
+    funds: &[Coin],
+) -> Result<(), VerifyError> {
+    let ExecuteMsg::Deposit {
+        root,
+        nullifier_hash,
+        identifier,
+        new_note,
+        proof,
+        bound_height,
+        ..
+    } = msg
+    else {
+        return Err(VerifyError::NotDeposit);
+    };
+
+    let proof = Proof::<Bn254>::deserialize_compressed_unchecked(&base64::decode(proof)?[..])?;
+    let diff_balance_root = PoseidonHash::crh(&poseidon_bn254(), &funds_balances(assets, funds))?;
+
+    Groth16::<Bn254, LibsnarkReduction>::verify(
+        vk,
+        &[
+            bound_height.map(Fr::from).unwrap_or_default(),
+            decode_field(root)?,
+            diff_balance_root,
+            decode_field(nullifier_hash)?,
+            decode_field(identifier)?,
+            decode_field(new_note)?,
+        ],
+        &proof,
+    )?
+    .then_some(())
+    .ok_or(VerifyError::InvalidProof)
+}
@@ -0,0 +1,32 @@
+use std::{error::Error, io::Read};
+
+use ark_bn254::Bn254;
+use ark_groth16::ProvingKey;
+use ark_serialize::CanonicalDeserialize;
+use circuits::utils::poseidon_bn254;
+use prover::{deposit_msg, DepositWitness};
+use rand::rngs::OsRng;
+
+const USAGE: &str = "Usage: prover <proving key path> [witness json path]";
+
+/// Generates a deposit execute message from a JSON witness, read from a file or stdin.
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let pk_path = args.next().ok_or(USAGE)?;
+    let witness = match args.next() {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut witness = String::new();
+            std::io::stdin().read_to_string(&mut witness)?;
+            witness
+        }
+    };
+
+    let pk = ProvingKey::<Bn254>::deserialize_uncompressed_unchecked(&std::fs::read(pk_path)?[..])?;
+    let witness: DepositWitness = serde_json::from_str(&witness)?;
+    let msg = deposit_msg(&poseidon_bn254(), &pk, &witness, &mut OsRng)?;
+
+    println!("{}", serde_json::to_string(&msg)?);
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use std::{error::Error, process::Command};
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16};
+use ark_serialize::CanonicalSerialize;
+use ark_std::{UniformRand, Zero};
+use circuits::utils::poseidon_bn254;
+use common::{setup_contract, ASSETS};
+use contracts::msg::{ExecuteMsg, QueryMsg};
+use cosmwasm_std::{Addr, Coin};
+use cw_multi_test::Executor;
+use prover::{encode_field, AccountWitness, AssetDiff, Circuit, DepositWitness};
+use rand::rngs::OsRng;
+
+#[test]
+fn cli_deposit() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let user = Addr::unchecked("user_1");
+
+    let (pk, vk) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(
+        Circuit::empty_without_tree(&hasher),
+        &mut OsRng,
+    )?;
+    let dir = std::env::temp_dir().join(format!("prover-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let pk_path = dir.join("pk.bin");
+    let witness_path = dir.join("witness.json");
+
+    let mut pk_bytes = vec![];
+    pk.serialize_uncompressed(&mut pk_bytes)?;
+    std::fs::write(&pk_path, pk_bytes)?;
+    std::fs::write(
+        &witness_path,
+        serde_json::to_string(&DepositWitness {
+            account: AccountWitness {
+                address: user.to_string(),
+                nullifier: encode_field(&Fr::rand(&mut OsRng))?,
+                blinding: encode_field(&Fr::zero())?,
+                balances: std::array::from_fn(|_| "0".to_string()),
+                index: None,
+            },
+            diffs: vec![AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "500000".to_string(),
+            }],
+            tree_notes: vec![],
+            new_blinding: None,
+            bound_height: None,
+        })?,
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_prover"))
+        .arg(&pk_path)
+        .arg(&witness_path)
+        .output()?;
+    std::fs::remove_dir_all(&dir)?;
+    assert!(
+        output.status.success(),
+        "prover failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Arkworks traces are also printed to stdout when `print-trace` is enabled in the workspace
+    let stdout = String::from_utf8(output.stdout)?;
+    let msg: ExecuteMsg = serde_json::from_str(stdout.lines().last().ok_or("empty output")?)?;
+
+    let (mut app, addr) = setup_contract(&user, &vk)?;
+
+    let new_note = match &msg {
+        ExecuteMsg::Deposit { new_note, .. } => new_note.clone(),
+        _ => panic!("Expected deposit message"),
+    };
+    app.execute_contract(
+        user.clone(),
+        addr.clone(),
+        &msg,
+        &[Coin::new(500_000, ASSETS[0])],
+    )?;
+
+    let notes: contracts::msg::NotesResponse = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::Notes {
+            limit: None,
+            start_after: None,
+            is_ascending: None,
+        },
+    )?;
+    assert_eq!(notes.notes, vec![new_note], "Invalid inserted note");
+
+    Ok(())
+}
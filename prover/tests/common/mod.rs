@@ -0,0 +1,51 @@
+use std::error::Error;
+
+use ark_bn254::Bn254;
+use ark_groth16::VerifyingKey;
+use ark_serialize::CanonicalSerialize;
+use contracts::{execute, instantiate, msg::InstantiateMsg, query, reply};
+use cosmwasm_std::{Addr, Coin};
+use cw_multi_test::{App, ContractWrapper, Executor};
+
+pub const ASSETS: [&str; 7] = ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"];
+
+/// Instantiates the contract verifying proofs with `vk`, with `user` funded with the first asset.
+pub fn setup_contract(
+    user: &Addr,
+    vk: &VerifyingKey<Bn254>,
+) -> Result<(App, Addr), Box<dyn Error>> {
+    let mut app = App::new(|r, _api, storage| {
+        r.bank
+            .init_balance(storage, user, vec![Coin::new(100_000_000, ASSETS[0])])
+            .expect("init balance failed");
+    });
+    let code_id = app.store_code(Box::new(
+        ContractWrapper::new(execute, instantiate, query).with_reply(reply),
+    ));
+    let mut vk_bytes = vec![];
+    vk.serialize_uncompressed(&mut vk_bytes)?;
+    let addr = app.instantiate_contract(
+        code_id,
+        user.clone(),
+        &InstantiateMsg {
+            assets: ASSETS.map(String::from),
+            main_circuit_vk: base64::encode(vk_bytes),
+            main_circuit_vk_check: None,
+            compress_vk: None,
+            unique_notes: None,
+            split_circuit_vk: None,
+            asset_bitmap_circuit_vk: None,
+            min_deposits: None,
+            store_proofs: None,
+            genesis_notes: None,
+            decimals: None,
+            max_proof_age: None,
+            track_all_roots: None,
+        },
+        &[],
+        "main",
+        None,
+    )?;
+
+    Ok((app, addr))
+}
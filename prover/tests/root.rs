@@ -0,0 +1,91 @@
+mod common;
+
+use std::error::Error;
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16};
+use ark_std::{UniformRand, Zero};
+use circuits::utils::poseidon_bn254;
+use common::{setup_contract, ASSETS};
+use contracts::msg::{NotesResponse, QueryMsg};
+use cosmwasm_std::{Addr, Coin};
+use cw_multi_test::Executor;
+use prover::{
+    deposit_msg, encode_field, verify_contract_root, AccountWitness, AssetDiff, Circuit,
+    DepositWitness,
+};
+use rand::rngs::OsRng;
+
+#[test]
+fn contract_root_from_notes() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let user = Addr::unchecked("user_1");
+
+    let (pk, vk) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(
+        Circuit::empty_without_tree(&hasher),
+        &mut OsRng,
+    )?;
+    let (mut app, addr) = setup_contract(&user, &vk)?;
+
+    for amount in [100_000u128, 200_000, 300_000] {
+        let msg = deposit_msg(
+            &hasher,
+            &pk,
+            &DepositWitness {
+                account: AccountWitness {
+                    address: user.to_string(),
+                    nullifier: encode_field(&Fr::rand(&mut OsRng))?,
+                    blinding: encode_field(&Fr::zero())?,
+                    balances: std::array::from_fn(|_| "0".to_string()),
+                    index: None,
+                },
+                diffs: vec![AssetDiff {
+                    asset_index: 0,
+                    is_add: true,
+                    amount: amount.to_string(),
+                }],
+                tree_notes: vec![],
+                new_blinding: None,
+                bound_height: None,
+            },
+            &mut OsRng,
+        )?;
+        app.execute_contract(
+            user.clone(),
+            addr.clone(),
+            &msg,
+            &[Coin::new(amount, ASSETS[0])],
+        )?;
+    }
+
+    let notes: NotesResponse = app.wrap().query_wasm_smart(
+        &addr,
+        &QueryMsg::Notes {
+            limit: None,
+            start_after: None,
+            is_ascending: None,
+        },
+    )?;
+    let root: String = app.wrap().query_wasm_smart(&addr, &QueryMsg::Root {})?;
+    assert_eq!(notes.notes.len(), 3, "Invalid notes dump");
+
+    assert!(
+        verify_contract_root(&notes.notes, &root),
+        "Root must match the notes dump"
+    );
+
+    let mut reordered = notes.notes.clone();
+    reordered.swap(0, 1);
+    assert!(!verify_contract_root(&reordered, &root), "Reordered notes");
+    assert!(
+        !verify_contract_root(&notes.notes[..2], &root),
+        "Missing notes"
+    );
+    assert!(
+        !verify_contract_root(&notes.notes, "invalid"),
+        "Invalid root"
+    );
+
+    Ok(())
+}
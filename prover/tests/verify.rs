@@ -0,0 +1,109 @@
+mod common;
+
+use std::error::Error;
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16};
+use ark_std::{UniformRand, Zero};
+use circuits::utils::poseidon_bn254;
+use common::{setup_contract, ASSETS};
+use contracts::msg::ExecuteMsg;
+use cosmwasm_std::{Addr, Coin};
+use cw_multi_test::Executor;
+use prover::{
+    deposit_msg, encode_field, verify_deposit, AccountWitness, AssetDiff, Circuit, DepositWitness,
+    VerifyError,
+};
+use rand::rngs::OsRng;
+
+#[test]
+fn native_verifier_matches_contract() -> Result<(), Box<dyn Error>> {
+    let hasher = poseidon_bn254();
+    let user = Addr::unchecked("user_1");
+    let assets = ASSETS.map(String::from);
+
+    let (pk, vk) = Groth16::<Bn254, LibsnarkReduction>::circuit_specific_setup(
+        Circuit::empty_without_tree(&hasher),
+        &mut OsRng,
+    )?;
+    let (mut app, addr) = setup_contract(&user, &vk)?;
+
+    let msg = deposit_msg(
+        &hasher,
+        &pk,
+        &DepositWitness {
+            account: AccountWitness {
+                address: user.to_string(),
+                nullifier: encode_field(&Fr::rand(&mut OsRng))?,
+                blinding: encode_field(&Fr::zero())?,
+                balances: std::array::from_fn(|_| "0".to_string()),
+                index: None,
+            },
+            diffs: vec![AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            }],
+            tree_notes: vec![],
+            new_blinding: None,
+            bound_height: None,
+        },
+        &mut OsRng,
+    )?;
+    let tampered = match msg.clone() {
+        ExecuteMsg::Deposit {
+            root,
+            nullifier_hash,
+            identifier,
+            proof,
+            tag,
+            bound_height,
+            asset_bitmap,
+            ..
+        } => ExecuteMsg::Deposit {
+            root,
+            nullifier_hash,
+            identifier,
+            new_note: encode_field(&Fr::rand(&mut OsRng))?,
+            proof,
+            tag,
+            bound_height,
+            asset_bitmap,
+        },
+        _ => unreachable!("deposit_msg must build a deposit"),
+    };
+
+    // Rejected cases come first, as they leave the contract state untouched
+    let cases = [
+        (&msg, vec![Coin::new(99_999, ASSETS[0])], false),
+        (&msg, vec![Coin::new(100_000, ASSETS[1])], false),
+        (&tampered, vec![Coin::new(100_000, ASSETS[0])], false),
+        (&msg, vec![Coin::new(100_000, ASSETS[0])], true),
+    ];
+    for (msg, funds, is_valid) in cases {
+        let native = verify_deposit(&vk, &assets, msg, &funds);
+        assert_eq!(
+            native.is_ok(),
+            is_valid,
+            "Unexpected native result {native:?}"
+        );
+        if !is_valid {
+            assert!(matches!(native, Err(VerifyError::InvalidProof)));
+        }
+
+        let contract = app.execute_contract(user.clone(), addr.clone(), msg, &funds);
+        assert_eq!(
+            contract.is_ok(),
+            is_valid,
+            "Unexpected contract result {contract:?}"
+        );
+    }
+
+    assert!(matches!(
+        verify_deposit(&vk, &assets, &ExecuteMsg::TransferExcess {}, &[]),
+        Err(VerifyError::NotDeposit)
+    ));
+
+    Ok(())
+}
@@ -1,18 +1,38 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use ark_bn254::Fr;
+use ark_crypto_primitives::sponge::poseidon::PoseidonConfig;
 use ark_ff::PrimeField;
 use ark_serialize::{
     CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Valid, Write,
 };
-use ark_std::{UniformRand, Zero};
-use circuits::N_ASSETS;
-use rand::rngs::OsRng;
+use ark_std::{io, UniformRand, Zero};
+use bech32::{ToBase32, Variant};
+use circuits::{
+    encoding::{self, decode_field},
+    poseidon::PoseidonHash,
+    utils::poseidon_bn254,
+    N_ASSETS,
+};
+use rand::{rngs::OsRng, RngCore};
+use ripemd::Ripemd160;
 use serde_json::json;
-use serde_wasm_bindgen::to_value;
+use serde_wasm_bindgen::{from_value, to_value};
+use sha2::{Digest, Sha256};
 use wasm_bindgen::prelude::*;
 
-use crate::{protocol::AssetDiff, utils::serialize_to_hex};
+use crate::{error::WasmError, protocol::AssetDiff, utils::serialize_to_hex};
+
+/// Domain separators of the values derived from an account seed.
+const NULLIFIER_DOMAIN: &[u8] = b"nullifier-domain";
+const BLINDING_DOMAIN: &[u8] = b"blinding-domain";
+
+/// Serialized sizes of the fields following the balances, the nullifier, blinding and address,
+/// then the index with and without a value.
+const TAIL_SIZES: [usize; 2] = [3 * 32 + 1, 3 * 32 + 1 + 4];
+
+/// Bech32 prefix of the addresses derived from public keys.
+pub const ADDRESS_PREFIX: &str = "osmo";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Asset(pub [u128; N_ASSETS]);
@@ -39,14 +59,34 @@ impl Account {
         Self::new(address)
     }
 
+    #[wasm_bindgen(js_name = fromSeed)]
+    pub fn wasm_from_seed(address: &str, seed: &str) -> Result<Self, WasmError> {
+        Self::from_seed(address, decode_field(seed)?, &poseidon_bn254())
+    }
+
+    /// Base64 encoded [`Account::seed_blinding`] of the base64 encoded `seed`.
+    #[wasm_bindgen(js_name = seedBlinding)]
+    pub fn wasm_seed_blinding(seed: &str, counter: u64) -> Result<String, WasmError> {
+        Ok(serialize_to_hex(&Self::seed_blinding(
+            decode_field(seed)?,
+            counter,
+            &poseidon_bn254(),
+        )?)?)
+    }
+
+    #[wasm_bindgen(js_name = fromPubkey)]
+    pub fn wasm_from_pubkey(pubkey: &[u8]) -> Result<Self, WasmError> {
+        Self::from_pubkey(pubkey)
+    }
+
     #[wasm_bindgen(js_name = fromString)]
-    pub fn wasm_from_string(account: &str) -> Self {
-        Self::from_string(account)
+    pub fn wasm_from_string(account: &str) -> Result<Self, WasmError> {
+        Self::try_from_string(account)
     }
 
     #[wasm_bindgen(js_name = toString)]
-    pub fn wasm_to_string(&self) -> String {
-        self.to_string()
+    pub fn wasm_to_string(&self) -> Result<String, WasmError> {
+        self.try_to_string()
     }
 
     #[wasm_bindgen(js_name = updateIndex)]
@@ -55,26 +95,31 @@ impl Account {
     }
 
     #[wasm_bindgen(js_name = updateIndexFromString)]
-    pub fn update_account_index(account: &str, new_index: u32) -> String {
-        let mut account = Self::from_string(account);
+    pub fn update_account_index(account: &str, new_index: u32) -> Result<String, WasmError> {
+        let mut account = Self::try_from_string(account)?;
         account.index = Some(new_index);
-        account.to_string()
+        account.try_to_string()
+    }
+
+    #[wasm_bindgen(js_name = migrateAssets)]
+    pub fn wasm_migrate_assets(old_account: &str, mapping: JsValue) -> Result<String, WasmError> {
+        let mapping: Vec<usize> = from_value(mapping)?;
+        Self::migrate_assets(old_account, &mapping)
     }
 
     #[wasm_bindgen]
-    pub fn balance(&self) -> JsValue {
-        to_value(&json!(self
+    pub fn balance(&self) -> Result<JsValue, WasmError> {
+        Ok(to_value(&json!(self
             .balance
             .0
             .into_iter()
             .map(|e| e.to_string())
-            .collect::<Vec<_>>()))
-        .expect("Failed to serialize to js value")
+            .collect::<Vec<_>>()))?)
     }
 
     #[wasm_bindgen]
-    pub fn blinding(&self) -> String {
-        serialize_to_hex(&self.latest_blinding).expect("Failed to serialize blinding")
+    pub fn blinding(&self) -> Result<String, WasmError> {
+        Ok(serialize_to_hex(&self.latest_blinding)?)
     }
 
     #[wasm_bindgen]
@@ -89,32 +134,219 @@ impl Account {
             balance: Asset([0; N_ASSETS]),
             nullifier: Fr::rand(&mut OsRng),
             latest_blinding: Fr::zero(),
-            address: Fr::from_le_bytes_mod_order(address.as_bytes()),
+            address: encoding::address_to_field(address),
             index: None,
         }
     }
 
+    /// Creates an account whose nullifier and first blinding are derived from `seed` as
+    /// `H_crh([seed, domain])`. As every following blinding is derived by
+    /// [`Account::advance_blinding`], all notes of the account can be recovered from the seed
+    /// alone.
+    pub fn from_seed(
+        address: &str,
+        seed: Fr,
+        hash: &PoseidonConfig<Fr>,
+    ) -> Result<Self, WasmError> {
+        Ok(Self {
+            balance: Asset([0; N_ASSETS]),
+            nullifier: PoseidonHash::crh(
+                hash,
+                &[seed, Fr::from_le_bytes_mod_order(NULLIFIER_DOMAIN)],
+            )?,
+            latest_blinding: PoseidonHash::crh(
+                hash,
+                &[seed, Fr::from_le_bytes_mod_order(BLINDING_DOMAIN)],
+            )?,
+            address: encoding::address_to_field(address),
+            index: None,
+        })
+    }
+
+    /// Blinding of the `counter`-th note of the chain of the account recovered from `seed`, the
+    /// first blinding of [`Account::from_seed`] advanced `counter` times.
+    pub fn seed_blinding(
+        seed: Fr,
+        counter: u64,
+        hash: &PoseidonConfig<Fr>,
+    ) -> Result<Fr, WasmError> {
+        // The blindings don't depend on the address
+        let mut account = Self::from_seed("", seed, hash)?;
+        for _ in 0..counter {
+            account.advance_blinding(hash)?;
+        }
+        Ok(account.latest_blinding)
+    }
+
+    /// Creates an account for the secp256k1 `pubkey`, whose address is the bech32 encoding of
+    /// `ripemd160(sha256(pubkey))`, i.e. the `info.sender` the contract will see for that key.
+    pub fn from_pubkey(pubkey: &[u8]) -> Result<Self, WasmError> {
+        Ok(Self::new(&pubkey_to_address(pubkey)?))
+    }
+
+    /// Same as [`Account::try_from_string`], panicking on a malformed blob. Only meant for
+    /// trusted blobs such as the ones this build serialized itself.
     pub fn from_string(account: &str) -> Self {
-        Self::deserialize_compressed(&base64::decode(account).expect("Invalid account hex")[..])
-            .expect("Unable to deserialize account")
+        Self::try_from_string(account).expect("Unable to deserialize account")
+    }
+
+    /// Deserializes an account blob, including the legacy ones serialized before balances were
+    /// prefixed by their count.
+    pub fn try_from_string(account: &str) -> Result<Self, WasmError> {
+        let bytes = base64::decode(account)?;
+        let (balances, rest) = split_balances(&bytes)?;
+
+        let mut prefixed = ((balances.len() / 16) as u64).to_le_bytes().to_vec();
+        prefixed.extend(balances);
+        prefixed.extend(rest);
+        Ok(Self::deserialize_compressed(&prefixed[..])?)
+    }
+
+    /// Serializes the account into the base64 blob read by [`Account::try_from_string`].
+    pub fn try_to_string(&self) -> Result<String, WasmError> {
+        Ok(serialize_to_hex(self)?)
     }
 
-    pub fn to_string(&self) -> String {
-        serialize_to_hex(self).expect("Unable to serialize account")
+    /// Migrates an account serialized under another `N_ASSETS` to this build's assets, moving the
+    /// balance of each old asset `i` into the slot `mapping[i]`. Slots no old asset is mapped to
+    /// start empty.
+    pub fn migrate_assets(old_account: &str, mapping: &[usize]) -> Result<String, WasmError> {
+        Self::migrate_assets_to::<N_ASSETS>(old_account, mapping)
     }
 
-    pub fn update_balance(&mut self, diffs: &[AssetDiff]) {
+    /// Same as [`Account::migrate_assets`], into a layout of `M_ASSETS` assets.
+    pub fn migrate_assets_to<const M_ASSETS: usize>(
+        old_account: &str,
+        mapping: &[usize],
+    ) -> Result<String, WasmError> {
+        let bytes = base64::decode(old_account)?;
+        let (old_balances, rest) = split_balances(&bytes)?;
+        let count = old_balances.len() / 16;
+        (count == mapping.len())
+            .then_some(())
+            .ok_or(WasmError::InvalidAssetCount(count))?;
+
+        let mut balances = [None; M_ASSETS];
+        for (balance, &new_index) in old_balances.chunks(16).zip(mapping) {
+            balances
+                .get_mut(new_index)
+                .filter(|slot| slot.is_none())
+                .ok_or(WasmError::InvalidAssetMapping(new_index))?
+                .replace(u128::from_le_bytes(
+                    balance
+                        .try_into()
+                        .map_err(|_| SerializationError::InvalidData)?,
+                ));
+        }
+
+        // The remaining fields don't depend on the number of assets
+        let (nullifier, latest_blinding, address, index) =
+            <(Fr, Fr, Fr, Option<u32>)>::deserialize_compressed(rest)?;
+
+        let mut migrated = (M_ASSETS as u64).to_le_bytes().to_vec();
+        for balance in balances {
+            migrated.extend(balance.unwrap_or_default().to_le_bytes());
+        }
+        (nullifier, latest_blinding, address, index).serialize_compressed(&mut migrated)?;
+        Ok(base64::encode(migrated))
+    }
+
+    pub fn update_balance(&mut self, diffs: &[AssetDiff]) -> Result<(), WasmError> {
         for diff in diffs {
-            let amount = u128::from_str(&diff.amount).expect("Failed to parse amount");
-            match diff.is_add {
-                true => self.balance.0[diff.asset_index] += amount,
-                false => self.balance.0[diff.asset_index] -= amount,
-            }
+            let amount = u128::from_str(&diff.amount)
+                .map_err(|_| WasmError::InvalidAmount(diff.amount.clone()))?;
+            let balance = self
+                .balance
+                .0
+                .get_mut(diff.asset_index)
+                .ok_or(WasmError::InvalidAssetIndex(diff.asset_index))?;
+            *balance = match diff.is_add {
+                true => balance
+                    .checked_add(amount)
+                    .ok_or_else(|| WasmError::InvalidAmount(diff.amount.clone()))?,
+                false => balance
+                    .checked_sub(amount)
+                    .ok_or(WasmError::InsufficientBalance(diff.asset_index))?,
+            };
         }
+        Ok(())
+    }
+
+    pub fn randomize_blinding<R: RngCore>(&mut self, rng: &mut R) {
+        self.latest_blinding = Fr::rand(rng);
     }
 
-    pub fn randomize_blinding(&mut self) {
-        self.latest_blinding = Fr::rand(&mut OsRng);
+    /// Replaces the blinding by the one of the account's next note, `H_crh([nullifier, domain,
+    /// blinding])`. The nullifier is never revealed, so the next blinding stays hidden even once
+    /// a withdrawal reveals the current one.
+    pub fn advance_blinding(&mut self, hash: &PoseidonConfig<Fr>) -> Result<(), WasmError> {
+        self.latest_blinding = PoseidonHash::crh(
+            hash,
+            &[
+                self.nullifier,
+                Fr::from_le_bytes_mod_order(BLINDING_DOMAIN),
+                self.latest_blinding,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Identifier of the account's current note, `H_tto_crh(address, blinding)`.
+    pub fn identifier(&self, hash: &PoseidonConfig<Fr>) -> Result<Fr, WasmError> {
+        Ok(PoseidonHash::tto_crh(
+            hash,
+            self.address,
+            self.latest_blinding,
+        )?)
+    }
+
+    /// Commitment of the account's current note, `H_crh(balance_root, identifier, nullifier)`.
+    pub fn note(&self, hash: &PoseidonConfig<Fr>) -> Result<Fr, WasmError> {
+        let balance_root = PoseidonHash::crh(hash, &self.balance.0.map(Fr::from))?;
+        Ok(PoseidonHash::crh(
+            hash,
+            &[balance_root, self.identifier(hash)?, self.nullifier],
+        )?)
+    }
+}
+
+/// Splits a serialized account into its balances and the fields following them. Balances are
+/// either prefixed by their `u64` count or, in legacy blobs, unprefixed with their count
+/// inferred from the blob's length. Both layouts never have the same length.
+fn split_balances(bytes: &[u8]) -> Result<(&[u8], &[u8]), SerializationError> {
+    if let Some(prefix) = bytes.get(..8).and_then(|prefix| prefix.try_into().ok()) {
+        let count = u64::from_le_bytes(prefix);
+        let size = count
+            .checked_mul(16)
+            .and_then(|size| usize::try_from(size).ok());
+        if let Some(size) = size.filter(|size| {
+            TAIL_SIZES
+                .iter()
+                .any(|tail| size.checked_add(8 + tail) == Some(bytes.len()))
+        }) {
+            return Ok(bytes[8..].split_at(size));
+        }
+    }
+
+    TAIL_SIZES
+        .iter()
+        .filter_map(|tail| bytes.len().checked_sub(*tail))
+        .find(|size| size % 16 == 0)
+        .map(|size| bytes.split_at(size))
+        .ok_or(SerializationError::InvalidData)
+}
+
+/// Bech32 address of a secp256k1 public key, as derived by the cosmos SDK.
+pub fn pubkey_to_address(pubkey: &[u8]) -> Result<String, WasmError> {
+    let hash = Ripemd160::digest(Sha256::digest(pubkey));
+    bech32::encode(ADDRESS_PREFIX, hash.to_base32(), Variant::Bech32)
+        .map_err(|e| WasmError::Serialization(e.to_string()))
+}
+
+/// Base64 blob of [`Account::try_to_string`], failing the formatter if it can't be serialized.
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serialize_to_hex(self).map_err(|_| fmt::Error)?)
     }
 }
 
@@ -124,9 +356,11 @@ impl Valid for Asset {
     }
 }
 
+/// Balances are prefixed by their `u64` count, so that accounts stored under a different
+/// `N_ASSETS` are rejected instead of being misread.
 impl CanonicalSerialize for Asset {
     fn serialized_size(&self, _compress: ark_serialize::Compress) -> usize {
-        16 * N_ASSETS
+        8 + 16 * N_ASSETS
     }
 
     fn serialize_with_mode<W: Write>(
@@ -134,13 +368,15 @@ impl CanonicalSerialize for Asset {
         mut writer: W,
         _compress: ark_serialize::Compress,
     ) -> Result<(), SerializationError> {
+        writer
+            .write_all(&(N_ASSETS as u64).to_le_bytes())
+            .map_err(SerializationError::IoError)?;
         writer
             .write_all(
                 &self
                     .0
                     .into_iter()
-                    .map(|x| x.to_le_bytes())
-                    .flatten()
+                    .flat_map(|x| x.to_le_bytes())
                     .collect::<Vec<_>>(),
             )
             .map_err(SerializationError::IoError)
@@ -153,6 +389,16 @@ impl CanonicalDeserialize for Asset {
         _compress: ark_serialize::Compress,
         _validate: ark_serialize::Validate,
     ) -> Result<Self, SerializationError> {
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        if len != N_ASSETS as u64 {
+            return Err(SerializationError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Account balances were serialized with a different number of assets",
+            )));
+        }
+
         let mut bytes = [0u8; 16 * N_ASSETS];
         reader.read_exact(&mut bytes)?;
         let mut res = [0u128; N_ASSETS];
@@ -169,9 +415,15 @@ impl CanonicalDeserialize for Asset {
 
 #[cfg(test)]
 mod tests {
-    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use ark_bn254::Fr;
+    use ark_ff::PrimeField;
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+    use ark_std::io;
+    use circuits::{encoding, utils::poseidon_bn254, N_ASSETS};
 
-    use super::Asset;
+    use crate::error::WasmError;
+
+    use super::{pubkey_to_address, Account, Asset};
 
     #[test]
     fn correct_serialization() {
@@ -183,4 +435,205 @@ mod tests {
         let asset2 = Asset::deserialize_compressed(&bytes[..]).expect("deserialization failed");
         assert_eq!(asset, asset2);
     }
+
+    #[test]
+    fn different_n_assets() {
+        let account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let mut bytes = Vec::new();
+        account
+            .serialize_compressed(&mut bytes)
+            .expect("serialization failed");
+
+        // Replace the balances with the ones of a build having two less assets
+        let mut old_bytes = (N_ASSETS as u64 - 2).to_le_bytes().to_vec();
+        old_bytes.extend([0u8; 16 * (N_ASSETS - 2)]);
+        old_bytes.extend(&bytes[8 + 16 * N_ASSETS..]);
+
+        let err = Account::deserialize_compressed(&old_bytes[..]).unwrap_err();
+        assert!(
+            matches!(&err, SerializationError::IoError(e) if e.kind() == io::ErrorKind::InvalidData),
+            "Unexpected error {err:?}"
+        );
+        assert!(err
+            .to_string()
+            .contains("Account balances were serialized with a different number of assets"));
+    }
+
+    #[test]
+    fn legacy_account() {
+        // Serialized before balances were prefixed, with balances 1 to 7, nullifier 1,
+        // blinding 2 and index 3
+        let legacy = "AQAAAAAAAAAAAAAAAAAAAAIAAAAAAAAAAAAAAAAAAAADAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAAAAAAAAUAAAAAAAAAAAAAAAAAAAAGAAAAAAAAAAAAAAAAAAAABwAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABn2GlpgXIRyLTTsMsQILrtLTzhkiQRn/lL2khv/NeREQEDAAAA";
+
+        let account = Account::try_from_string(legacy).expect("Failed to read legacy account");
+        assert_eq!(account.balance, Asset([1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(account.nullifier, Fr::from(1));
+        assert_eq!(account.latest_blinding, Fr::from(2));
+        assert_eq!(
+            account.address,
+            encoding::address_to_field("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu")
+        );
+        assert_eq!(account.index, Some(3));
+
+        // Written back in the prefixed layout
+        assert_eq!(Account::from_string(&account.to_string()), account);
+        assert_eq!(
+            base64::decode(account.to_string()).expect("Invalid base64")[..8],
+            (N_ASSETS as u64).to_le_bytes()
+        );
+
+        let mut truncated = base64::decode(legacy).expect("Invalid base64");
+        truncated.pop();
+        assert!(Account::try_from_string(&base64::encode(truncated)).is_err());
+    }
+
+    #[test]
+    fn migrate_assets() {
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.balance = Asset([1, 2, 3, 4, 5, 6, 7]);
+        account.index = Some(3);
+        let blob = account.to_string();
+
+        // Moving the old assets into the first slots of a build with three more assets
+        let mapping = (0..N_ASSETS).collect::<Vec<_>>();
+        let migrated = base64::decode(
+            Account::migrate_assets_to::<10>(&blob, &mapping).expect("Failed to migrate"),
+        )
+        .expect("Invalid base64");
+        assert_eq!(migrated[..8], 10u64.to_le_bytes());
+        let balances = migrated[8..8 + 16 * 10]
+            .chunks(16)
+            .map(|b| u128::from_le_bytes(b.try_into().expect("Invalid balance length")))
+            .collect::<Vec<_>>();
+        assert_eq!(balances, [1, 2, 3, 4, 5, 6, 7, 0, 0, 0]);
+
+        let old = base64::decode(&blob).expect("Invalid base64");
+        assert_eq!(
+            migrated[8 + 16 * 10..],
+            old[8 + 16 * N_ASSETS..],
+            "Account secrets and index must be kept"
+        );
+
+        // Reordering within the same number of assets
+        let reversed = (0..N_ASSETS).rev().collect::<Vec<_>>();
+        let migrated = Account::from_string(
+            &Account::migrate_assets(&blob, &reversed).expect("Failed to migrate"),
+        );
+        assert_eq!(migrated.balance, Asset([7, 6, 5, 4, 3, 2, 1]));
+        assert_eq!(migrated.nullifier, account.nullifier);
+        assert_eq!(migrated.index, account.index);
+
+        assert!(matches!(
+            Account::migrate_assets(&blob, &mapping[1..]),
+            Err(WasmError::InvalidAssetCount(7))
+        ));
+        let mut duplicated = mapping.clone();
+        duplicated[6] = 0;
+        assert!(matches!(
+            Account::migrate_assets(&blob, &duplicated),
+            Err(WasmError::InvalidAssetMapping(0))
+        ));
+        assert!(matches!(
+            Account::migrate_assets_to::<5>(&blob, &mapping),
+            Err(WasmError::InvalidAssetMapping(5))
+        ));
+    }
+
+    #[test]
+    fn migrate_legacy_assets() {
+        // Legacy blob of a build with two assets, balances 10 and 20, nullifier 1, blinding 2
+        // and no index
+        let mut legacy = [10u128, 20].map(u128::to_le_bytes).concat();
+        (Fr::from(1), Fr::from(2), Fr::from(3), None::<u32>)
+            .serialize_compressed(&mut legacy)
+            .expect("serialization failed");
+        let legacy = base64::encode(legacy);
+
+        let migrated = Account::from_string(
+            &Account::migrate_assets(&legacy, &[6, 0]).expect("Failed to migrate"),
+        );
+        assert_eq!(migrated.balance, Asset([20, 0, 0, 0, 0, 0, 10]));
+        assert_eq!(migrated.nullifier, Fr::from(1));
+        assert_eq!(migrated.latest_blinding, Fr::from(2));
+        assert_eq!(migrated.index, None);
+
+        // The decoded count is reported, not the mapping's
+        assert!(matches!(
+            Account::migrate_assets(&legacy, &[0, 1, 2]),
+            Err(WasmError::InvalidAssetCount(2))
+        ));
+    }
+
+    #[test]
+    fn address_matches_contract_sender() {
+        let address = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+
+        // The contract derives the address from the sender's bech32 string
+        let sender_address: Fr = encoding::address_to_field(address);
+
+        assert_eq!(Account::new(address).address, sender_address);
+        assert_eq!(
+            sender_address,
+            Fr::from_le_bytes_mod_order(address.as_bytes()),
+            "Address encoding changed"
+        );
+    }
+
+    #[test]
+    fn from_pubkey_matches_contract_sender() {
+        // Compressed secp256k1 generator point
+        let pubkey =
+            hex_literal("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let address = "osmo1w508d6qejxtdg4y5r3zarvary0c5xw7kjxy2e2";
+        assert_eq!(
+            pubkey_to_address(&pubkey).expect("Failed to encode address"),
+            address
+        );
+
+        let account = Account::from_pubkey(&pubkey).expect("Failed to encode address");
+        assert_eq!(account.address, encoding::address_to_field(address));
+    }
+
+    fn hex_literal(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("Invalid hex"))
+            .collect()
+    }
+
+    #[test]
+    fn recover_from_seed() {
+        let hash = poseidon_bn254();
+        let address = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+        let seed = Fr::from(42);
+
+        let mut account =
+            Account::from_seed(address, seed, &hash).expect("Failed to derive from seed");
+        account.balance.0[0] = 100;
+        account.randomize_blinding(&mut rand::rngs::OsRng);
+        account.index = Some(3);
+
+        // Only the seed survives the loss of the account blob
+        let recovered =
+            Account::from_seed(address, seed, &hash).expect("Failed to derive from seed");
+        assert_eq!(recovered.nullifier, account.nullifier);
+        assert_ne!(
+            Account::from_seed(address, Fr::from(43), &hash)
+                .expect("Failed to derive from seed")
+                .nullifier,
+            account.nullifier
+        );
+        // The blindings of its notes are derived from the seed as well
+        let mut next = recovered;
+        next.advance_blinding(&hash)
+            .expect("Failed to derive blinding");
+        assert_eq!(
+            next.latest_blinding,
+            Account::seed_blinding(seed, 1, &hash).expect("Failed to derive from seed")
+        );
+
+        // Accounts with random nullifiers are still imported as is
+        let random = Account::new(address);
+        assert_eq!(Account::from_string(&random.to_string()), random);
+    }
 }
@@ -0,0 +1,154 @@
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::SerializationError;
+use base64::DecodeError;
+use circuits::{encoding::FieldError, merkle_tree::MerkleError};
+use serde_json::json;
+use serde_wasm_bindgen::to_value;
+use thiserror::Error;
+use wasm_bindgen::JsValue;
+
+/// Error of the wasm bindings, thrown to JS as `{ kind, detail }`.
+#[derive(Error, Debug)]
+pub enum WasmError {
+    #[error("Decode Base64: {0}")]
+    Decode(#[from] DecodeError),
+
+    #[error("Ark Serialization: {0}")]
+    Serialization(String),
+
+    #[error("Synthesis: {0}")]
+    Synthesis(String),
+
+    #[error("JS value: {0}")]
+    JsValue(String),
+
+    #[error("Merkle Tree: {0}")]
+    Merkle(String),
+
+    #[error("Field element encoding is not canonical")]
+    NonCanonicalField,
+
+    #[error("Invalid amount {0}")]
+    InvalidAmount(String),
+
+    #[error("Invalid asset index {0}")]
+    InvalidAssetIndex(usize),
+
+    #[error("Insufficient balance of asset {0}")]
+    InsufficientBalance(usize),
+
+    #[error("Invalid balance count {0}")]
+    InvalidBalanceCount(usize),
+
+    #[error("Invalid asset count {0}")]
+    InvalidAssetCount(usize),
+
+    #[error("Invalid asset mapping to slot {0}")]
+    InvalidAssetMapping(usize),
+
+    #[error("Invalid asset denom {0:?}")]
+    InvalidAsset(String),
+
+    #[error("Invalid swap route")]
+    InvalidSwapRoute,
+
+    #[error("At most {0} swap routes are supported")]
+    TooManyRoutes(usize),
+
+    #[error("Withdrawals can't be bound to a height")]
+    BoundWithdrawal,
+
+    #[error("Note not found at index {0} in provided tree, the notes may need to be refetched")]
+    NoteNotFound(u32),
+
+    #[error("Proof verification failed")]
+    InvalidProof,
+
+    #[error("Proving key is not set")]
+    ProvingKeyNotSet,
+
+    #[error(
+        "Proving key does not match circuit, expected {expected:?} (public inputs, witnesses) but found {found:?}"
+    )]
+    ProvingKeyMismatch {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+}
+
+impl WasmError {
+    /// Machine readable category of the error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Decode(_) => "decode",
+            Self::Serialization(_) => "serialization",
+            Self::Synthesis(_) => "synthesis",
+            Self::JsValue(_) => "js_value",
+            Self::Merkle(_) => "merkle",
+            Self::NonCanonicalField => "non_canonical_field",
+            Self::InvalidAmount(_) => "invalid_amount",
+            Self::InvalidAssetIndex(_) => "invalid_asset_index",
+            Self::InsufficientBalance(_) => "insufficient_balance",
+            Self::InvalidBalanceCount(_) => "invalid_balance_count",
+            Self::InvalidAssetCount(_) => "invalid_asset_count",
+            Self::InvalidAssetMapping(_) => "invalid_asset_mapping",
+            Self::InvalidAsset(_) => "invalid_asset",
+            Self::InvalidSwapRoute => "invalid_swap_route",
+            Self::TooManyRoutes(_) => "too_many_routes",
+            Self::BoundWithdrawal => "bound_withdrawal",
+            Self::NoteNotFound(_) => "note_not_found",
+            Self::InvalidProof => "invalid_proof",
+            Self::ProvingKeyNotSet => "proving_key_not_set",
+            Self::ProvingKeyMismatch { .. } => "proving_key_mismatch",
+        }
+    }
+}
+
+impl From<FieldError> for WasmError {
+    fn from(value: FieldError) -> Self {
+        match value {
+            FieldError::Decode(e) => Self::Decode(e),
+            FieldError::NonCanonical => Self::NonCanonicalField,
+        }
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for WasmError {
+    fn from(value: serde_wasm_bindgen::Error) -> Self {
+        Self::JsValue(value.to_string())
+    }
+}
+
+impl From<MerkleError> for WasmError {
+    fn from(value: MerkleError) -> Self {
+        Self::Merkle(value.to_string())
+    }
+}
+
+impl From<SerializationError> for WasmError {
+    fn from(value: SerializationError) -> Self {
+        Self::Serialization(value.to_string())
+    }
+}
+
+impl From<SynthesisError> for WasmError {
+    fn from(value: SynthesisError) -> Self {
+        Self::Synthesis(value.to_string())
+    }
+}
+
+impl From<Box<dyn ark_std::error::Error>> for WasmError {
+    fn from(value: Box<dyn ark_std::error::Error>) -> Self {
+        Self::Synthesis(value.to_string())
+    }
+}
+
+impl From<WasmError> for JsValue {
+    fn from(value: WasmError) -> Self {
+        to_value(&json!({
+            "kind": value.kind(),
+            "detail": value.to_string(),
+        }))
+        .expect("Failed to serialize error to js value")
+    }
+}
@@ -1,6 +1,8 @@
 pub mod account;
+pub mod error;
 pub mod protocol;
 pub mod smt;
+pub mod swap;
 
 mod utils;
 
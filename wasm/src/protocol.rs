@@ -1,25 +1,30 @@
-use std::{collections::BTreeMap, ops::Neg, str::FromStr};
+use std::{cell::RefCell, collections::BTreeMap, ops::Neg, str::FromStr};
 
 use ark_bn254::{Bn254, Fr};
-use ark_crypto_primitives::snark::SNARK;
-use ark_ff::{PrimeField, ToConstraintField};
-use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, ProvingKey, VerifyingKey};
+use ark_crypto_primitives::{snark::SNARK, sponge::poseidon::PoseidonConfig};
+use ark_groth16::{r1cs_to_qap::LibsnarkReduction, Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::{
+    ConstraintSynthesizer, ConstraintSystem, OptimizationGoal, SynthesisMode,
+};
 use ark_serialize::CanonicalDeserialize;
-use ark_std::Zero;
+use ark_std::{UniformRand, Zero};
 use circuits::{
+    encoding::{self, decode_field, MAX_ROUTES},
     merkle_tree::{Path, SparseMerkleTree},
     poseidon::PoseidonHash,
     utils::poseidon_bn254,
-    MainCircuitBn254, N_ASSETS, TREE_DEPTH,
+    MainCircuitBn254, SplittedSettleCircuitBn254, SplittedSpendCircuitBn254, N_ASSETS, TREE_DEPTH,
 };
 use osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountIn;
-use rand::rngs::OsRng;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, to_vec};
+use serde_json::{json, Value};
 use serde_wasm_bindgen::{from_value, to_value};
 use wasm_bindgen::prelude::*;
 
-use crate::{account::Account, utils::serialize_to_hex};
+use crate::{account::Account, error::WasmError, utils::serialize_to_hex};
+
+type Tree = SparseMerkleTree<Fr, PoseidonHash<Fr>, { TREE_DEPTH }>;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AssetDiff {
@@ -29,16 +34,131 @@ pub struct AssetDiff {
 }
 
 impl AssetDiff {
-    pub fn balances(diffs: &[Self]) -> [Fr; N_ASSETS] {
+    pub fn balances(diffs: &[Self]) -> Result<[Fr; N_ASSETS], WasmError> {
         let mut balances = [Fr::zero(); N_ASSETS];
         for diff in diffs {
-            balances[diff.asset_index] =
-                Fr::from(u128::from_str(&diff.amount).expect("Failed to parse amount"));
+            let balance = balances
+                .get_mut(diff.asset_index)
+                .ok_or(WasmError::InvalidAssetIndex(diff.asset_index))?;
+            *balance = Fr::from(
+                u128::from_str(&diff.amount)
+                    .map_err(|_| WasmError::InvalidAmount(diff.amount.clone()))?,
+            );
             if !diff.is_add {
-                balances[diff.asset_index] = balances[diff.asset_index].neg();
+                *balance = balance.neg();
             };
         }
-        balances
+        Ok(balances)
+    }
+}
+
+/// Note of the tree with the public balance changes of the transaction that inserted it: the funds
+/// of a deposit, the withdrawn assets of a withdrawal as subtractions, or the subtracted
+/// `token_in` and the added `token_out_min_amount` of a swap.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainNote {
+    pub note: String,
+    pub diffs: Vec<AssetDiff>,
+}
+
+fn tree_from_notes(notes: &[String], hash: &PoseidonConfig<Fr>) -> Result<Tree, WasmError> {
+    let leaves = notes
+        .iter()
+        .enumerate()
+        .map(|(i, l)| Ok((i as u32, decode_field(l)?)))
+        .collect::<Result<BTreeMap<_, _>, WasmError>>()?;
+    Ok(SparseMerkleTree::new(&leaves, hash, &Fr::zero())?)
+}
+
+/// Generates the membership path of the account's `old_note` at `index` of `tree`, failing if the
+/// tree built from the client's notes doesn't hold it there, e.g. because they are stale.
+fn old_note_path(
+    tree: &Tree,
+    index: u32,
+    old_note: &Fr,
+    hash: &PoseidonConfig<Fr>,
+) -> Result<Path<Fr, PoseidonHash<Fr>, { TREE_DEPTH }>, WasmError> {
+    let path = tree.generate_membership_proof(index as u64);
+    path.check_membership(&tree.root(), old_note, hash)
+        .unwrap_or(false)
+        .then_some(path)
+        .ok_or(WasmError::NoteNotFound(index))
+}
+
+/// Denom of the contract's `assets` moved by `diff`.
+fn asset_denom<'a>(assets: &'a [String], diff: &AssetDiff) -> Result<&'a String, WasmError> {
+    assets
+        .get(diff.asset_index)
+        .ok_or(WasmError::InvalidAssetIndex(diff.asset_index))
+}
+
+/// Calculates the `aux` public input binding a deposit to `bound_height` as the contract does.
+/// The contract verifies withdrawals against the zero `aux`, so they can't be bound to a height.
+fn deposit_withdraw_aux(diffs: &[AssetDiff], bound_height: Option<u64>) -> Result<Fr, WasmError> {
+    match bound_height {
+        Some(_) if diffs.iter().any(|diff| !diff.is_add) => Err(WasmError::BoundWithdrawal),
+        Some(bound_height) => Ok(Fr::from(bound_height)),
+        None => Ok(Fr::zero()),
+    }
+}
+
+/// Calculates the `aux` public input exactly as the contract does for `swap_argument`.
+pub fn swap_aux(
+    hash: &PoseidonConfig<Fr>,
+    swap_argument: &MsgSwapExactAmountIn,
+    timeout: Option<u64>,
+) -> Result<Fr, WasmError> {
+    let token_in = swap_argument
+        .token_in
+        .as_ref()
+        .ok_or(WasmError::InvalidSwapRoute)?;
+    let routes = swap_argument
+        .routes
+        .iter()
+        .map(|r| (r.pool_id, r.token_out_denom.as_str()))
+        .collect::<Vec<_>>();
+
+    let amount = |amount: &str| {
+        u128::from_str(amount).map_err(|_| WasmError::InvalidAmount(amount.to_string()))
+    };
+
+    Ok(encoding::swap_aux(
+        hash,
+        &routes,
+        &token_in.denom,
+        amount(&token_in.amount)?,
+        amount(&swap_argument.token_out_min_amount)?,
+        timeout,
+    )?)
+}
+
+thread_local! {
+    /// Main circuit proving key set by [`Protocol::set_proving_key`], deserialized once.
+    static PROVING_KEY: RefCell<Option<ProvingKey<Bn254>>> = const { RefCell::new(None) };
+}
+
+/// Proven main circuit transition of an account's latest note into the note of `new_account`.
+struct Transition {
+    /// Public inputs in the order the contract rebuilds them, `[aux, root, diff_balance_root,
+    /// nullifier_hash, identifier, new_note]`.
+    public_inputs: [Fr; 6],
+    proof: Proof<Bn254>,
+    new_account: Account,
+}
+
+impl Transition {
+    /// Base64 encoded public fields and proof of the transition, with the new account.
+    fn output(&self) -> Result<Value, WasmError> {
+        let [_, root, diff_balance_root, nullifier_hash, identifier, new_note] = self.public_inputs;
+        Ok(json!({
+            "diff_balance_root": serialize_to_hex(&diff_balance_root)?,
+            "proof": serialize_to_hex(&self.proof)?,
+            "root": serialize_to_hex(&root)?,
+            "nullifier_hash": serialize_to_hex(&nullifier_hash)?,
+            "identifier": serialize_to_hex(&identifier)?,
+            "new_note": serialize_to_hex(&new_note)?,
+            "new_account": self.new_account.try_to_string()?,
+        }))
     }
 }
 
@@ -47,6 +167,34 @@ pub struct Protocol;
 
 #[wasm_bindgen]
 impl Protocol {
+    #[wasm_bindgen(js_name = validate_membership)]
+    pub fn wasm_validate_membership(account: &str, tree_notes: JsValue) -> Result<bool, WasmError> {
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+        Self::validate_membership(&Account::try_from_string(account)?, &tree_notes)
+    }
+
+    #[wasm_bindgen(js_name = compute_note)]
+    pub fn wasm_compute_note(
+        balances: JsValue,
+        identifier: &str,
+        nullifier: &str,
+    ) -> Result<String, WasmError> {
+        let balances: Vec<String> = from_value(balances)?;
+        Self::compute_note(&balances, identifier, nullifier)
+    }
+
+    #[wasm_bindgen(js_name = diff_balance_root)]
+    pub fn wasm_diff_balance_root(diffs: JsValue) -> Result<String, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        Self::diff_balance_root(&diffs)
+    }
+
+    #[wasm_bindgen(js_name = withdraw_diff_root)]
+    pub fn wasm_withdraw_diff_root(withdrawn: JsValue) -> Result<String, WasmError> {
+        let withdrawn = from_value::<Vec<AssetDiff>>(withdrawn)?;
+        Self::withdraw_diff_root(&withdrawn)
+    }
+
     #[wasm_bindgen]
     pub fn deposit_withdraw_with_check(
         pk: &[u8],
@@ -54,46 +202,541 @@ impl Protocol {
         account: &str,
         tree_notes: JsValue,
         diffs: JsValue,
-    ) -> JsValue {
-        let hash = poseidon_bn254();
+        bound_height: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
 
-        // Deserialize diffs
-        let diffs =
-            from_value::<Vec<AssetDiff>>(diffs).expect("Failed to deserialize balance diffs");
-
-        let leaf_list: Vec<String> = from_value(tree_notes).expect("Failed to parse leaf list");
-        let length = leaf_list.len();
-        let tree = SparseMerkleTree::new(
-            &BTreeMap::from_iter(leaf_list.into_iter().enumerate().map(|(i, l)| {
-                (
-                    i as u32,
-                    Fr::from_le_bytes_mod_order(&base64::decode(l).unwrap()),
-                )
-            })),
+        Ok(to_value(&Self::deposit_withdraw_with_check_with_rng(
+            pk,
+            vk,
+            &Account::try_from_string(account)?,
+            &tree_notes,
+            &diffs,
+            bound_height,
+            &mut OsRng,
+        )?)?)
+    }
+
+    /// Proves the transition of `account` applying `diffs`. A deposit into a contract with a
+    /// maximum proof age must be bound to a recent block height with `bound_height`, which is
+    /// rejected for a withdrawal as the contract never binds those.
+    #[wasm_bindgen]
+    pub fn deposit_withdraw(
+        pk: &[u8],
+        account: &str,
+        tree_notes: JsValue,
+        diffs: JsValue,
+        bound_height: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        Ok(to_value(&Self::deposit_withdraw_with_rng(
+            pk,
+            &Account::try_from_string(account)?,
+            &tree_notes,
+            &diffs,
+            bound_height,
+            &mut OsRng,
+        )?)?)
+    }
+
+    /// Proves a deposit for each diff set of `diffs_list`, each one spending the note of the
+    /// previous one as inserted right after `tree_notes`. Returns the deposits in order, along
+    /// with the account holding all of them as `new_account`.
+    #[wasm_bindgen]
+    pub fn batch_deposit(
+        pk: &[u8],
+        account: &str,
+        tree_notes: JsValue,
+        diffs_list: JsValue,
+        bound_height: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let diffs_list = from_value::<Vec<Vec<AssetDiff>>>(diffs_list)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        Ok(to_value(&Self::batch_deposit_with_rng(
+            pk,
+            &Account::try_from_string(account)?,
+            &tree_notes,
+            &diffs_list,
+            bound_height,
+            &mut OsRng,
+        )?)?)
+    }
+
+    /// Computes the public fields [`Protocol::deposit_withdraw`] would prove for `diffs`, without
+    /// the root nor the proof. The new note commits to the account's next blinding, so all of
+    /// them are reproduced by the proof.
+    #[wasm_bindgen(js_name = deposit_preview)]
+    pub fn wasm_deposit_preview(account: &str, diffs: JsValue) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+
+        Ok(to_value(&Self::deposit_preview(
+            &Account::try_from_string(account)?,
+            &diffs,
+        )?)?)
+    }
+
+    /// Deposits `diffs` into a new note owned by `recipient_address`, whose account is returned
+    /// as `new_account` for the recipient to import.
+    #[wasm_bindgen]
+    pub fn deposit_for(
+        pk: &[u8],
+        recipient_address: &str,
+        tree_notes: JsValue,
+        diffs: JsValue,
+        bound_height: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        Ok(to_value(&Self::deposit_for_with_rng(
+            pk,
+            recipient_address,
+            &tree_notes,
+            &diffs,
+            bound_height,
+            &mut OsRng,
+        )?)?)
+    }
+
+    #[wasm_bindgen(js_name = set_proving_key)]
+    pub fn wasm_set_proving_key(pk: &[u8]) -> Result<(), WasmError> {
+        Self::set_proving_key(pk)
+    }
+
+    /// Drops the proving key set by [`Protocol::set_proving_key`], freeing its memory.
+    #[wasm_bindgen]
+    pub fn clear_proving_key() {
+        PROVING_KEY.with(|key| key.borrow_mut().take());
+    }
+
+    /// Same as [`Protocol::deposit_withdraw`], with the proving key set by
+    /// [`Protocol::set_proving_key`].
+    #[wasm_bindgen]
+    pub fn deposit_withdraw_cached(
+        account: &str,
+        tree_notes: JsValue,
+        diffs: JsValue,
+        bound_height: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        let account = Account::try_from_string(account)?;
+        let output = Self::with_proving_key(|pk| {
+            Self::deposit_withdraw_with_key(
+                pk,
+                &account,
+                &tree_notes,
+                &diffs,
+                bound_height,
+                &mut OsRng,
+            )
+        })??;
+        Ok(to_value(&output)?)
+    }
+
+    /// Same as [`Protocol::swap`], with the proving key set by [`Protocol::set_proving_key`].
+    #[wasm_bindgen]
+    pub fn swap_cached(
+        account: &str,
+        tree_notes: JsValue,
+        diffs: JsValue,
+        swap_argument: JsValue,
+        timeout: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let swap_argument: MsgSwapExactAmountIn = from_value(swap_argument)?;
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        let account = Account::try_from_string(account)?;
+        let output = Self::with_proving_key(|pk| {
+            Self::swap_with_key(
+                pk,
+                &account,
+                &tree_notes,
+                &diffs,
+                &swap_argument,
+                timeout,
+                &mut OsRng,
+            )
+        })??;
+        Ok(to_value(&output)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn swap(
+        pk: &[u8],
+        account: &str,
+        tree_notes: JsValue,
+        diffs: JsValue,
+        swap_argument: JsValue,
+        timeout: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let swap_argument: MsgSwapExactAmountIn = from_value(swap_argument)?;
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        Ok(to_value(&Self::swap_with_rng(
+            pk,
+            &Account::try_from_string(account)?,
+            &tree_notes,
+            &diffs,
+            &swap_argument,
+            timeout,
+            &mut OsRng,
+        )?)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn split_spend(
+        pk_spend: &[u8],
+        account: &str,
+        tree_notes: JsValue,
+    ) -> Result<JsValue, WasmError> {
+        let tree_notes: Vec<String> = from_value(tree_notes)?;
+
+        Ok(to_value(&Self::split_spend_with_rng(
+            pk_spend,
+            &Account::try_from_string(account)?,
+            &tree_notes,
+            &mut OsRng,
+        )?)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn split_settle(
+        pk_settle: &[u8],
+        account: &str,
+        diffs: JsValue,
+        aux: &str,
+    ) -> Result<JsValue, WasmError> {
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+
+        Ok(to_value(&Self::split_settle_with_rng(
+            pk_settle,
+            &Account::try_from_string(account)?,
+            &diffs,
+            decode_field(aux)?,
+            &mut OsRng,
+        )?)?)
+    }
+
+    /// Calculates the base64 encoded identifier the contract derives for a withdrawal from
+    /// `sender` with the base64 encoded `blinding`, `H_tto_crh(address, blinding)`.
+    #[wasm_bindgen]
+    pub fn withdraw_identifier(sender: &str, blinding: &str) -> Result<String, WasmError> {
+        let hash = poseidon_bn254();
+        let identifier = PoseidonHash::tto_crh(
             &hash,
-            &Fr::zero(),
+            encoding::address_to_field(sender),
+            decode_field(blinding)?,
+        )?;
+        Ok(serialize_to_hex(&identifier)?)
+    }
+
+    /// Follows the spend chain of the account recovered from `seed` through the [`ChainNote`]s of
+    /// the tree in insertion order, returning the index, balances, account and blinding counter
+    /// of its first note whose nullifier hash `nullifier_used_fn` reports as unspent, or `null` if
+    /// the chain ends with a spent note.
+    ///
+    /// The `counter`-th note of the chain commits to [`Account::seed_blinding`] `counter` and to
+    /// the balances of the previous note with the public diffs of its own transaction applied,
+    /// so it is the first note after the previous one matching them. `nullifier_used_fn` is
+    /// called synchronously with each base64 encoded nullifier hash, so the spent nullifiers have
+    /// to be fetched beforehand.
+    #[wasm_bindgen]
+    pub fn find_latest_note(
+        seed: &str,
+        address: &str,
+        notes: JsValue,
+        nullifier_used_fn: &js_sys::Function,
+    ) -> Result<JsValue, WasmError> {
+        let notes = from_value::<Vec<ChainNote>>(notes)?;
+
+        Ok(to_value(&Self::find_latest_note_with(
+            decode_field(seed)?,
+            address,
+            &notes,
+            |nullifier_hash| {
+                nullifier_used_fn
+                    .call1(&JsValue::NULL, &JsValue::from_str(nullifier_hash))
+                    .map(|used| used.is_truthy())
+                    .map_err(|e| WasmError::JsValue(format!("{e:?}")))
+            },
+        )?)?)
+    }
+
+    /// Verifies a deposit `proof` against `vk` with the public inputs the contract rebuilds from
+    /// the base64 encoded deposit fields and `bound_height`, so that a proof it would reject isn't
+    /// broadcast. Any malformed input is reported as an invalid proof.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_deposit_locally(
+        vk: &[u8],
+        root: &str,
+        diff_balance_root: &str,
+        nullifier_hash: &str,
+        identifier: &str,
+        new_note: &str,
+        proof: &str,
+        bound_height: Option<u64>,
+    ) -> bool {
+        let verify = || -> Result<bool, WasmError> {
+            let public_inputs = [
+                bound_height.map(Fr::from).unwrap_or_default(),
+                decode_field(root)?,
+                decode_field(diff_balance_root)?,
+                decode_field(nullifier_hash)?,
+                decode_field(identifier)?,
+                decode_field(new_note)?,
+            ];
+            let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(vk)?;
+            let proof = Proof::deserialize_compressed_unchecked(&base64::decode(proof)?[..])?;
+            Ok(Groth16::<Bn254, LibsnarkReduction>::verify(
+                &vk,
+                &public_inputs,
+                &proof,
+            )?)
+        };
+        verify().unwrap_or(false)
+    }
+
+    #[wasm_bindgen(js_name = apply_swap_result)]
+    pub fn wasm_apply_swap_result(
+        account: &str,
+        in_asset_index: usize,
+        in_amount: &str,
+        out_asset_index: usize,
+        out_min_amount: &str,
+    ) -> Result<JsValue, WasmError> {
+        Ok(to_value(
+            &Self::apply_swap_result(
+                &Account::try_from_string(account)?,
+                in_asset_index,
+                in_amount,
+                out_asset_index,
+                out_min_amount,
+            )?
+            .try_to_string()?,
+        )?)
+    }
+
+    #[wasm_bindgen(js_name = asset_denoms)]
+    pub fn wasm_asset_denoms(assets: JsValue) -> Result<JsValue, WasmError> {
+        let assets: Vec<String> = from_value(assets)?;
+        Ok(to_value(&Self::asset_denoms(&assets)?)?)
+    }
+
+    #[wasm_bindgen(js_name = build_deposit_msg)]
+    pub fn wasm_build_deposit_msg(
+        output: JsValue,
+        diffs: JsValue,
+        assets: JsValue,
+    ) -> Result<JsValue, WasmError> {
+        let output: Value = from_value(output)?;
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let assets: Vec<String> = from_value(assets)?;
+
+        Ok(to_value(&Self::build_deposit_msg(
+            &output, &diffs, &assets,
+        )?)?)
+    }
+
+    #[wasm_bindgen(js_name = build_withdraw_msg)]
+    pub fn wasm_build_withdraw_msg(
+        account: &str,
+        output: JsValue,
+        diffs: JsValue,
+        assets: JsValue,
+    ) -> Result<JsValue, WasmError> {
+        let output: Value = from_value(output)?;
+        let diffs = from_value::<Vec<AssetDiff>>(diffs)?;
+        let assets: Vec<String> = from_value(assets)?;
+
+        Ok(to_value(&Self::build_withdraw_msg(
+            &Account::try_from_string(account)?,
+            &output,
+            &diffs,
+            &assets,
+        )?)?)
+    }
+
+    #[wasm_bindgen(js_name = build_swap_msg)]
+    pub fn wasm_build_swap_msg(
+        output: JsValue,
+        swap_argument: JsValue,
+        timeout: Option<u64>,
+    ) -> Result<JsValue, WasmError> {
+        let output: Value = from_value(output)?;
+        let swap_argument: MsgSwapExactAmountIn = from_value(swap_argument)?;
+
+        Ok(to_value(&Self::build_swap_msg(
+            &output,
+            &swap_argument,
+            timeout,
+        ))?)
+    }
+}
+
+impl Protocol {
+    /// Deserializes `pk`, checking that it was generated for the main circuit of `N_ASSETS` and
+    /// `TREE_DEPTH` by comparing its public input and witness counts with the circuit's.
+    pub fn main_proving_key(pk: &[u8]) -> Result<ProvingKey<Bn254>, WasmError> {
+        let pk = ProvingKey::<Bn254>::deserialize_uncompressed_unchecked(pk)?;
+
+        let cs = ConstraintSystem::new_ref();
+        cs.set_optimization_goal(OptimizationGoal::Constraints);
+        cs.set_mode(SynthesisMode::Setup);
+        MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254())
+            .generate_constraints(cs.clone())?;
+        cs.finalize();
+
+        let expected = (cs.num_instance_variables(), cs.num_witness_variables());
+        let found = (pk.vk.gamma_abc_g1.len(), pk.l_query.len());
+        (expected == found)
+            .then_some(pk)
+            .ok_or(WasmError::ProvingKeyMismatch { expected, found })
+    }
+
+    /// Deserializes the main circuit proving key once and caches it for the `*_cached` proving
+    /// methods, replacing any previously set key.
+    pub fn set_proving_key(pk: &[u8]) -> Result<(), WasmError> {
+        let pk = Self::main_proving_key(pk)?;
+        PROVING_KEY.with(|key| key.replace(Some(pk)));
+        Ok(())
+    }
+
+    /// Runs `f` with the proving key set by [`Protocol::set_proving_key`], failing if none is set.
+    pub fn with_proving_key<T>(f: impl FnOnce(&ProvingKey<Bn254>) -> T) -> Result<T, WasmError> {
+        PROVING_KEY.with(|key| {
+            key.borrow()
+                .as_ref()
+                .map(f)
+                .ok_or(WasmError::ProvingKeyNotSet)
+        })
+    }
+
+    /// Same as [`Protocol::deposit_withdraw_with_check`], with the proof randomized by `rng`.
+    pub fn deposit_withdraw_with_check_with_rng<R: RngCore + CryptoRng>(
+        pk: &[u8],
+        vk: &[u8],
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        bound_height: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let transition = Self::prove_transition(
+            &Self::main_proving_key(pk)?,
+            account,
+            tree_notes,
+            diffs,
+            deposit_withdraw_aux(diffs, bound_height)?,
+            rng,
+        )?;
+
+        Groth16::<Bn254, LibsnarkReduction>::verify(
+            &VerifyingKey::deserialize_uncompressed_unchecked(vk)?,
+            &transition.public_inputs,
+            &transition.proof,
+        )?
+        .then_some(())
+        .ok_or(WasmError::InvalidProof)?;
+
+        Self::deposit_withdraw_output(account, &transition, bound_height)
+    }
+
+    /// Same as [`Protocol::deposit_withdraw`], with the proof randomized by `rng`.
+    pub fn deposit_withdraw_with_rng<R: RngCore + CryptoRng>(
+        pk: &[u8],
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        bound_height: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        Self::deposit_withdraw_with_key(
+            &Self::main_proving_key(pk)?,
+            account,
+            tree_notes,
+            diffs,
+            bound_height,
+            rng,
         )
-        .expect("Failed to create merkle tree");
+    }
+
+    /// Same as [`Protocol::deposit_withdraw_with_rng`], with the proving key deserialized.
+    pub fn deposit_withdraw_with_key<R: RngCore + CryptoRng>(
+        pk: &ProvingKey<Bn254>,
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        bound_height: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let transition = Self::prove_transition(
+            pk,
+            account,
+            tree_notes,
+            diffs,
+            deposit_withdraw_aux(diffs, bound_height)?,
+            rng,
+        )?;
+        Self::deposit_withdraw_output(account, &transition, bound_height)
+    }
+
+    /// Output of a deposit or withdrawal `transition` of `account`, the account before it.
+    fn deposit_withdraw_output(
+        account: &Account,
+        transition: &Transition,
+        bound_height: Option<u64>,
+    ) -> Result<Value, WasmError> {
+        let mut output = transition.output()?;
+        output["is_index_empty"] = json!(account.index.is_none());
+        output["bound_height"] = json!(bound_height);
+        Ok(output)
+    }
+
+    /// Proves with `pk` the transition of the account's latest note applying `diffs` into a new
+    /// note inserted after `tree_notes`, bound to `aux`, with the proof randomized by `rng`. The
+    /// new note commits to the account's next blinding, so that the notes of an account recovered
+    /// from its seed can be found again by [`Protocol::find_latest_note`].
+    ///
+    /// Accounts without an index have no note yet, the transition is then a first deposit
+    /// against the zero root.
+    fn prove_transition<R: RngCore + CryptoRng>(
+        pk: &ProvingKey<Bn254>,
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        aux: Fr,
+        rng: &mut R,
+    ) -> Result<Transition, WasmError> {
+        let hash = poseidon_bn254();
+
+        let length = tree_notes.len();
+        let tree = tree_from_notes(tree_notes, &hash)?;
 
         // Update account balance and blinding
-        let account = Account::from_string(account);
-        let mut new_account = *&account;
-        new_account.update_balance(&diffs);
-        new_account.randomize_blinding();
+        let account = *account;
+        let mut new_account = account;
+        new_account.update_balance(diffs)?;
+        new_account.advance_blinding(&hash)?;
         new_account.update_index(Some(length as u32));
 
         // Calculate diff balances and diff balance root
-        let diff_balances = AssetDiff::balances(&diffs);
-        let diff_balance_root =
-            PoseidonHash::crh(&hash, &diff_balances).expect("Failed to hash balance root");
+        let diff_balances = AssetDiff::balances(diffs)?;
+        let diff_balance_root = PoseidonHash::crh(&hash, &diff_balances)?;
 
         // Calculate old note and old note nullifier hash
         let old_note_balances = account.balance.0.map(Fr::from);
-        let old_note_balance_root =
-            PoseidonHash::crh(&hash, &old_note_balances).expect("Failed to hash balance root");
+        let old_note_balance_root = PoseidonHash::crh(&hash, &old_note_balances)?;
         let old_note_identifier =
-            PoseidonHash::tto_crh(&hash, account.address, account.latest_blinding)
-                .expect("Failed to hash identifier");
+            PoseidonHash::tto_crh(&hash, account.address, account.latest_blinding)?;
         let old_note = PoseidonHash::crh(
             &hash,
             &[
@@ -101,52 +744,38 @@ impl Protocol {
                 old_note_identifier,
                 account.nullifier,
             ],
-        )
-        .expect("Failed to hash old note");
+        )?;
 
         // Calculate old note path and old note nullifier hash
         let (merkle_path, old_note_nullifier_hash, root) = match account.index {
             Some(i) => (
-                tree.generate_membership_proof(i as u64),
-                PoseidonHash::tto_crh(&hash, old_note, account.nullifier)
-                    .expect("Failed to hash nullifier"),
+                old_note_path(&tree, i, &old_note, &hash)?,
+                PoseidonHash::tto_crh(&hash, old_note, account.nullifier)?,
                 tree.root(),
             ),
             None => (Path::empty(), Fr::zero(), Fr::zero()),
         };
 
-        if account.index.is_some() {
-            merkle_path
-                .check_membership(&root, &old_note, &hash)
-                .expect("Failed to calculate membership")
-                .then_some(())
-                .expect("Failed to check membership");
-        }
-
         // Calculate new note and new note nullifier hash
         let new_note_blinding = new_account.latest_blinding;
         let new_note_balances: [Fr; N_ASSETS] = new_account.balance.0.map(Fr::from);
-        let new_note_balance_root =
-            PoseidonHash::crh(&hash, &new_note_balances).expect("Failed to hash balance root");
+        let new_note_balance_root = PoseidonHash::crh(&hash, &new_note_balances)?;
         let new_note = PoseidonHash::crh(
             &hash,
             &[
                 new_note_balance_root,
-                PoseidonHash::tto_crh(&hash, account.address, new_note_blinding)
-                    .expect("Failed to hash identifier"),
+                PoseidonHash::tto_crh(&hash, account.address, new_note_blinding)?,
                 account.nullifier,
             ],
-        )
-        .expect("Failed to hash new note");
+        )?;
 
         // Generate proof
         let proof = Groth16::<Bn254>::prove(
-            &ProvingKey::deserialize_uncompressed_unchecked(pk)
-                .expect("Failed to deserialize proving key"),
+            pk,
             MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }> {
                 address: account.address,
                 nullifier: account.nullifier,
-                aux: Fr::zero(),
+                aux,
                 utxo_root: root,
                 diff_balance_root,
                 diff_balances,
@@ -160,314 +789,1823 @@ impl Protocol {
                 parameters: hash,
                 _hg: std::marker::PhantomData,
             },
-            &mut OsRng,
-        )
-        .expect("Failed to generate proof");
+            rng,
+        )?;
 
-        Groth16::<Bn254, LibsnarkReduction>::verify(
-            &VerifyingKey::deserialize_uncompressed_unchecked(vk)
-                .expect("Failed to deserialize verifying key"),
-            &[
-                Fr::zero(),
+        Ok(Transition {
+            public_inputs: [
+                aux,
                 root,
                 diff_balance_root,
                 old_note_nullifier_hash,
                 old_note_identifier,
                 new_note,
             ],
-            &proof,
-        )
-        .expect("Failed to verify proof")
-        .then_some(())
-        .expect("Proof verification failed");
+            proof,
+            new_account,
+        })
+    }
+
+    /// Same as [`Protocol::batch_deposit`], with the proofs randomized by `rng`.
+    pub fn batch_deposit_with_rng<R: RngCore + CryptoRng>(
+        pk: &[u8],
+        account: &Account,
+        tree_notes: &[String],
+        diffs_list: &[Vec<AssetDiff>],
+        bound_height: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let pk = Self::main_proving_key(pk)?;
+
+        let mut account = *account;
+        let mut tree_notes = tree_notes.to_vec();
+        let mut deposits = Vec::with_capacity(diffs_list.len());
+        for diffs in diffs_list {
+            let transition = Self::prove_transition(
+                &pk,
+                &account,
+                &tree_notes,
+                diffs,
+                deposit_withdraw_aux(diffs, bound_height)?,
+                rng,
+            )?;
+            let [.., new_note] = transition.public_inputs;
+            deposits.push(Self::deposit_withdraw_output(
+                &account,
+                &transition,
+                bound_height,
+            )?);
+            account = transition.new_account;
+            tree_notes.push(serialize_to_hex(&new_note)?);
+        }
+
+        Ok(json!({
+            "deposits": deposits,
+            "new_account": account.try_to_string()?,
+        }))
+    }
+
+    /// Same as [`Protocol::wasm_deposit_preview`], with the account deserialized.
+    pub fn deposit_preview(account: &Account, diffs: &[AssetDiff]) -> Result<Value, WasmError> {
+        let hash = poseidon_bn254();
+
+        // Update account balance and blinding
+        let mut new_account = *account;
+        new_account.update_balance(diffs)?;
+        new_account.advance_blinding(&hash)?;
+
+        let diff_balance_root = PoseidonHash::crh(&hash, &AssetDiff::balances(diffs)?)?;
+        let old_note_identifier = account.identifier(&hash)?;
+        let old_note_nullifier_hash = match account.index {
+            Some(_) => PoseidonHash::tto_crh(&hash, account.note(&hash)?, account.nullifier)?,
+            None => Fr::zero(),
+        };
 
-        // Return proof and new account
-        to_value(&json!({
+        Ok(json!({
             "is_index_empty": account.index.is_none(),
-            "diff_balance_root": serialize_to_hex(&diff_balance_root).expect("Failed to serialize diff balance root"),
-            "proof": serialize_to_hex(&proof).expect("Failed to serialize proof"),
-            "root": serialize_to_hex(&root).expect("Failed to serialize root"),
-            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash).expect("Failed to serialize nullifier hash"),
-            "identifier": serialize_to_hex(&old_note_identifier).expect("Failed to serialize identifier"),
-            "new_note": serialize_to_hex(&new_note).expect("Failed to serialize new note"),
-            "new_account": new_account.to_string(),
+            "diff_balance_root": serialize_to_hex(&diff_balance_root)?,
+            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash)?,
+            "identifier": serialize_to_hex(&old_note_identifier)?,
+            "new_note": serialize_to_hex(&new_account.note(&hash)?)?,
         }))
-        .expect("Failed to serialize to js value")
     }
 
-    #[wasm_bindgen]
-    pub fn deposit_withdraw(
+    /// Same as [`Protocol::deposit_for`], with the recipient's nullifier, first blinding and the
+    /// proof randomized by `rng`.
+    pub fn deposit_for_with_rng<R: RngCore + CryptoRng>(
         pk: &[u8],
-        account: &str,
-        tree_notes: JsValue,
-        diffs: JsValue,
-    ) -> JsValue {
-        let hash = poseidon_bn254();
+        recipient_address: &str,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        bound_height: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let mut recipient = Account::new(recipient_address);
+        recipient.nullifier = Fr::rand(rng);
+        recipient.randomize_blinding(rng);
 
-        // Deserialize diffs
-        let diffs =
-            from_value::<Vec<AssetDiff>>(diffs).expect("Failed to deserialize balance diffs");
-
-        let leaf_list: Vec<String> = from_value(tree_notes).expect("Failed to parse leaf list");
-        let length = leaf_list.len();
-        let tree = SparseMerkleTree::new(
-            &BTreeMap::from_iter(leaf_list.into_iter().enumerate().map(|(i, l)| {
-                (
-                    i as u32,
-                    Fr::from_le_bytes_mod_order(&base64::decode(l).unwrap()),
-                )
-            })),
-            &hash,
-            &Fr::zero(),
+        Self::deposit_withdraw_with_rng(pk, &recipient, tree_notes, diffs, bound_height, rng)
+    }
+
+    /// Same as [`Protocol::swap`], with the proof randomized by `rng`.
+    pub fn swap_with_rng<R: RngCore + CryptoRng>(
+        pk: &[u8],
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        swap_argument: &MsgSwapExactAmountIn,
+        timeout: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        Self::swap_with_key(
+            &Self::main_proving_key(pk)?,
+            account,
+            tree_notes,
+            diffs,
+            swap_argument,
+            timeout,
+            rng,
         )
-        .expect("Failed to create merkle tree");
+    }
 
-        // Update account balance and blinding
-        let account = Account::from_string(account);
-        let mut new_account = *&account;
-        new_account.update_balance(&diffs);
-        new_account.randomize_blinding();
-        new_account.update_index(Some(length as u32));
+    /// Same as [`Protocol::swap_with_rng`], with the proving key deserialized.
+    pub fn swap_with_key<R: RngCore + CryptoRng>(
+        pk: &ProvingKey<Bn254>,
+        account: &Account,
+        tree_notes: &[String],
+        diffs: &[AssetDiff],
+        swap_argument: &MsgSwapExactAmountIn,
+        timeout: Option<u64>,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        // The contract would reject the swap before verifying the proof
+        (swap_argument.routes.len() <= MAX_ROUTES)
+            .then_some(())
+            .ok_or(WasmError::TooManyRoutes(MAX_ROUTES))?;
+        // Only notes in the tree can be swapped from
+        account
+            .index
+            .ok_or(WasmError::NoteNotFound(tree_notes.len() as u32))?;
 
-        // Calculate diff balances and diff balance root
-        let diff_balances = AssetDiff::balances(&diffs);
-        let diff_balance_root =
-            PoseidonHash::crh(&hash, &diff_balances).expect("Failed to hash balance root");
+        let aux = swap_aux(&poseidon_bn254(), swap_argument, timeout)?;
+        Self::prove_transition(pk, account, tree_notes, diffs, aux, rng)?.output()
+    }
 
-        // Calculate old note and old note nullifier hash
-        let old_note_balances = account.balance.0.map(Fr::from);
-        let old_note_balance_root =
-            PoseidonHash::crh(&hash, &old_note_balances).expect("Failed to hash balance root");
-        let old_note_identifier =
-            PoseidonHash::tto_crh(&hash, account.address, account.latest_blinding)
-                .expect("Failed to hash identifier");
-        let old_note = PoseidonHash::crh(
-            &hash,
-            &[
-                old_note_balance_root,
-                old_note_identifier,
-                account.nullifier,
-            ],
-        )
-        .expect("Failed to hash old note");
+    /// Proves the spend half of a splitted transition, the membership of the account's latest
+    /// note, with the proof randomized by `rng`.
+    ///
+    /// The returned `nullifier_hash` is shared with [`Protocol::split_settle_with_rng`] of the
+    /// same account, binding both proofs to the same old note.
+    pub fn split_spend_with_rng<R: RngCore + CryptoRng>(
+        pk_spend: &[u8],
+        account: &Account,
+        tree_notes: &[String],
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let hash = poseidon_bn254();
+        let tree = tree_from_notes(tree_notes, &hash)?;
 
-        // Calculate old note path and old note nullifier hash
-        let (merkle_path, old_note_nullifier_hash, root) = match account.index {
-            Some(i) => (
-                tree.generate_membership_proof(i as u64),
-                PoseidonHash::tto_crh(&hash, old_note, account.nullifier)
-                    .expect("Failed to hash nullifier"),
-                tree.root(),
-            ),
-            None => (Path::empty(), Fr::zero(), Fr::zero()),
+        // Calculate old note
+        let old_note_balance_root = PoseidonHash::crh(&hash, &account.balance.0.map(Fr::from))?;
+        let old_note = account.note(&hash)?;
+
+        // Calculate old note path, old note nullifier hash and root
+        let (merkle_path, root) = match account.index {
+            Some(i) => (tree.generate_membership_proof(i as u64), tree.root()),
+            None => (Path::empty(), Fr::zero()),
         };
+        let old_note_nullifier_hash = Self::split_nullifier_hash(&hash, account)?;
 
-        if account.index.is_some() {
+        if let Some(i) = account.index {
             merkle_path
-                .check_membership(&root, &old_note, &hash)
-                .expect("Failed to calculate membership")
+                .check_membership(&root, &old_note, &hash)?
                 .then_some(())
-                .expect("Failed to check membership");
+                .ok_or(WasmError::NoteNotFound(i))?;
         }
 
-        // Calculate new note and new note nullifier hash
-        let new_note_blinding = new_account.latest_blinding;
-        let new_note_balances: [Fr; N_ASSETS] = new_account.balance.0.map(Fr::from);
-        let new_note_balance_root =
-            PoseidonHash::crh(&hash, &new_note_balances).expect("Failed to hash balance root");
-        let new_note = PoseidonHash::crh(
-            &hash,
-            &[
-                new_note_balance_root,
-                PoseidonHash::tto_crh(&hash, account.address, new_note_blinding)
-                    .expect("Failed to hash identifier"),
-                account.nullifier,
-            ],
-        )
-        .expect("Failed to hash new note");
+        // Generate proof
+        let proof = Groth16::<Bn254>::prove(
+            &ProvingKey::deserialize_uncompressed_unchecked(pk_spend)?,
+            SplittedSpendCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }> {
+                nullifier: account.nullifier,
+                utxo_root: root,
+                old_note_nullifier_hash,
+                old_note_identifier: account.identifier(&hash)?,
+                old_note_balance_root,
+                old_note_path: merkle_path,
+                parameters: hash,
+                _hg: std::marker::PhantomData,
+            },
+            rng,
+        )?;
+
+        Ok(json!({
+            "proof": serialize_to_hex(&proof)?,
+            "root": serialize_to_hex(&root)?,
+            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash)?,
+        }))
+    }
+
+    /// Proves the settle half of a splitted transition, applying `diffs` to the account's latest
+    /// note into a note committing to the account's next blinding, with the proof randomized by
+    /// `rng`.
+    ///
+    /// The new account has no index until its note is inserted by the contract.
+    pub fn split_settle_with_rng<R: RngCore + CryptoRng>(
+        pk_settle: &[u8],
+        account: &Account,
+        diffs: &[AssetDiff],
+        aux: Fr,
+        rng: &mut R,
+    ) -> Result<Value, WasmError> {
+        let hash = poseidon_bn254();
+
+        // Update account balance and blinding
+        let mut new_account = *account;
+        new_account.update_balance(diffs)?;
+        new_account.advance_blinding(&hash)?;
+        new_account.update_index(None);
+
+        // Calculate diff balances and diff balance root
+        let diff_balances = AssetDiff::balances(diffs)?;
+        let diff_balance_root = PoseidonHash::crh(&hash, &diff_balances)?;
+
+        // Calculate old note identifier and nullifier hash
+        let old_note_identifier = account.identifier(&hash)?;
+        let old_note_nullifier_hash = Self::split_nullifier_hash(&hash, account)?;
+
+        // Calculate new note
+        let new_note_blinding = new_account.latest_blinding;
+        let new_note = new_account.note(&hash)?;
 
         // Generate proof
         let proof = Groth16::<Bn254>::prove(
-            &ProvingKey::deserialize_uncompressed_unchecked(pk)
-                .expect("Failed to deserialize proving key"),
-            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }> {
+            &ProvingKey::deserialize_uncompressed_unchecked(pk_settle)?,
+            SplittedSettleCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }> {
                 address: account.address,
                 nullifier: account.nullifier,
-                aux: Fr::zero(),
-                utxo_root: root,
+                aux,
                 diff_balance_root,
                 diff_balances,
                 old_note_nullifier_hash,
                 old_note_identifier,
-                old_note_path: merkle_path,
-                old_note_balances,
+                old_note_balances: account.balance.0.map(Fr::from),
                 new_note,
                 new_note_blinding,
-                new_note_balances,
+                new_note_balances: new_account.balance.0.map(Fr::from),
                 parameters: hash,
                 _hg: std::marker::PhantomData,
+                _hpv: std::marker::PhantomData,
+                _h: std::marker::PhantomData,
             },
-            &mut OsRng,
-        )
-        .expect("Failed to generate proof");
+            rng,
+        )?;
 
-        // Return proof and new account
-        to_value(&json!({
-            "is_index_empty": account.index.is_none(),
-            "diff_balance_root": serialize_to_hex(&diff_balance_root).expect("Failed to serialize diff balance root"),
-            "proof": serialize_to_hex(&proof).expect("Failed to serialize proof"),
-            "root": serialize_to_hex(&root).expect("Failed to serialize root"),
-            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash).expect("Failed to serialize nullifier hash"),
-            "identifier": serialize_to_hex(&old_note_identifier).expect("Failed to serialize identifier"),
-            "new_note": serialize_to_hex(&new_note).expect("Failed to serialize new note"),
-            "new_account": new_account.to_string(),
+        Ok(json!({
+            "diff_balance_root": serialize_to_hex(&diff_balance_root)?,
+            "proof": serialize_to_hex(&proof)?,
+            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash)?,
+            "identifier": serialize_to_hex(&old_note_identifier)?,
+            "new_note": serialize_to_hex(&new_note)?,
+            "new_account": new_account.try_to_string()?,
         }))
-        .expect("Failed to serialize to js value")
     }
 
-    #[wasm_bindgen]
-    pub fn swap(
-        pk: &[u8],
-        account: &str,
-        tree_notes: JsValue,
-        diffs: JsValue,
-        swap_argument: JsValue,
+    /// Applies a swap of `in_amount` of the asset at `in_asset_index` to the account's balances,
+    /// crediting only the committed `out_min_amount` of the asset at `out_asset_index`.
+    ///
+    /// The swap output above `out_min_amount` is never part of the note, it is refunded to the
+    /// sender by the contract's excess transfer instead.
+    pub fn apply_swap_result(
+        account: &Account,
+        in_asset_index: usize,
+        in_amount: &str,
+        out_asset_index: usize,
+        out_min_amount: &str,
+    ) -> Result<Account, WasmError> {
+        let mut account = *account;
+        account.update_balance(&[
+            AssetDiff {
+                asset_index: in_asset_index,
+                is_add: false,
+                amount: in_amount.to_string(),
+            },
+            AssetDiff {
+                asset_index: out_asset_index,
+                is_add: true,
+                amount: out_min_amount.to_string(),
+            },
+        ])?;
+        Ok(account)
+    }
+
+    /// Builds the `{ msg, funds }` broadcasting a deposit proven by [`Protocol::deposit_withdraw`],
+    /// attaching the added `diffs` in the denoms of the contract's `assets`.
+    pub fn build_deposit_msg(
+        output: &Value,
+        diffs: &[AssetDiff],
+        assets: &[String],
+    ) -> Result<Value, WasmError> {
+        let funds = diffs
+            .iter()
+            .filter(|d| d.is_add)
+            .map(|d| Ok(json!({ "denom": asset_denom(assets, d)?, "amount": d.amount })))
+            .collect::<Result<Vec<_>, WasmError>>()?;
+
+        Ok(json!({
+            "msg": {
+                "deposit": {
+                    "root": output["root"],
+                    "nullifier_hash": output["nullifier_hash"],
+                    "identifier": output["identifier"],
+                    "new_note": output["new_note"],
+                    "proof": output["proof"],
+                    "bound_height": output["bound_height"],
+                },
+            },
+            "funds": funds,
+        }))
+    }
+
+    /// Validates the contract's `Assets` query result, returning the index of each denom to use
+    /// as [`AssetDiff::asset_index`].
+    ///
+    /// The circuits are built for `N_ASSETS` assets, but their denoms are only set at
+    /// instantiation, so the contract is the authority on the order.
+    pub fn asset_denoms(assets: &[String]) -> Result<BTreeMap<String, usize>, WasmError> {
+        (assets.len() == N_ASSETS)
+            .then_some(())
+            .ok_or(WasmError::InvalidAssetCount(assets.len()))?;
+
+        let mut indices = BTreeMap::new();
+        for (i, denom) in assets.iter().enumerate() {
+            (!denom.is_empty() && indices.insert(denom.clone(), i).is_none())
+                .then_some(())
+                .ok_or_else(|| WasmError::InvalidAsset(denom.clone()))?;
+        }
+        Ok(indices)
+    }
+
+    /// Builds the `{ msg, funds }` broadcasting a withdrawal of the subtracted `diffs` proven by
+    /// [`Protocol::deposit_withdraw`] for `account`, the account before the withdrawal.
+    pub fn build_withdraw_msg(
+        account: &Account,
+        output: &Value,
+        diffs: &[AssetDiff],
+        assets: &[String],
+    ) -> Result<Value, WasmError> {
+        let withdrawn = diffs
+            .iter()
+            .filter(|d| !d.is_add)
+            .map(|d| Ok((asset_denom(assets, d)?.clone(), json!(d.amount))))
+            .collect::<Result<serde_json::Map<_, _>, WasmError>>()?;
+
+        Ok(json!({
+            "msg": {
+                "withdraw": {
+                    "assets": withdrawn,
+                    "root": output["root"],
+                    "nullifier_hash": output["nullifier_hash"],
+                    "blinding": serialize_to_hex(&account.latest_blinding)?,
+                    "new_note": output["new_note"],
+                    "proof": output["proof"],
+                },
+            },
+            "funds": [],
+        }))
+    }
+
+    /// Builds the `{ msg, funds }` broadcasting a swap proven by [`Protocol::swap`] with the same
+    /// `swap_argument` and `timeout`.
+    pub fn build_swap_msg(
+        output: &Value,
+        swap_argument: &MsgSwapExactAmountIn,
         timeout: Option<u64>,
-    ) -> JsValue {
+    ) -> Value {
+        json!({
+            "msg": {
+                "swap": {
+                    "swap_argument": swap_argument,
+                    "root": output["root"],
+                    "nullifier_hash": output["nullifier_hash"],
+                    "identifier": output["identifier"],
+                    "new_note": output["new_note"],
+                    "proof": output["proof"],
+                    "timeout": timeout,
+                },
+            },
+            "funds": [],
+        })
+    }
+
+    /// Same as [`Protocol::find_latest_note`], with the spent nullifier hashes reported by
+    /// `nullifier_used`.
+    pub fn find_latest_note_with(
+        seed: Fr,
+        address: &str,
+        notes: &[ChainNote],
+        mut nullifier_used: impl FnMut(&str) -> Result<bool, WasmError>,
+    ) -> Result<Value, WasmError> {
         let hash = poseidon_bn254();
+        let leaves = notes
+            .iter()
+            .map(|note| decode_field::<Fr>(&note.note))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut account = Account::from_seed(address, seed, &hash)?;
+
+        // Every note of the chain shares the seed's nullifier, so it can't be longer than the tree
+        let mut start = 0;
+        for counter in 1..=notes.len() {
+            account.advance_blinding(&hash)?;
 
-        let mut swap_argument: MsgSwapExactAmountIn =
-            from_value(swap_argument).expect("Failed to deserialize swap args");
-        // Normalize swap argument and then calculate aux
-        swap_argument.sender = String::new();
-        let aux = (&to_vec(&swap_argument)
-            .expect("Failed to serialize swap args")
-            .into_iter()
-            .chain(
-                to_vec(&timeout)
-                    .expect("Failed to serialize timeout")
-                    .into_iter(),
+            let mut next = None;
+            for (index, (note, leaf)) in notes.iter().zip(&leaves).enumerate().skip(start) {
+                let mut candidate = account;
+                match candidate.update_balance(&note.diffs) {
+                    Ok(()) => {}
+                    // A transaction the account can't afford isn't part of its chain
+                    Err(WasmError::InsufficientBalance(_)) => continue,
+                    Err(e) => return Err(e),
+                }
+                if candidate.note(&hash)? == *leaf {
+                    next = Some((index, candidate));
+                    break;
+                }
+            }
+            // The chain ends at a note whose spending transaction isn't in the tree yet
+            let Some((index, next)) = next else {
+                break;
+            };
+            account = next;
+            account.index = Some(index as u32);
+
+            let nullifier_hash = serialize_to_hex(&PoseidonHash::tto_crh(
+                &hash,
+                leaves[index],
+                account.nullifier,
+            )?)?;
+            if !nullifier_used(&nullifier_hash)? {
+                return Ok(json!({
+                    "index": index,
+                    "counter": counter,
+                    "balance": account.balance.0.map(|b| b.to_string()),
+                    "account": account.try_to_string()?,
+                }));
+            }
+            start = index + 1;
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Nullifier hash of the account's latest note, or zero if it has not been inserted yet.
+    fn split_nullifier_hash(hash: &PoseidonConfig<Fr>, account: &Account) -> Result<Fr, WasmError> {
+        Ok(match account.index {
+            Some(_) => PoseidonHash::tto_crh(hash, account.note(hash)?, account.nullifier)?,
+            None => Fr::zero(),
+        })
+    }
+
+    /// Calculates the base64 encoded note of the decimal `balances` of each asset with the base64
+    /// encoded `identifier` and `nullifier`, `H_crh([balance_root, identifier, nullifier])`.
+    pub fn compute_note(
+        balances: &[String],
+        identifier: &str,
+        nullifier: &str,
+    ) -> Result<String, WasmError> {
+        let hash = poseidon_bn254();
+        (balances.len() == N_ASSETS)
+            .then_some(())
+            .ok_or(WasmError::InvalidBalanceCount(balances.len()))?;
+        let balances = balances
+            .iter()
+            .map(|b| {
+                u128::from_str(b)
+                    .map(Fr::from)
+                    .map_err(|_| WasmError::InvalidAmount(b.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let identifier = decode_field::<Fr>(identifier)?;
+        let nullifier = decode_field::<Fr>(nullifier)?;
+
+        let note = PoseidonHash::crh(
+            &hash,
+            &[PoseidonHash::crh(&hash, &balances)?, identifier, nullifier],
+        )?;
+        Ok(serialize_to_hex(&note)?)
+    }
+
+    /// Calculates the base64 encoded diff balance root the contract derives from the transferred
+    /// funds, with outflows as negated amounts.
+    pub fn diff_balance_root(diffs: &[AssetDiff]) -> Result<String, WasmError> {
+        let hash = poseidon_bn254();
+        let diff_balance_root = PoseidonHash::crh(&hash, &AssetDiff::balances(diffs)?)?;
+        Ok(serialize_to_hex(&diff_balance_root)?)
+    }
+
+    /// Calculates the base64 encoded diff balance root the contract derives from the `withdrawn`
+    /// assets, whose amounts are all negated regardless of `is_add`.
+    pub fn withdraw_diff_root(withdrawn: &[AssetDiff]) -> Result<String, WasmError> {
+        let diffs = withdrawn
+            .iter()
+            .map(|diff| AssetDiff {
+                is_add: false,
+                ..diff.clone()
+            })
+            .collect::<Vec<_>>();
+        Self::diff_balance_root(&diffs)
+    }
+
+    /// Checks that the account's current note is in the tree built from `tree_notes` at the
+    /// account's index, without generating any proof.
+    pub fn validate_membership(
+        account: &Account,
+        tree_notes: &[String],
+    ) -> Result<bool, WasmError> {
+        let hash = poseidon_bn254();
+        let index = match account.index {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        let tree = tree_from_notes(tree_notes, &hash)?;
+
+        // The path alone only proves the note is one of the leaves of its lowest level pair, so
+        // the index is checked as well
+        Ok(tree
+            .generate_membership_proof(index as u64)
+            .get_index(&tree.root(), &account.note(&hash)?, &hash)
+            .map(|i| i == Fr::from(index))
+            .unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, ops::Neg};
+
+    use ark_bn254::{Bn254, Fr};
+    use ark_crypto_primitives::snark::SNARK;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_groth16::{Groth16, Proof, ProvingKey};
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use ark_std::Zero;
+    use circuits::{
+        encoding::{decode_field, MAX_ROUTES},
+        poseidon::PoseidonHash,
+        utils::poseidon_bn254,
+        MainCircuitBn254, SplittedSettleCircuitBn254, SplittedSpendCircuitBn254, N_ASSETS,
+        TREE_DEPTH,
+    };
+    use contracts::{
+        error::ContractError,
+        msg::{ExecuteMsg, InstantiateMsg, QueryMsg},
+    };
+    use cosmwasm_std::{
+        from_binary,
+        testing::{mock_dependencies, mock_env, mock_info},
+        Coin, Uint128,
+    };
+    use osmosis_std::types::{
+        cosmos::base::v1beta1::Coin as OsmosisCoin,
+        osmosis::{gamm::v1beta1::MsgSwapExactAmountIn, poolmanager::v1beta1::SwapAmountInRoute},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde_json::{json, Value};
+
+    use crate::{account::Account, error::WasmError, utils::serialize_to_hex};
+
+    use super::{swap_aux, AssetDiff, ChainNote, Protocol};
+
+    #[test]
+    fn diff_balance_root() {
+        let hash = poseidon_bn254();
+        let diffs = [
+            AssetDiff {
+                asset_index: 0,
+                is_add: false,
+                amount: "100000".to_string(),
+            },
+            AssetDiff {
+                asset_index: 6,
+                is_add: true,
+                amount: "50000".to_string(),
+            },
+        ];
+
+        // Swapping 100000 of the first asset for 50000 of the last, as computed by the contract
+        let mut contract_diffs = [Fr::zero(); N_ASSETS];
+        contract_diffs[0] = Fr::from(100_000u128).neg();
+        contract_diffs[6] = Fr::from(50_000u128);
+        let contract_root = PoseidonHash::crh(&hash, &contract_diffs).expect("Failed to hash");
+
+        assert_eq!(
+            Protocol::diff_balance_root(&diffs).expect("Failed to compute root"),
+            serialize_to_hex(&contract_root).expect("Failed to serialize root")
+        );
+    }
+
+    #[test]
+    fn withdraw_diff_root() {
+        let hash = poseidon_bn254();
+        let assets = ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"];
+        let withdrawn = [(0, 100_000), (6, 50_000)].map(|(asset_index, amount)| AssetDiff {
+            asset_index,
+            is_add: true,
+            amount: amount.to_string(),
+        });
+
+        // Withdrawing 100000 of the first asset and 50000 of the last, as computed by the contract
+        let withdrawn_assets = BTreeMap::from([
+            ("uosmo", Uint128::new(100_000)),
+            ("uatom", Uint128::new(50_000)),
+        ]);
+        let contract_diffs = assets
+            .iter()
+            .map(|a| {
+                withdrawn_assets
+                    .get(a)
+                    .map(|f| Fr::from(f.u128()).neg())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let contract_root = PoseidonHash::crh(&hash, &contract_diffs).expect("Failed to hash");
+
+        assert_eq!(
+            Protocol::withdraw_diff_root(&withdrawn).expect("Failed to compute root"),
+            serialize_to_hex(&contract_root).expect("Failed to serialize root")
+        );
+    }
+
+    #[test]
+    fn validate_membership() {
+        let hash = poseidon_bn254();
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.balance.0[0] = 100;
+        account.randomize_blinding(&mut StdRng::seed_from_u64(0));
+
+        let notes = [
+            Fr::from(1),
+            account.note(&hash).expect("Failed to hash note"),
+            Fr::from(2),
+        ]
+        .map(|e| serialize_to_hex(&e).expect("Failed to serialize note"));
+
+        let validate = |account: &Account| {
+            Protocol::validate_membership(account, &notes).expect("Failed to validate")
+        };
+        account.update_index(Some(1));
+        assert!(validate(&account));
+
+        account.update_index(Some(0));
+        assert!(!validate(&account));
+
+        account.update_index(Some(3));
+        assert!(!validate(&account));
+
+        assert!(matches!(
+            Protocol::validate_membership(&account, &["not base64".to_string()]),
+            Err(WasmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn reproducible_proof() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+
+        let account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: true,
+            amount: "100000".to_string(),
+        }];
+
+        let [first, second] = [0; 2].map(|_| {
+            Protocol::deposit_withdraw_with_rng(
+                &pk_bytes,
+                &account,
+                &[],
+                &diffs,
+                None,
+                &mut StdRng::seed_from_u64(42),
             )
-            .collect::<Vec<_>>())
-            .to_field_elements()
-            .and_then(|e| PoseidonHash::crh(&hash, &e).ok())
-            .expect("Failed to hash aux");
-
-        // Deserialize diffs
-        let diffs =
-            from_value::<Vec<AssetDiff>>(diffs).expect("Failed to deserialize balance diffs");
-
-        let leaf_list: Vec<String> = from_value(tree_notes).expect("Failed to parse leaf list");
-        let length = leaf_list.len();
-        let tree = SparseMerkleTree::new(
-            &BTreeMap::from_iter(leaf_list.into_iter().enumerate().map(|(i, l)| {
-                (
-                    i as u32,
-                    Fr::from_le_bytes_mod_order(&base64::decode(l).unwrap()),
+            .expect("Failed to prove")
+        });
+
+        assert_eq!(first["proof"], second["proof"]);
+        assert_eq!(first["new_account"], second["new_account"]);
+    }
+
+    #[test]
+    fn compute_note() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+
+        let account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let output = Protocol::deposit_withdraw_with_rng(
+            &pk_bytes,
+            &account,
+            &[],
+            &[AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            }],
+            None,
+            &mut rng,
+        )
+        .expect("Failed to prove");
+        let new_account = Account::from_string(
+            output["new_account"]
+                .as_str()
+                .expect("New account must be a string"),
+        );
+
+        let mut balances = vec!["0".to_string(); N_ASSETS];
+        balances[0] = "100000".to_string();
+        let note = Protocol::compute_note(
+            &balances,
+            &serialize_to_hex(
+                &new_account
+                    .identifier(&hash)
+                    .expect("Failed to hash identifier"),
+            )
+            .expect("Failed to serialize"),
+            &serialize_to_hex(&new_account.nullifier).expect("Failed to serialize"),
+        )
+        .expect("Failed to compute note");
+        assert_eq!(output["new_note"], note);
+    }
+
+    #[test]
+    fn cached_proving_key() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+
+        let account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: true,
+            amount: "100000".to_string(),
+        }];
+        let prove = || {
+            Protocol::with_proving_key(|pk| {
+                Protocol::deposit_withdraw_with_key(
+                    pk,
+                    &account,
+                    &[],
+                    &diffs,
+                    None,
+                    &mut StdRng::seed_from_u64(42),
                 )
-            })),
-            &hash,
-            &Fr::zero(),
+                .expect("Failed to prove")
+            })
+        };
+
+        assert!(prove().is_err(), "Proving without a key must fail");
+
+        Protocol::set_proving_key(&pk_bytes).expect("Failed to set proving key");
+        let output = prove().expect("Failed to prove with cached key");
+        assert_eq!(
+            output,
+            Protocol::deposit_withdraw_with_rng(
+                &pk_bytes,
+                &account,
+                &[],
+                &diffs,
+                None,
+                &mut StdRng::seed_from_u64(42),
+            )
+            .expect("Failed to prove")
+        );
+
+        let field = |key: &str| {
+            Fr::from_le_bytes_mod_order(
+                &base64::decode(output[key].as_str().expect("Field must be a string"))
+                    .expect("Invalid base64"),
+            )
+        };
+        let proof = Proof::<Bn254>::deserialize_compressed(
+            &base64::decode(output["proof"].as_str().expect("Proof must be a string"))
+                .expect("Invalid proof base64")[..],
+        )
+        .expect("Failed to deserialize proof");
+        assert!(Groth16::<Bn254>::verify(
+            &vk,
+            &[
+                Fr::zero(),
+                field("root"),
+                field("diff_balance_root"),
+                field("nullifier_hash"),
+                field("identifier"),
+                field("new_note"),
+            ],
+            &proof,
         )
-        .expect("Failed to create merkle tree");
+        .expect("Failed to verify proof"));
 
-        // Update account balance and blinding
-        let account = Account::from_string(account);
-        let mut new_account = *&account;
-        new_account.update_balance(&diffs);
-        new_account.randomize_blinding();
-        new_account.update_index(Some(length as u32));
+        Protocol::clear_proving_key();
+        assert!(matches!(prove(), Err(WasmError::ProvingKeyNotSet)));
+    }
 
-        // Calculate diff balances and diff balance root
-        let diff_balances = AssetDiff::balances(&diffs);
-        let diff_balance_root =
-            PoseidonHash::crh(&hash, &diff_balances).expect("Failed to hash balance root");
+    #[test]
+    fn deposit_for() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
 
-        // Calculate old note and old note nullifier hash
-        let old_note_balances = account.balance.0.map(Fr::from);
-        let old_note_balance_root =
-            PoseidonHash::crh(&hash, &old_note_balances).expect("Failed to hash balance root");
-        let old_note_identifier =
-            PoseidonHash::tto_crh(&hash, account.address, account.latest_blinding)
-                .expect("Failed to hash identifier");
-        let old_note = PoseidonHash::crh(
-            &hash,
+        let bob = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+        let gift = Protocol::deposit_for_with_rng(
+            &pk_bytes,
+            bob,
+            &[],
+            &[AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            }],
+            None,
+            &mut rng,
+        )
+        .expect("Failed to prove");
+        let bob_account = Account::from_string(
+            gift["new_account"]
+                .as_str()
+                .expect("New account must be a string"),
+        );
+        assert_eq!(bob_account.address, Account::new(bob).address);
+        assert_eq!(bob_account.balance.0[0], 100_000);
+
+        // Bob spends the gifted note once it is in the tree
+        let tree_notes = [gift["new_note"]
+            .as_str()
+            .expect("New note must be a string")
+            .to_string()];
+        let output = Protocol::deposit_withdraw_with_rng(
+            &pk_bytes,
+            &bob_account,
+            &tree_notes,
+            &[AssetDiff {
+                asset_index: 0,
+                is_add: false,
+                amount: "100000".to_string(),
+            }],
+            None,
+            &mut rng,
+        )
+        .expect("Failed to prove");
+
+        let field = |key: &str| {
+            Fr::from_le_bytes_mod_order(
+                &base64::decode(output[key].as_str().expect("Field must be a string"))
+                    .expect("Invalid base64"),
+            )
+        };
+        let proof = Proof::<Bn254>::deserialize_compressed(
+            &base64::decode(output["proof"].as_str().expect("Proof must be a string"))
+                .expect("Invalid proof base64")[..],
+        )
+        .expect("Failed to deserialize proof");
+        assert!(Groth16::<Bn254>::verify(
+            &vk,
             &[
-                old_note_balance_root,
-                old_note_identifier,
-                account.nullifier,
+                Fr::zero(),
+                field("root"),
+                field("diff_balance_root"),
+                field("nullifier_hash"),
+                field("identifier"),
+                field("new_note"),
             ],
+            &proof,
         )
-        .expect("Failed to hash old note");
+        .expect("Failed to verify proof"));
+        assert_eq!(
+            output["identifier"],
+            Protocol::withdraw_identifier(
+                bob,
+                &bob_account
+                    .blinding()
+                    .expect("Failed to serialize blinding")
+            )
+            .expect("Failed to derive identifier")
+        );
+    }
 
-        // Calculate old note path and old note nullifier hash
-        let i = account.index.expect("Index is none");
-        let (merkle_path, old_note_nullifier_hash, root) = {
-            (
-                tree.generate_membership_proof(i as u64),
-                PoseidonHash::tto_crh(&hash, old_note, account.nullifier)
-                    .expect("Failed to hash nullifier"),
-                tree.root(),
+    #[test]
+    fn batch_deposit() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+
+        let account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let diffs_list = [100_000, 200_000, 300_000].map(|amount| {
+            vec![AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: amount.to_string(),
+            }]
+        });
+        let output =
+            Protocol::batch_deposit_with_rng(&pk_bytes, &account, &[], &diffs_list, None, &mut rng)
+                .expect("Failed to prove");
+
+        let new_account = Account::from_string(
+            output["new_account"]
+                .as_str()
+                .expect("New account must be a string"),
+        );
+        assert_eq!(new_account.balance.0[0], 600_000);
+        assert_eq!(new_account.index, Some(2));
+
+        let deposits = output["deposits"]
+            .as_array()
+            .expect("Deposits must be an array");
+        assert_eq!(deposits.len(), 3);
+        for (i, deposit) in deposits.iter().enumerate() {
+            assert_eq!(deposit["is_index_empty"], i == 0);
+
+            let field = |key: &str| {
+                Fr::from_le_bytes_mod_order(
+                    &base64::decode(deposit[key].as_str().expect("Field must be a string"))
+                        .expect("Invalid base64"),
+                )
+            };
+            let proof = Proof::<Bn254>::deserialize_compressed(
+                &base64::decode(deposit["proof"].as_str().expect("Proof must be a string"))
+                    .expect("Invalid proof base64")[..],
             )
+            .expect("Failed to deserialize proof");
+            assert!(
+                Groth16::<Bn254>::verify(
+                    &vk,
+                    &[
+                        Fr::zero(),
+                        field("root"),
+                        field("diff_balance_root"),
+                        field("nullifier_hash"),
+                        field("identifier"),
+                        field("new_note"),
+                    ],
+                    &proof,
+                )
+                .expect("Failed to verify proof"),
+                "Deposit {i} must verify"
+            );
+        }
+        assert_eq!(
+            deposits[2]["new_note"],
+            serialize_to_hex(&new_account.note(&hash).expect("Failed to hash note"))
+                .expect("Failed to serialize note")
+        );
+    }
+
+    #[test]
+    fn find_latest_note() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let address = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+        let seed = Fr::from(42);
+
+        let diff = |asset_index, is_add, amount: &str| AssetDiff {
+            asset_index,
+            is_add,
+            amount: amount.to_string(),
+        };
+        let deposit = vec![diff(0, true, "100000")];
+        let swap = vec![diff(0, false, "40000"), diff(1, true, "20000")];
+        let withdraw = vec![diff(1, false, "5000")];
+        let swap_argument = MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uatom".to_string(),
+            }],
+            token_in: Some(OsmosisCoin {
+                denom: "uosmo".to_string(),
+                amount: "40000".to_string(),
+            }),
+            token_out_min_amount: "20000".to_string(),
         };
 
-        merkle_path
-            .check_membership(&root, &old_note, &hash)
-            .expect("Failed to calculate membership")
-            .then_some(())
-            .expect("Failed to check membership");
+        // A deposit, a swap and a withdrawal at leaves 1, 3 and 5, each spending the note of the
+        // previous one, with notes of other accounts in between, the first one from a deposit of
+        // the same funds
+        let other = |n: u64, diffs: &[AssetDiff]| ChainNote {
+            note: serialize_to_hex(&Fr::from(n)).expect("Failed to serialize note"),
+            diffs: diffs.to_vec(),
+        };
+        let mut notes = vec![other(1, &deposit)];
+        let mut account =
+            Account::from_seed(address, seed, &hash).expect("Failed to derive from seed");
+        let mut nullifier_hashes = vec![];
+        for (diffs, is_swap) in [(&deposit, false), (&swap, true), (&withdraw, false)] {
+            let tree_notes = notes.iter().map(|n| n.note.clone()).collect::<Vec<_>>();
+            let output = match is_swap {
+                true => Protocol::swap_with_key(
+                    &pk,
+                    &account,
+                    &tree_notes,
+                    diffs,
+                    &swap_argument,
+                    None,
+                    &mut rng,
+                ),
+                false => Protocol::deposit_withdraw_with_key(
+                    &pk,
+                    &account,
+                    &tree_notes,
+                    diffs,
+                    None,
+                    &mut rng,
+                ),
+            }
+            .expect("Failed to prove");
+            if account.index.is_some() {
+                nullifier_hashes.push(
+                    output["nullifier_hash"]
+                        .as_str()
+                        .expect("Nullifier hash must be a string")
+                        .to_string(),
+                );
+            }
 
-        // Calculate new note and new note nullifier hash
-        let new_note_blinding = new_account.latest_blinding;
-        let new_note_balances: [Fr; N_ASSETS] = new_account.balance.0.map(Fr::from);
-        let new_note_balance_root =
-            PoseidonHash::crh(&hash, &new_note_balances).expect("Failed to hash balance root");
-        let new_note = PoseidonHash::crh(
-            &hash,
-            &[
-                new_note_balance_root,
-                PoseidonHash::tto_crh(&hash, account.address, new_note_blinding)
-                    .expect("Failed to hash identifier"),
-                account.nullifier,
-            ],
+            account = Account::from_string(
+                output["new_account"]
+                    .as_str()
+                    .expect("New account must be a string"),
+            );
+            notes.push(ChainNote {
+                note: output["new_note"]
+                    .as_str()
+                    .expect("New note must be a string")
+                    .to_string(),
+                diffs: diffs.clone(),
+            });
+            notes.push(other(notes.len() as u64 + 10, &[]));
+        }
+        let find = |notes: &[ChainNote], spent: &[String]| {
+            Protocol::find_latest_note_with(seed, address, notes, |n| {
+                Ok(spent.iter().any(|s| s == n))
+            })
+            .expect("Failed to follow the chain")
+        };
+
+        let latest = find(&notes, &nullifier_hashes);
+        assert_eq!(latest["index"], 5);
+        assert_eq!(latest["counter"], 3);
+        assert_eq!(latest["balance"][0], "60000");
+        assert_eq!(latest["balance"][1], "15000");
+        assert_eq!(
+            Account::from_string(
+                latest["account"]
+                    .as_str()
+                    .expect("Account must be a string")
+            ),
+            account
+        );
+
+        // Before the withdrawal is indexed, the swapped note is the latest one
+        let swapped = find(&notes[..4], &nullifier_hashes[..1]);
+        assert_eq!(swapped["index"], 3);
+        assert_eq!(swapped["counter"], 2);
+        assert_eq!(swapped["balance"][1], "20000");
+
+        // A spent note whose spending transaction isn't in the tree yet ends the chain
+        assert_eq!(find(&notes[..4], &nullifier_hashes), Value::Null);
+    }
+
+    #[test]
+    fn verify_deposit_locally() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
         )
-        .expect("Failed to hash new note");
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+        let mut vk_bytes = vec![];
+        vk.serialize_uncompressed(&mut vk_bytes)
+            .expect("Failed to serialize verifying key");
 
-        // Generate proof
-        let proof = Groth16::<Bn254>::prove(
-            &ProvingKey::deserialize_uncompressed_unchecked(pk)
-                .expect("Failed to deserialize proving key"),
-            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }> {
-                address: account.address,
-                nullifier: account.nullifier,
-                aux,
-                utxo_root: root,
-                diff_balance_root,
-                diff_balances,
-                old_note_nullifier_hash,
-                old_note_identifier,
-                old_note_path: merkle_path,
-                old_note_balances,
+        let output = Protocol::deposit_withdraw_with_rng(
+            &pk_bytes,
+            &Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu"),
+            &[],
+            &[AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            }],
+            Some(100),
+            &mut rng,
+        )
+        .expect("Failed to prove");
+        let field = |key: &str| output[key].as_str().expect("Field must be a string");
+        let verify = |new_note: &str, proof: &str, bound_height: Option<u64>| {
+            Protocol::verify_deposit_locally(
+                &vk_bytes,
+                field("root"),
+                field("diff_balance_root"),
+                field("nullifier_hash"),
+                field("identifier"),
                 new_note,
-                new_note_blinding,
-                new_note_balances,
-                parameters: hash,
-                _hg: std::marker::PhantomData,
+                proof,
+                bound_height,
+            )
+        };
+
+        assert!(verify(field("new_note"), field("proof"), Some(100)));
+        assert!(!verify(field("new_note"), field("proof"), None));
+        let tampered_note = serialize_to_hex(&Fr::from(1)).expect("Failed to serialize note");
+        assert!(!verify(&tampered_note, field("proof"), Some(100)));
+        assert!(!verify(field("new_note"), "not a proof", Some(100)));
+
+        // The contract rejects the note with a trailing byte even though it reduces to the same
+        // field element
+        let mut over_length = decode_field::<Fr>(field("new_note"))
+            .expect("Invalid new note")
+            .into_bigint()
+            .to_bytes_le();
+        over_length.push(0);
+        assert!(!verify(
+            &base64::encode(over_length),
+            field("proof"),
+            Some(100)
+        ));
+    }
+
+    #[test]
+    fn bound_deposit_on_contract() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+        let mut vk_bytes = vec![];
+        vk.serialize_uncompressed(&mut vk_bytes)
+            .expect("Failed to serialize verifying key");
+
+        let address = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+        let assets =
+            ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"].map(String::from);
+        let env = mock_env();
+        let mut deps = mock_dependencies();
+        contracts::instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("admin", &[]),
+            InstantiateMsg {
+                assets: assets.clone(),
+                main_circuit_vk: base64::encode(vk_bytes),
+                main_circuit_vk_check: None,
+                compress_vk: None,
+                unique_notes: None,
+                split_circuit_vk: None,
+                asset_bitmap_circuit_vk: None,
+                min_deposits: None,
+                store_proofs: None,
+                genesis_notes: None,
+                decimals: None,
+                max_proof_age: Some(10),
+                track_all_roots: None,
             },
-            &mut OsRng,
         )
-        .expect("Failed to generate proof");
-
-        // Return proof and new account
-        to_value(&json!({
-            "diff_balance_root": serialize_to_hex(&diff_balance_root).expect("Failed to serialize diff balance root"),
-            "proof": serialize_to_hex(&proof).expect("Failed to serialize proof"),
-            "root": serialize_to_hex(&root).expect("Failed to serialize root"),
-            "nullifier_hash": serialize_to_hex(&old_note_nullifier_hash).expect("Failed to serialize nullifier hash"),
-            "identifier": serialize_to_hex(&old_note_identifier).expect("Failed to serialize identifier"),
-            "new_note": serialize_to_hex(&new_note).expect("Failed to serialize new note"),
-            "new_account": new_account.to_string(),
-        }))
-        .expect("Failed to serialize to js value")
+        .expect("Failed to instantiate");
+
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: true,
+            amount: "100000".to_string(),
+        }];
+        let mut deposit = |bound_height: Option<u64>| {
+            let output = Protocol::deposit_withdraw_with_rng(
+                &pk_bytes,
+                &Account::new(address),
+                &[],
+                &diffs,
+                bound_height,
+                &mut rng,
+            )
+            .expect("Failed to prove");
+            assert_eq!(output["bound_height"], json!(bound_height));
+            let built =
+                Protocol::build_deposit_msg(&output, &diffs, &assets).expect("Failed to build msg");
+            let funds: Vec<Coin> =
+                serde_json::from_value(built["funds"].clone()).expect("Failed to parse funds");
+            contracts::execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(address, &funds),
+                serde_json::from_value(built["msg"].clone()).expect("Failed to parse msg"),
+            )
+        };
+
+        let err = deposit(None).expect_err("Unbound deposit must be rejected");
+        assert!(
+            matches!(err, ContractError::StaleProof(10)),
+            "Unexpected error {err:?}"
+        );
+        deposit(Some(env.block.height - 5)).expect("Bound deposit must verify");
+
+        // Withdrawals are always verified against the zero aux
+        let err = Protocol::deposit_withdraw_with_rng(
+            &pk_bytes,
+            &Account::new(address),
+            &[],
+            &[AssetDiff {
+                is_add: false,
+                ..diffs[0].clone()
+            }],
+            Some(env.block.height),
+            &mut rng,
+        )
+        .expect_err("Bound withdrawal must be rejected");
+        assert!(
+            matches!(err, WasmError::BoundWithdrawal),
+            "Unexpected error {err:?}"
+        );
+    }
+
+    #[test]
+    fn deposit_preview() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: true,
+            amount: "100000".to_string(),
+        }];
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        let mut tree_notes = vec![];
+
+        // First and subsequent deposits
+        for _ in 0..2 {
+            let preview = Protocol::deposit_preview(&account, &diffs).expect("Failed to preview");
+            let output = Protocol::deposit_withdraw_with_rng(
+                &pk_bytes,
+                &account,
+                &tree_notes,
+                &diffs,
+                None,
+                &mut StdRng::seed_from_u64(7),
+            )
+            .expect("Failed to prove");
+            for key in [
+                "is_index_empty",
+                "diff_balance_root",
+                "nullifier_hash",
+                "identifier",
+                "new_note",
+            ] {
+                assert_eq!(preview[key], output[key], "Mismatched {key}");
+            }
+
+            account = Account::from_string(
+                output["new_account"]
+                    .as_str()
+                    .expect("New account must be a string"),
+            );
+            tree_notes.push(
+                output["new_note"]
+                    .as_str()
+                    .expect("New note must be a string")
+                    .to_string(),
+            );
+        }
+    }
+
+    #[test]
+    fn stale_tree_notes() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.balance.0[0] = 100;
+        account.randomize_blinding(&mut rng);
+        account.update_index(Some(1));
+
+        // The account's note was inserted at index 1, but the client only knows of another note
+        let stale_notes = [Fr::from(1), Fr::from(2)]
+            .map(|e| serialize_to_hex(&e).expect("Failed to serialize note"));
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: false,
+            amount: "40".to_string(),
+        }];
+        let swap_argument = MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uatom".to_string(),
+            }],
+            token_in: Some(OsmosisCoin {
+                denom: "uosmo".to_string(),
+                amount: "40".to_string(),
+            }),
+            token_out_min_amount: "20".to_string(),
+        };
+
+        for err in [
+            Protocol::deposit_withdraw_with_key(
+                &pk,
+                &account,
+                &stale_notes,
+                &diffs,
+                None,
+                &mut rng,
+            )
+            .expect_err("Stale notes must be rejected"),
+            Protocol::swap_with_key(
+                &pk,
+                &account,
+                &stale_notes,
+                &diffs,
+                &swap_argument,
+                None,
+                &mut rng,
+            )
+            .expect_err("Stale notes must be rejected"),
+        ] {
+            assert!(
+                matches!(err, WasmError::NoteNotFound(1)),
+                "Unexpected error {err:?}"
+            );
+            assert_eq!(err.kind(), "note_not_found");
+            assert!(err
+                .to_string()
+                .starts_with("Note not found at index 1 in provided tree"));
+        }
+    }
+
+    #[test]
+    fn swap_too_many_routes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.update_index(Some(0));
+        let swap_argument = MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: (1..=MAX_ROUTES as u64 + 1)
+                .map(|pool_id| SwapAmountInRoute {
+                    pool_id,
+                    token_out_denom: "uatom".to_string(),
+                })
+                .collect(),
+            token_in: Some(OsmosisCoin {
+                denom: "uosmo".to_string(),
+                amount: "40".to_string(),
+            }),
+            token_out_min_amount: "20".to_string(),
+        };
+
+        let err = Protocol::swap_with_key(&pk, &account, &[], &[], &swap_argument, None, &mut rng)
+            .expect_err("Swap must be rejected before proving");
+        assert!(
+            matches!(err, WasmError::TooManyRoutes(MAX_ROUTES)),
+            "Unexpected error {err:?}"
+        );
+        assert_eq!(err.kind(), "too_many_routes");
+    }
+
+    #[test]
+    fn mismatched_proving_key() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let serialize = |pk: ProvingKey<Bn254>| {
+            let mut pk_bytes = vec![];
+            pk.serialize_uncompressed(&mut pk_bytes)
+                .expect("Failed to serialize proving key");
+            pk_bytes
+        };
+        let (small_pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<3, 10>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+        let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(
+            MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+            &mut rng,
+        )
+        .expect("Failed to setup circuit");
+
+        let err = Protocol::set_proving_key(&serialize(small_pk))
+            .expect_err("Proving key of another circuit must be rejected");
+        assert!(matches!(err, WasmError::ProvingKeyMismatch { .. }));
+        assert!(err
+            .to_string()
+            .starts_with("Proving key does not match circuit"));
+
+        Protocol::main_proving_key(&serialize(pk)).expect("Proving key must match circuit");
+    }
+
+    #[test]
+    fn error_kinds() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let [(pk, _), (_, other_vk)] = [0; 2].map(|_| {
+            Groth16::<Bn254>::circuit_specific_setup(
+                MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+                &mut rng,
+            )
+            .expect("Failed to setup circuit")
+        });
+        let mut pk_bytes = vec![];
+        pk.serialize_uncompressed(&mut pk_bytes)
+            .expect("Failed to serialize proving key");
+        let mut vk_bytes = vec![];
+        other_vk
+            .serialize_uncompressed(&mut vk_bytes)
+            .expect("Failed to serialize verifying key");
+
+        let bad_base64 =
+            WasmError::from(decode_field::<Fr>("!!").expect_err("Base64 must be invalid"));
+        let non_canonical = WasmError::from(
+            decode_field::<Fr>(&base64::encode(Fr::MODULUS.to_bytes_le()))
+                .expect_err("Modulus must not be canonical"),
+        );
+        let invalid_proof = Protocol::deposit_withdraw_with_check_with_rng(
+            &pk_bytes,
+            &vk_bytes,
+            &Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu"),
+            &[],
+            &[AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            }],
+            None,
+            &mut rng,
+        )
+        .expect_err("Proof must not verify against another key");
+
+        assert_eq!(bad_base64.kind(), "decode");
+        assert_eq!(non_canonical.kind(), "non_canonical_field");
+        assert_eq!(invalid_proof.kind(), "invalid_proof");
+    }
+
+    #[test]
+    fn swap_aux_matches_contract() {
+        let hash = poseidon_bn254();
+        let swap_argument = MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uatom".to_string(),
+            }],
+            token_in: Some(OsmosisCoin {
+                denom: "uosmo".to_string(),
+                amount: "100000".to_string(),
+            }),
+            token_out_min_amount: "50000".to_string(),
+        };
+
+        for timeout in [None, Some(123)] {
+            assert_eq!(
+                swap_aux(&hash, &swap_argument, timeout).expect("Failed to compute aux"),
+                contracts::swap_aux(&hash, &swap_argument, timeout)
+                    .expect("Failed to compute contract aux"),
+                "Client and contract aux mismatch for timeout {timeout:?}"
+            );
+        }
+        assert_ne!(
+            swap_aux(&hash, &swap_argument, None).expect("Failed to compute aux"),
+            swap_aux(&hash, &swap_argument, Some(0)).expect("Failed to compute aux")
+        );
+    }
+
+    #[test]
+    fn asset_denoms() {
+        let assets =
+            ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"].map(String::from);
+        let mut deps = mock_dependencies();
+        contracts::state::ASSETS
+            .save(deps.as_mut().storage, &assets)
+            .expect("Failed to save assets");
+        let configured: Vec<String> = from_binary(
+            &contracts::query(deps.as_ref(), mock_env(), QueryMsg::Assets {})
+                .expect("Failed to query assets"),
+        )
+        .expect("Failed to parse assets");
+
+        let indices = Protocol::asset_denoms(&configured).expect("Assets must be valid");
+        for (i, denom) in assets.iter().enumerate() {
+            assert_eq!(indices[denom], i);
+        }
+
+        assert!(matches!(
+            Protocol::asset_denoms(&configured[1..]),
+            Err(WasmError::InvalidAssetCount(6))
+        ));
+        let mut duplicated = configured;
+        duplicated[6] = "uosmo".to_string();
+        assert!(matches!(
+            Protocol::asset_denoms(&duplicated),
+            Err(WasmError::InvalidAsset(denom)) if denom == "uosmo"
+        ));
+    }
+
+    #[test]
+    fn split_spend_settle() {
+        let hash = poseidon_bn254();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (pk_spend, vk_spend) = Groth16::<Bn254>::circuit_specific_setup(
+            SplittedSpendCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup spend circuit");
+        let (pk_settle, vk_settle) = Groth16::<Bn254>::circuit_specific_setup(
+            SplittedSettleCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&hash),
+            &mut rng,
+        )
+        .expect("Failed to setup settle circuit");
+        let serialize_pk = |pk: &ProvingKey<Bn254>| {
+            let mut bytes = vec![];
+            pk.serialize_uncompressed(&mut bytes)
+                .expect("Failed to serialize proving key");
+            bytes
+        };
+        let decode = |value: &Value| {
+            base64::decode(value.as_str().expect("Value is not a string")).expect("Invalid base64")
+        };
+
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.balance.0[0] = 100;
+        account.randomize_blinding(&mut rng);
+        account.update_index(Some(1));
+        let notes = [
+            Fr::from(1),
+            account.note(&hash).expect("Failed to hash note"),
+        ]
+        .map(|e| serialize_to_hex(&e).expect("Failed to serialize note"));
+        let diffs = [AssetDiff {
+            asset_index: 0,
+            is_add: false,
+            amount: "40".to_string(),
+        }];
+
+        let spend =
+            Protocol::split_spend_with_rng(&serialize_pk(&pk_spend), &account, &notes, &mut rng)
+                .expect("Failed to prove spend");
+        let settle = Protocol::split_settle_with_rng(
+            &serialize_pk(&pk_settle),
+            &account,
+            &diffs,
+            Fr::zero(),
+            &mut rng,
+        )
+        .expect("Failed to prove settle");
+        assert_eq!(spend["nullifier_hash"], settle["nullifier_hash"]);
+
+        let field = |value: &Value| Fr::from_le_bytes_mod_order(&decode(value));
+        let nullifier_hash = field(&spend["nullifier_hash"]);
+        assert_eq!(
+            nullifier_hash,
+            PoseidonHash::tto_crh(
+                &hash,
+                account.note(&hash).expect("Failed to hash note"),
+                account.nullifier
+            )
+            .expect("Failed to hash nullifier")
+        );
+
+        let spend_proof = Proof::deserialize_compressed(&decode(&spend["proof"])[..])
+            .expect("Failed to deserialize spend proof");
+        assert!(Groth16::<Bn254>::verify(
+            &vk_spend,
+            &[field(&spend["root"]), nullifier_hash],
+            &spend_proof
+        )
+        .expect("Failed to verify spend proof"));
+
+        let settle_proof = Proof::deserialize_compressed(&decode(&settle["proof"])[..])
+            .expect("Failed to deserialize settle proof");
+        assert!(Groth16::<Bn254>::verify(
+            &vk_settle,
+            &[
+                Fr::zero(),
+                field(&settle["diff_balance_root"]),
+                nullifier_hash,
+                field(&settle["identifier"]),
+                field(&settle["new_note"]),
+            ],
+            &settle_proof
+        )
+        .expect("Failed to verify settle proof"));
+    }
+
+    #[test]
+    fn withdraw_identifier() {
+        let hash = poseidon_bn254();
+        let sender = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+        let blinding = Fr::from(12345);
+
+        // Derived as the contract does from the sender's bech32 bytes
+        let contract_identifier = PoseidonHash::tto_crh(
+            &hash,
+            Fr::from_le_bytes_mod_order(sender.as_bytes()),
+            blinding,
+        )
+        .expect("Failed to hash identifier");
+        let identifier = Protocol::withdraw_identifier(
+            sender,
+            &serialize_to_hex(&blinding).expect("Failed to serialize blinding"),
+        )
+        .expect("Failed to derive identifier");
+        assert_eq!(
+            identifier,
+            serialize_to_hex(&contract_identifier).expect("Failed to serialize identifier")
+        );
+
+        let mut account = Account::new(sender);
+        account.latest_blinding = blinding;
+        assert_eq!(
+            identifier,
+            serialize_to_hex(
+                &account
+                    .identifier(&hash)
+                    .expect("Failed to hash identifier")
+            )
+            .expect("Failed to serialize identifier")
+        );
+    }
+
+    #[test]
+    fn apply_swap_result() {
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.balance.0[0] = 500_000;
+
+        // Swapping 100000 of the first asset for at least 50000 of the last one, the swap
+        // actually returning 60000 with the excess refunded
+        let updated = Account::from_string(
+            &Protocol::apply_swap_result(&account, 0, "100000", 6, "50000")
+                .expect("Failed to apply swap")
+                .to_string(),
+        );
+        assert_eq!(updated.balance.0, [400_000, 0, 0, 0, 0, 0, 50_000]);
+        assert_eq!(updated.latest_blinding, account.latest_blinding);
+        assert_eq!(updated.index, account.index);
+
+        assert!(matches!(
+            Protocol::apply_swap_result(&account, 0, "600000", 6, "50000"),
+            Err(WasmError::InsufficientBalance(0))
+        ));
+        assert!(matches!(
+            Protocol::apply_swap_result(&account, 0, "100000", N_ASSETS, "50000"),
+            Err(WasmError::InvalidAssetIndex(N_ASSETS))
+        ));
+        assert!(matches!(
+            Protocol::apply_swap_result(&account, 0, "-1", 6, "50000"),
+            Err(WasmError::InvalidAmount(amount)) if amount == "-1"
+        ));
+    }
+
+    #[test]
+    fn built_msgs_deserialize() {
+        let assets =
+            ["uosmo", "uinj", "uusdt", "uusdc", "uwbtc", "ueth", "uatom"].map(String::from);
+        let field = |value: u64| serialize_to_hex(&Fr::from(value)).expect("Failed to serialize");
+        let output = json!({
+            "root": field(1),
+            "nullifier_hash": field(2),
+            "identifier": field(3),
+            "new_note": field(4),
+            "proof": field(5),
+        });
+        let diffs = [
+            AssetDiff {
+                asset_index: 0,
+                is_add: true,
+                amount: "100000".to_string(),
+            },
+            AssetDiff {
+                asset_index: 6,
+                is_add: false,
+                amount: "50000".to_string(),
+            },
+        ];
+        let parse = |built: Value| {
+            (
+                serde_json::from_value::<ExecuteMsg>(built["msg"].clone())
+                    .expect("Failed to deserialize execute msg"),
+                serde_json::from_value::<Vec<Coin>>(built["funds"].clone())
+                    .expect("Failed to deserialize funds"),
+            )
+        };
+
+        match parse(Protocol::build_deposit_msg(&output, &diffs, &assets).expect("Failed to build"))
+        {
+            (
+                ExecuteMsg::Deposit {
+                    root,
+                    new_note,
+                    proof,
+                    ..
+                },
+                funds,
+            ) => {
+                assert_eq!((root, new_note, proof), (field(1), field(4), field(5)));
+                assert_eq!(funds, vec![Coin::new(100_000, "uosmo")]);
+            }
+            msg => panic!("Unexpected msg {msg:?}"),
+        }
+
+        let mut account = Account::new("osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu");
+        account.randomize_blinding(&mut StdRng::seed_from_u64(0));
+        match parse(
+            Protocol::build_withdraw_msg(&account, &output, &diffs, &assets)
+                .expect("Failed to build"),
+        ) {
+            (
+                ExecuteMsg::Withdraw {
+                    assets, blinding, ..
+                },
+                funds,
+            ) => {
+                assert_eq!(
+                    assets,
+                    BTreeMap::from([("uatom".to_string(), Uint128::new(50_000))])
+                );
+                assert_eq!(
+                    blinding,
+                    account.blinding().expect("Failed to serialize blinding")
+                );
+                assert!(funds.is_empty());
+            }
+            msg => panic!("Unexpected msg {msg:?}"),
+        }
+
+        let swap_argument = MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uatom".to_string(),
+            }],
+            token_in: Some(OsmosisCoin {
+                denom: "uosmo".to_string(),
+                amount: "100000".to_string(),
+            }),
+            token_out_min_amount: "50000".to_string(),
+        };
+        match parse(Protocol::build_swap_msg(&output, &swap_argument, Some(123))) {
+            (
+                ExecuteMsg::Swap {
+                    swap_argument: parsed,
+                    identifier,
+                    timeout,
+                    ..
+                },
+                funds,
+            ) => {
+                assert_eq!(parsed, swap_argument);
+                assert_eq!((identifier, timeout), (field(3), Some(123)));
+                assert!(funds.is_empty());
+            }
+            msg => panic!("Unexpected msg {msg:?}"),
+        }
     }
 }
@@ -5,11 +5,14 @@ use ark_crypto_primitives::sponge::poseidon::PoseidonConfig;
 use ark_ff::{BigInteger, PrimeField};
 use ark_std::Zero;
 use circuits::{
-    merkle_tree::SparseMerkleTree as SMT, poseidon::PoseidonHash, utils::poseidon_bn254, TREE_DEPTH,
+    encoding::decode_field, merkle_tree::SparseMerkleTree as SMT, poseidon::PoseidonHash,
+    utils::poseidon_bn254, TREE_DEPTH,
 };
 use serde_wasm_bindgen::from_value;
 use wasm_bindgen::prelude::*;
 
+use crate::error::WasmError;
+
 #[wasm_bindgen]
 pub struct SparseMerkleTree {
     pub latest_index: usize,
@@ -18,6 +21,12 @@ pub struct SparseMerkleTree {
     hasher: PoseidonConfig<Fr>,
 }
 
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[wasm_bindgen]
 impl SparseMerkleTree {
     #[wasm_bindgen(constructor)]
@@ -36,21 +45,17 @@ impl SparseMerkleTree {
     }
 
     #[wasm_bindgen]
-    pub fn insert_batch(&mut self, leaf_list: JsValue) {
-        let leaf_list: Vec<String> = from_value(leaf_list).expect("Failed to parse leaf list");
+    pub fn insert_batch(&mut self, leaf_list: JsValue) -> Result<(), WasmError> {
+        let leaf_list: Vec<String> = from_value(leaf_list)?;
         let len = leaf_list.len();
 
-        self.tree
-            .insert_batch(
-                &BTreeMap::from_iter(leaf_list.into_iter().enumerate().map(|(i, e)| {
-                    (
-                        (self.latest_index + i) as u32,
-                        Fr::from_le_bytes_mod_order(&base64::decode(e).unwrap()),
-                    )
-                })),
-                &self.hasher,
-            )
-            .expect("Failed to insert batch into tree");
+        let leaves = leaf_list
+            .iter()
+            .enumerate()
+            .map(|(i, e)| Ok(((self.latest_index + i) as u32, decode_field(e)?)))
+            .collect::<Result<BTreeMap<_, _>, WasmError>>()?;
+        self.tree.insert_checked(&leaves, &self.hasher)?;
         self.latest_index += len;
+        Ok(())
     }
 }
@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+use osmosis_std::types::{
+    cosmos::base::v1beta1::Coin,
+    osmosis::{gamm::v1beta1::MsgSwapExactAmountIn, poolmanager::v1beta1::SwapAmountInRoute},
+};
+use serde_wasm_bindgen::to_value;
+use wasm_bindgen::prelude::*;
+
+use crate::error::WasmError;
+
+/// Builder of the swap argument of [`crate::protocol::Protocol::swap`], with the sender cleared
+/// as the contract overwrites it.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct SwapBuilder {
+    token_in: Option<Coin>,
+    routes: Vec<SwapAmountInRoute>,
+    token_out_min_amount: Option<String>,
+}
+
+#[wasm_bindgen]
+impl SwapBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen]
+    pub fn token_in(mut self, denom: &str, amount: &str) -> Self {
+        self.token_in = Some(Coin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        });
+        self
+    }
+
+    /// Appends a hop through `pool_id` into `denom`, hops being swapped in the order added.
+    #[wasm_bindgen]
+    pub fn add_route(mut self, pool_id: u64, denom: &str) -> Self {
+        self.routes.push(SwapAmountInRoute {
+            pool_id,
+            token_out_denom: denom.to_string(),
+        });
+        self
+    }
+
+    #[wasm_bindgen]
+    pub fn min_out(mut self, amount: &str) -> Self {
+        self.token_out_min_amount = Some(amount.to_string());
+        self
+    }
+
+    #[wasm_bindgen(js_name = build)]
+    pub fn wasm_build(&self) -> Result<JsValue, WasmError> {
+        Ok(to_value(&self.build()?)?)
+    }
+}
+
+impl SwapBuilder {
+    /// Builds the swap argument, failing on a missing or non-positive amount or an empty or
+    /// malformed route.
+    pub fn build(&self) -> Result<MsgSwapExactAmountIn, WasmError> {
+        let token_in = self.token_in.clone().ok_or(WasmError::InvalidSwapRoute)?;
+        let token_out_min_amount = self
+            .token_out_min_amount
+            .clone()
+            .ok_or_else(|| WasmError::InvalidAmount(String::new()))?;
+        for amount in [&token_in.amount, &token_out_min_amount] {
+            u128::from_str(amount)
+                .ok()
+                .filter(|amount| *amount > 0)
+                .ok_or_else(|| WasmError::InvalidAmount(amount.clone()))?;
+        }
+        (!token_in.denom.is_empty()
+            && !self.routes.is_empty()
+            && self
+                .routes
+                .iter()
+                .all(|r| r.pool_id != 0 && !r.token_out_denom.is_empty()))
+        .then_some(())
+        .ok_or(WasmError::InvalidSwapRoute)?;
+
+        Ok(MsgSwapExactAmountIn {
+            sender: String::new(),
+            routes: self.routes.clone(),
+            token_in: Some(token_in),
+            token_out_min_amount,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use circuits::utils::poseidon_bn254;
+
+    use crate::{error::WasmError, protocol::swap_aux};
+
+    use super::SwapBuilder;
+
+    #[test]
+    fn two_hop_swap() {
+        let hash = poseidon_bn254();
+        let swap_argument = SwapBuilder::new()
+            .token_in("uosmo", "100000")
+            .add_route(1, "uatom")
+            .add_route(7, "uusdc")
+            .min_out("50000")
+            .build()
+            .expect("Failed to build swap");
+
+        assert!(swap_argument.sender.is_empty());
+        assert_eq!(
+            swap_argument
+                .routes
+                .iter()
+                .map(|r| (r.pool_id, r.token_out_denom.as_str()))
+                .collect::<Vec<_>>(),
+            [(1, "uatom"), (7, "uusdc")]
+        );
+        assert_eq!(
+            swap_aux(&hash, &swap_argument, Some(123)).expect("Failed to compute aux"),
+            contracts::swap_aux(&hash, &swap_argument, Some(123))
+                .expect("Failed to compute contract aux")
+        );
+
+        let swap = SwapBuilder::new()
+            .token_in("uosmo", "100000")
+            .min_out("50000");
+        assert!(matches!(
+            swap.clone().build(),
+            Err(WasmError::InvalidSwapRoute)
+        ));
+        assert!(matches!(
+            swap.clone().add_route(0, "uatom").build(),
+            Err(WasmError::InvalidSwapRoute)
+        ));
+        assert!(matches!(
+            swap.add_route(1, "uatom").min_out("0").build(),
+            Err(WasmError::InvalidAmount(amount)) if amount == "0"
+        ));
+    }
+}
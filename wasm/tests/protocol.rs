@@ -0,0 +1,201 @@
+//! Tests of the js facing [`Protocol`] functions, run with `wasm-pack test --node`.
+#![cfg(target_arch = "wasm32")]
+
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::snark::SNARK;
+use ark_ff::PrimeField;
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use circuits::{utils::poseidon_bn254, MainCircuitBn254, N_ASSETS, TREE_DEPTH};
+use osmosis_std::types::{
+    cosmos::base::v1beta1::Coin as OsmosisCoin,
+    osmosis::{gamm::v1beta1::MsgSwapExactAmountIn, poolmanager::v1beta1::SwapAmountInRoute},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::Value;
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use wasm::{
+    account::Account,
+    protocol::{swap_aux, AssetDiff, Protocol},
+};
+
+const ADDRESS: &str = "osmo1zlymlax05tg9km9jyw496jx60v86m4548xw2xu";
+
+fn setup() -> (Vec<u8>, Vec<u8>, VerifyingKey<Bn254>) {
+    let (pk, vk): (ProvingKey<Bn254>, _) = Groth16::<Bn254>::circuit_specific_setup(
+        MainCircuitBn254::<{ N_ASSETS }, { TREE_DEPTH }>::empty_without_tree(&poseidon_bn254()),
+        &mut StdRng::seed_from_u64(0),
+    )
+    .expect("Failed to setup circuit");
+    let mut pk_bytes = vec![];
+    pk.serialize_uncompressed(&mut pk_bytes)
+        .expect("Failed to serialize proving key");
+    let mut vk_bytes = vec![];
+    vk.serialize_uncompressed(&mut vk_bytes)
+        .expect("Failed to serialize verifying key");
+    (pk_bytes, vk_bytes, vk)
+}
+
+/// Decodes the base64 encoded field `key` of `output`.
+fn field(output: &Value, key: &str) -> Fr {
+    Fr::from_le_bytes_mod_order(
+        &base64::decode(output[key].as_str().expect("Field must be a string"))
+            .expect("Invalid base64"),
+    )
+}
+
+fn proof(output: &Value) -> Proof<Bn254> {
+    Proof::deserialize_compressed(
+        &base64::decode(output["proof"].as_str().expect("Proof must be a string"))
+            .expect("Invalid proof base64")[..],
+    )
+    .expect("Failed to deserialize proof")
+}
+
+/// Deposits 100 of the first asset into a new note, returning the output and the tree notes.
+fn deposit(pk: &[u8]) -> (Value, Vec<String>) {
+    let diffs = vec![AssetDiff {
+        asset_index: 0,
+        is_add: true,
+        amount: "100".to_string(),
+    }];
+    let output: Value = from_value(
+        Protocol::deposit_withdraw(
+            pk,
+            &Account::new(ADDRESS).to_string(),
+            to_value(&Vec::<String>::new()).expect("Failed to serialize tree notes"),
+            to_value(&diffs).expect("Failed to serialize diffs"),
+            None,
+        )
+        .expect("Failed to prove deposit"),
+    )
+    .expect("Failed to deserialize output");
+    let new_note = output["new_note"]
+        .as_str()
+        .expect("New note must be a string")
+        .to_string();
+    (output, vec![new_note])
+}
+
+#[wasm_bindgen_test]
+fn deposit_proof() {
+    let (pk, vk_bytes, vk) = setup();
+    let (output, _) = deposit(&pk);
+
+    for key in [
+        "root",
+        "diff_balance_root",
+        "nullifier_hash",
+        "identifier",
+        "new_note",
+    ] {
+        base64::decode(output[key].as_str().expect("Field must be a string"))
+            .expect("Invalid base64");
+    }
+    assert_eq!(
+        output["diff_balance_root"],
+        Protocol::diff_balance_root(&[AssetDiff {
+            asset_index: 0,
+            is_add: true,
+            amount: "100".to_string(),
+        }])
+        .expect("Failed to compute diff balance root")
+    );
+    let new_account = Account::try_from_string(
+        output["new_account"]
+            .as_str()
+            .expect("New account must be a string"),
+    )
+    .expect("Failed to deserialize new account");
+    assert_eq!(new_account.balance.0[0], 100);
+
+    assert!(Groth16::<Bn254>::verify(
+        &vk,
+        &[
+            Fr::from(0),
+            field(&output, "root"),
+            field(&output, "diff_balance_root"),
+            field(&output, "nullifier_hash"),
+            field(&output, "identifier"),
+            field(&output, "new_note"),
+        ],
+        &proof(&output),
+    )
+    .expect("Failed to verify"));
+
+    let str_field = |key: &str| output[key].as_str().expect("Field must be a string");
+    assert!(Protocol::verify_deposit_locally(
+        &vk_bytes,
+        str_field("root"),
+        str_field("diff_balance_root"),
+        str_field("nullifier_hash"),
+        str_field("identifier"),
+        str_field("new_note"),
+        str_field("proof"),
+        None,
+    ));
+}
+
+#[wasm_bindgen_test]
+fn swap_proof() {
+    let (pk, _, vk) = setup();
+    let (deposit, tree_notes) = deposit(&pk);
+    let account = Account::update_account_index(
+        deposit["new_account"]
+            .as_str()
+            .expect("New account must be a string"),
+        0,
+    )
+    .expect("Failed to update index");
+
+    let swap_argument = MsgSwapExactAmountIn {
+        sender: String::new(),
+        routes: vec![SwapAmountInRoute {
+            pool_id: 1,
+            token_out_denom: "uatom".to_string(),
+        }],
+        token_in: Some(OsmosisCoin {
+            denom: "uosmo".to_string(),
+            amount: "40".to_string(),
+        }),
+        token_out_min_amount: "20".to_string(),
+    };
+    let diffs = vec![AssetDiff {
+        asset_index: 0,
+        is_add: false,
+        amount: "40".to_string(),
+    }];
+    let output: Value = from_value(
+        Protocol::swap(
+            &pk,
+            &account,
+            to_value(&tree_notes).expect("Failed to serialize tree notes"),
+            to_value(&diffs).expect("Failed to serialize diffs"),
+            to_value(&swap_argument).expect("Failed to serialize swap argument"),
+            Some(10),
+        )
+        .expect("Failed to prove swap"),
+    )
+    .expect("Failed to deserialize output");
+
+    // The proof is bound to the swap as the contract computes its `aux`
+    let hash = poseidon_bn254();
+    let inputs = |aux| {
+        [
+            aux,
+            field(&output, "root"),
+            field(&output, "diff_balance_root"),
+            field(&output, "nullifier_hash"),
+            field(&output, "identifier"),
+            field(&output, "new_note"),
+        ]
+    };
+    let aux = |timeout| swap_aux(&hash, &swap_argument, timeout).expect("Failed to compute aux");
+    let proof = proof(&output);
+    assert!(
+        Groth16::<Bn254>::verify(&vk, &inputs(aux(Some(10))), &proof).expect("Failed to verify")
+    );
+    assert!(!Groth16::<Bn254>::verify(&vk, &inputs(aux(None)), &proof).expect("Failed to verify"));
+}